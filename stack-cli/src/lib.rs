@@ -1,12 +1,20 @@
 use core::fmt;
 use std::io::{self, prelude::Write};
 
+use codespan_reporting::{
+  diagnostic::{Diagnostic, Label},
+  files::SimpleFiles,
+  term::{
+    self,
+    termcolor::{ColorChoice, StandardStream},
+  },
+};
 use crossterm::{
   cursor::{self, MoveTo},
   style::Print,
   terminal, QueueableCommand,
 };
-use stack_core::prelude::*;
+use stack_core::{lexer::Span, prelude::*};
 
 pub mod server;
 
@@ -43,6 +51,34 @@ pub fn eprint_stack(context: &Context) {
   eprintln!()
 }
 
+/// Parses a [`Source`], printing every error if it fails to parse.
+pub fn parse_or_eprint(source: Source) -> Option<Vec<Expr>> {
+  let mut lexer = Lexer::new(source);
+  let (exprs, errors) = parse_recovering(&mut lexer);
+
+  for err in errors.iter() {
+    eprint_diagnostic(&err.source, err.kind.span(), &err.kind.to_string());
+  }
+
+  errors.is_empty().then_some(exprs)
+}
+
+/// Prints an error at the `span` of a [`Source`], along with the lines
+/// around it.
+pub fn eprint_diagnostic(source: &Source, span: Span, message: &str) {
+  let mut files = SimpleFiles::new();
+  let file_id = files.add(source.name(), source.source());
+
+  let diagnostic = Diagnostic::error()
+    .with_message(message)
+    .with_labels(vec![Label::primary(file_id, span.to_range())]);
+
+  let writer = StandardStream::stderr(ColorChoice::Always);
+  let config = term::Config::default();
+
+  let _ = term::emit(&mut writer.lock(), &config, &files, &diagnostic);
+}
+
 pub fn clear_screen() -> io::Result<()> {
   let mut stdout = std::io::stdout();
 
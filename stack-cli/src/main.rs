@@ -18,41 +18,33 @@ use notify::{
 };
 use reedline::{DefaultPrompt, DefaultPromptSegment, Reedline, Signal};
 use stack_cli::{
-  clear_screen, eprint_stack, ok_or_exit, print_stack, server::listen,
+  clear_screen, eprint_diagnostic, eprint_stack, ok_or_exit, parse_or_eprint,
+  print_stack,
+  server::{jsonrpc, listen, Server, ServerConfig},
 };
-use stack_core::prelude::*;
+use stack_core::{checker, formatter, prelude::*};
 
 fn main() {
   let cli = Cli::parse();
 
-  let new_context = || {
-    if cli.journal {
-      Context::new().with_journal(Some(cli.journal_length.unwrap_or(20)))
-    } else {
-      Context::new()
-    }
-  };
-
-  let mut engine =
-    Engine::new().with_debug_hook(Some(Arc::new(|s| eprintln!("{s}"))));
+  let new_context = || cli.new_context();
+  let mut engine = cli.new_engine();
   let mut context = new_context();
 
-  #[cfg(feature = "stack-std")]
-  {
-    if cli.enable_all || cli.enable_str {
-      engine.add_module(stack_std::str::module());
-    }
-
-    if cli.enable_all || cli.enable_fs {
-      engine.add_module(stack_std::fs::module(cli.sandbox));
-    }
+  // Each thread of the server creates its own sessions, since they can't be
+  // sent between threads.
+  let server_config = {
+    let cli = cli.clone();
+    move || cli.server_config()
+  };
 
-    if cli.enable_all || cli.enable_scope {
-      engine.add_module(stack_std::scope::module());
+  for (enabled, module) in cli.modules() {
+    if enabled {
+      engine.add_module(module);
     }
   }
 
-  match cli.subcommand {
+  match cli.subcommand.clone() {
     Subcommand::Stdin => {
       let mut stdin = std::io::stdin();
       let mut source = String::new();
@@ -60,8 +52,9 @@ fn main() {
       ok_or_exit(stdin.read_to_string(&mut source));
 
       let source = Source::new("stdin", source);
-      let mut lexer = Lexer::new(source);
-      let exprs = ok_or_exit(parse(&mut lexer));
+      let Some(exprs) = parse_or_eprint(source) else {
+        std::process::exit(1);
+      };
 
       context = ok_or_exit(engine.run(context, exprs));
       print_stack(&context);
@@ -96,8 +89,9 @@ fn main() {
               }
             } else {
               let source = Source::new("repl", line);
-              let mut lexer = Lexer::new(source);
-              let exprs = ok_or_exit(parse(&mut lexer));
+              let Some(exprs) = parse_or_eprint(source) else {
+                continue;
+              };
 
               context = match engine.run(context, exprs) {
                 Ok(context) => {
@@ -107,7 +101,7 @@ fn main() {
                 Err(e) => {
                   eprintln!("error: {e}");
                   eprint_stack(&e.context);
-                  e.context
+                  *e.context
                 }
               }
             }
@@ -118,8 +112,9 @@ fn main() {
     Subcommand::Run { input, watch } => {
       if !watch {
         let source = ok_or_exit(Source::from_path(input));
-        let mut lexer = Lexer::new(source);
-        let exprs = ok_or_exit(parse(&mut lexer));
+        let Some(exprs) = parse_or_eprint(source) else {
+          std::process::exit(1);
+        };
 
         context = ok_or_exit(engine.run(context, exprs));
         print_stack(&context);
@@ -143,14 +138,8 @@ fn main() {
 
           context.add_source(source.clone());
 
-          let mut lexer = Lexer::new(source);
-
-          let exprs = match parse(&mut lexer) {
-            Ok(exprs) => exprs,
-            Err(e) => {
-              eprintln!("error: {e}");
-              return context;
-            }
+          let Some(exprs) = parse_or_eprint(source) else {
+            return context;
           };
 
           match engine.run(context, exprs) {
@@ -195,7 +184,7 @@ fn main() {
                 eprintln!("{}", journal);
               }
 
-              e.context
+              *e.context
             }
           }
         };
@@ -220,7 +209,65 @@ fn main() {
         }
       }
     }
-    Subcommand::Serve => listen(),
+    Subcommand::Serve {
+      host,
+      port,
+      stdio,
+      socket,
+    } => {
+      if stdio {
+        ok_or_exit(jsonrpc::serve_stdio(Server::new(server_config)));
+      } else if let Some(socket) = socket {
+        ok_or_exit(jsonrpc::serve_unix(Server::new(server_config), &socket));
+      } else {
+        ok_or_exit(listen(&host, port, Server::new(server_config)));
+      }
+    }
+    Subcommand::Check { input } => {
+      let source = ok_or_exit(Source::from_path(input));
+      if parse_or_eprint(source.clone()).is_none() {
+        std::process::exit(1);
+      }
+
+      let diagnostics = ok_or_exit(checker::check(source.clone()));
+      for diagnostic in diagnostics.iter() {
+        eprint_diagnostic(&source, diagnostic.span, &diagnostic.to_string());
+      }
+
+      if !diagnostics.is_empty() {
+        std::process::exit(1);
+      }
+    }
+    Subcommand::Fmt { files, check } => {
+      let mut failed = false;
+
+      for path in files {
+        let source = ok_or_exit(Source::from_path(&path));
+        let formatted = match formatter::format(source.clone()) {
+          Ok(formatted) => formatted,
+          Err(_) => {
+            parse_or_eprint(source);
+            failed = true;
+            continue;
+          }
+        };
+
+        if formatted == source.source() {
+          continue;
+        }
+
+        if check {
+          println!("{} is not formatted", path.display());
+          failed = true;
+        } else {
+          ok_or_exit(std::fs::write(&path, formatted));
+        }
+      }
+
+      if failed {
+        std::process::exit(1);
+      }
+    }
   }
 }
 
@@ -239,6 +286,36 @@ struct Cli {
   #[arg(long, alias = "jl")]
   journal_length: Option<usize>,
 
+  /// Limit the amount of calls a script can make.
+  #[arg(long)]
+  fuel: Option<u64>,
+
+  /// Limit the amount of items on the stack.
+  #[arg(long, value_name = "ITEMS")]
+  max_stack_len: Option<usize>,
+  /// Limit the amount of items in a list or record, or bytes in a string.
+  #[arg(long, value_name = "ITEMS")]
+  max_collection_len: Option<usize>,
+  /// Limit the depth of nested calls.
+  #[arg(long, value_name = "DEPTH")]
+  max_call_depth: Option<usize>,
+  /// Limit the approximate amount of bytes used by the items on the stack and
+  /// in scopes.
+  #[arg(long, value_name = "BYTES")]
+  max_heap_size: Option<usize>,
+
+  /// Whether to compile scripts and run them with the bytecode VM.
+  #[arg(long)]
+  vm: bool,
+
+  /// What happens when integer arithmetic overflows.
+  #[arg(long, value_enum, default_value_t)]
+  arithmetic: Arithmetic,
+
+  /// A directory to search for imported files in, which can be repeated.
+  #[arg(long = "search-path", value_name = "DIR")]
+  search_paths: Vec<PathBuf>,
+
   /// Whether to run a sandbox variant of the enabled standard modules.
   #[arg(short, long)]
   #[cfg(feature = "stack-std")]
@@ -262,6 +339,99 @@ struct Cli {
   enable_scope: bool,
 }
 
+impl Cli {
+  fn new_context(&self) -> Context {
+    let context = if self.journal {
+      Context::new().with_journal(Some(self.journal_length.unwrap_or(20)))
+    } else {
+      Context::new()
+    };
+    let context = context.with_limits(Limits {
+      stack_len: self.max_stack_len,
+      collection_len: self.max_collection_len,
+      call_depth: self.max_call_depth,
+      heap_size: self.max_heap_size,
+    });
+
+    match self.fuel {
+      Some(fuel) => context.with_fuel(fuel),
+      None => context,
+    }
+  }
+
+  /// Creates an engine without any modules.
+  fn new_engine(&self) -> Engine {
+    let mut engine = Engine::new()
+      .with_debug_hook(Some(Arc::new(|s| eprintln!("{s}"))))
+      .with_backend(if self.vm {
+        Backend::Vm
+      } else {
+        Backend::TreeWalker
+      })
+      .with_arithmetic_mode(match self.arithmetic {
+        Arithmetic::Wrapping => ArithmeticMode::Wrapping,
+        Arithmetic::Checked => ArithmeticMode::Checked,
+        Arithmetic::Promote => ArithmeticMode::Promote,
+      });
+
+    for path in self.search_paths.iter() {
+      engine.add_search_path(path);
+    }
+
+    engine
+  }
+
+  /// Returns the standard modules, and whether each is enabled.
+  #[cfg(feature = "stack-std")]
+  fn modules(&self) -> Vec<(bool, Module)> {
+    vec![
+      (self.enable_all || self.enable_str, stack_std::str::module()),
+      (
+        self.enable_all || self.enable_fs,
+        stack_std::fs::module(self.sandbox),
+      ),
+      (
+        self.enable_all || self.enable_scope,
+        stack_std::scope::module(),
+      ),
+    ]
+  }
+
+  /// Returns the standard modules, and whether each is enabled.
+  #[cfg(not(feature = "stack-std"))]
+  fn modules(&self) -> Vec<(bool, Module)> {
+    Vec::new()
+  }
+
+  /// Returns the settings of the server, whose sessions enable their own
+  /// modules.
+  fn server_config(&self) -> ServerConfig {
+    let modules = self.modules();
+
+    ServerConfig {
+      engine: self.new_engine(),
+      context: self.new_context(),
+      modules: modules.iter().map(|(_, module)| module.clone()).collect(),
+      default_modules: modules
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, module)| module.name())
+        .collect(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+enum Arithmetic {
+  /// Wrap around at the bounds of a 64-bit integer.
+  Wrapping,
+  /// Fail with an arithmetic-overflow error.
+  Checked,
+  /// Promote the result to a big integer.
+  #[default]
+  Promote,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Default, clap::Subcommand)]
 enum Subcommand {
   /// Runs a REPL [alias >].
@@ -281,6 +451,39 @@ enum Subcommand {
     watch: bool,
   },
 
-  // TODO: add host and port as options
-  Serve,
+  /// Runs a server which runs the code sent to it, over websockets by
+  /// default.
+  Serve {
+    /// The host to listen on.
+    #[arg(long, default_value = "localhost")]
+    host: String,
+
+    /// The port to listen on.
+    #[arg(long, default_value_t = 5001)]
+    port: u16,
+
+    /// Whether to serve JSON-RPC over stdin and stdout instead.
+    #[arg(long, conflicts_with = "socket")]
+    stdio: bool,
+
+    /// Serve JSON-RPC on a Unix domain socket at this path instead.
+    #[arg(long, value_name = "PATH")]
+    socket: Option<PathBuf>,
+  },
+  /// Checks the stack effects of the code from an input file path.
+  Check {
+    /// The input file path.
+    input: PathBuf,
+  },
+  /// Formats source files in place.
+  Fmt {
+    /// The paths of the files to format.
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+
+    /// Whether to only check that the files are formatted, without changing
+    /// them.
+    #[arg(long)]
+    check: bool,
+  },
 }
@@ -1,10 +1,18 @@
-use stack_core::prelude::*;
-use std::{collections::HashMap, mem, rc::Rc, sync::Mutex};
+use stack_core::{journal::JournalEntry, prelude::*};
+use std::{
+  collections::HashMap,
+  mem,
+  panic::{self, AssertUnwindSafe},
+  sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError},
+  thread,
+};
 
 use serde::{Deserialize, Serialize};
-use ws::{Message, Sender};
+use ws::{Handler, Message, Sender};
 
-use crate::{eprint_stack, print_stack};
+use crate::eprint_stack;
+
+pub mod jsonrpc;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -16,17 +24,61 @@ pub enum Incoming {
   // Querying
   Stack(BasePayload),
   Context(BasePayload),
+
+  // Sessions
+  CreateSession(CreateSessionPayload),
+  CloseSession(CloseSessionPayload),
+
+  // Cancellation
+  Cancel(CancelPayload),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RunPayload {
   pub id: u32,
   pub code: String,
+  /// The named session to run in, or [`None`] for the connection's own.
+  #[serde(default)]
+  pub session: Option<String>,
+  /// Whether to send the journal entries of the run as events.
+  #[serde(default)]
+  pub journal: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BasePayload {
   pub id: u32,
+  /// The named session to query, or [`None`] for the connection's own.
+  #[serde(default)]
+  pub session: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CreateSessionPayload {
+  pub id: u32,
+  /// The name of the session, or [`None`] to generate one.
+  #[serde(default)]
+  pub name: Option<String>,
+  /// The names of the modules to enable, or [`None`] for the server's
+  /// defaults.
+  #[serde(default)]
+  pub modules: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CloseSessionPayload {
+  pub id: u32,
+  pub session: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CancelPayload {
+  /// The id of the run to cancel.
+  pub id: u32,
+  /// The named session which the run is in, which lets any connection cancel
+  /// it, or [`None`] for the runs of the connection.
+  #[serde(default)]
+  pub session: Option<String>,
 }
 
 impl Incoming {
@@ -34,6 +86,9 @@ impl Incoming {
     match self {
       Incoming::Run(payload) | Incoming::RunNew(payload) => payload.id,
       Incoming::Stack(payload) | Incoming::Context(payload) => payload.id,
+      Incoming::CreateSession(payload) => payload.id,
+      Incoming::CloseSession(payload) => payload.id,
+      Incoming::Cancel(payload) => payload.id,
     }
   }
 }
@@ -43,7 +98,17 @@ impl Incoming {
 pub enum OutgoingError {
   RunError(RunErrorPayload),
   ParseError(ParseErrorPayload),
+  /// The message isn't a valid command.
   CommandError(CommandErrorPayload),
+  /// The message isn't text.
+  InvalidMessage(CommandErrorPayload),
+  /// The session doesn't exist, already exists, or asked for an unknown
+  /// module.
+  SessionError(CommandErrorPayload),
+  /// The named session is handling another request.
+  Busy(CommandErrorPayload),
+  /// The server panicked while handling the request.
+  InternalError(CommandErrorPayload),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,6 +125,7 @@ pub struct ParseErrorPayload {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommandErrorPayload {
+  /// The id of the request, or `0` if it doesn't have one.
   pub for_id: u32,
   pub value: String,
 }
@@ -69,6 +135,27 @@ pub struct CommandErrorPayload {
 pub enum Outgoing {
   Ok(OkPayload),
   Error(OutgoingError),
+  /// Something which happened while running a request, which is sent before
+  /// its response.
+  Event(EventPayload),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventPayload {
+  pub for_id: u32,
+  #[serde(flatten)]
+  pub event: Event,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Event {
+  /// The output of `print` or `pretty`.
+  Print(String),
+  /// The output of `debug`.
+  Debug(String),
+  /// An entry of the journal, if it was asked for.
+  Journal(JournalEntry),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -78,7 +165,9 @@ pub enum OkPayload {
   Null(NullPayload),
   Many(ManyPayload),
   Map(MapPayload),
-  Context(ContextPayload),
+  // Boxed, since a context is much larger than the other payloads.
+  Context(Box<ContextPayload>),
+  Session(SessionPayload),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -110,6 +199,13 @@ pub struct ContextPayload {
   pub value: Context,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionPayload {
+  pub for_id: u32,
+  /// The name of the session.
+  pub value: String,
+}
+
 impl Outgoing {
   pub fn for_id(&self) -> u32 {
     match self {
@@ -119,164 +215,851 @@ impl Outgoing {
         OkPayload::Many(p) => p.for_id,
         OkPayload::Map(p) => p.for_id,
         OkPayload::Context(p) => p.for_id,
+        OkPayload::Session(p) => p.for_id,
       },
       Outgoing::Error(error) => match error {
         OutgoingError::RunError(p) => p.for_id,
         OutgoingError::ParseError(p) => p.for_id,
-        OutgoingError::CommandError(p) => p.for_id,
+        OutgoingError::CommandError(p)
+        | OutgoingError::InvalidMessage(p)
+        | OutgoingError::SessionError(p)
+        | OutgoingError::Busy(p)
+        | OutgoingError::InternalError(p) => p.for_id,
       },
+      Outgoing::Event(payload) => payload.for_id,
     }
   }
 }
 
-#[allow(clippy::result_large_err)]
-fn run(
-  code: String,
-  eng_mutex: Rc<Mutex<Engine>>,
-  ctx_mutex: Rc<Mutex<Context>>,
-  out: &Sender,
+/// The settings which the sessions of the server are created with.
+#[derive(Clone)]
+pub struct ServerConfig {
+  /// The engine which each session's engine is cloned from.
+  pub engine: Engine,
+  /// The context which each session's context is cloned from, and reset to.
+  pub context: Context,
+  /// The modules which sessions can enable.
+  pub modules: Vec<Module>,
+  /// The names of the modules which sessions enable, unless they ask for
+  /// others.
+  pub default_modules: Vec<Symbol>,
+}
+
+impl ServerConfig {
+  /// Creates a session with the default modules.
+  pub fn default_session(&self) -> Session {
+    self.session(|name| self.default_modules.contains(&name))
+  }
+
+  /// Creates a session with the modules called `names`, or the default
+  /// modules if [`None`].
+  pub fn new_session(
+    &self,
+    names: Option<&[String]>,
+  ) -> Result<Session, String> {
+    let Some(names) = names else {
+      return Ok(self.default_session());
+    };
+
+    if let Some(unknown) = names.iter().find(|name| {
+      !self
+        .modules
+        .iter()
+        .any(|module| module.name().as_str() == name.as_str())
+    }) {
+      return Err(format!("unknown module '{unknown}'"));
+    }
+
+    Ok(self.session(|name| names.iter().any(|x| x == name.as_str())))
+  }
+
+  fn session(&self, is_enabled: impl Fn(Symbol) -> bool) -> Session {
+    let mut engine = self.engine.clone();
+    for module in self.modules.iter() {
+      if is_enabled(module.name()) {
+        engine.add_module(module.clone());
+      }
+    }
+
+    Session {
+      engine,
+      context: self.context.clone(),
+    }
+  }
+}
+
+/// An [`Engine`] and the [`Context`] which it runs code in.
+#[derive(Clone)]
+pub struct Session {
+  pub engine: Engine,
+  pub context: Context,
+}
+
+/// The named sessions, which are shared between connections.
+#[derive(Default)]
+struct Sessions {
+  named: HashMap<String, Worker>,
+  next_id: u32,
+}
+
+/// Creates the settings of a thread's sessions.
+type NewConfig = Arc<dyn Fn() -> ServerConfig + Send + Sync>;
+
+/// A request for the thread of a named session, which is called with the
+/// session.
+type Job = Box<dyn FnOnce(&mut Session, &ServerConfig) + Send>;
+
+/// The key and token of the request which a named session is handling.
+type Running = Option<(Option<String>, CancellationToken)>;
+
+/// The thread of a named session, which handles one request at a time.
+#[derive(Clone)]
+struct Worker {
+  jobs: mpsc::Sender<Job>,
+  running: Arc<Mutex<Running>>,
+}
+
+impl Worker {
+  /// Starts the thread of a session with the modules called `names`, or the
+  /// default modules if [`None`].
+  ///
+  /// The thread stops once the worker and its clones have been dropped.
+  fn spawn(
+    new_config: NewConfig,
+    names: Option<Vec<String>>,
+  ) -> Result<Self, String> {
+    let (jobs, rx) = mpsc::channel::<Job>();
+    let (started, result) = mpsc::channel();
+
+    thread::spawn(move || {
+      let config = new_config();
+      let mut session = match config.new_session(names.as_deref()) {
+        Ok(session) => session,
+        Err(e) => {
+          let _ = started.send(Err(e));
+          return;
+        }
+      };
+      let _ = started.send(Ok(()));
+
+      for job in rx {
+        job(&mut session, &config);
+      }
+    });
+
+    result
+      .recv()
+      .unwrap_or_else(|_| Err("the session failed to start".into()))?;
+
+    Ok(Self {
+      jobs,
+      running: Arc::default(),
+    })
+  }
+
+  fn running(&self) -> MutexGuard<'_, Running> {
+    self.running.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+/// Sends an [`Event`] to the client which made the request.
+pub type Emit = Arc<dyn Fn(EventPayload) + Send + Sync>;
+
+/// The requests of a connection which haven't been responded to yet, by their
+/// id, so that they can be cancelled while they run.
+///
+/// Requests are added by the thread which reads the connection, rather than
+/// the one which handles them, so that a request can be cancelled before it
+/// starts.
+#[derive(Debug, Clone, Default)]
+pub struct Runs(Arc<Mutex<HashMap<String, CancellationToken>>>);
+
+impl Runs {
+  /// Adds the request with `id`, replacing any other with the same id.
+  pub fn start(&self, id: String) {
+    self.lock().insert(id, CancellationToken::new());
+  }
+
+  /// Returns the token of the request with `id`, adding it if it wasn't.
+  pub fn token(&self, id: &str) -> CancellationToken {
+    self.lock().entry(id.into()).or_default().clone()
+  }
+
+  /// Removes the request with `id`, once it has been responded to.
+  pub fn finish(&self, id: &str) {
+    self.lock().remove(id);
+  }
+
+  /// Cancels the request with `id`, returning whether there was one.
+  pub fn cancel(&self, id: &str) -> bool {
+    match self.lock().get(id) {
+      Some(token) => {
+        token.cancel();
+        true
+      }
+      None => false,
+    }
+  }
+
+  fn lock(&self) -> MutexGuard<'_, HashMap<String, CancellationToken>> {
+    self.0.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+/// Responds to the requests of every transport, such as websockets or
+/// JSON-RPC.
+///
+/// Sessions can't be sent between threads, so each [`Client`] and named
+/// session is handled on its own thread, so that one doesn't wait for the
+/// runs of another.
+pub struct Server {
+  new_config: NewConfig,
+  sessions: Mutex<Sessions>,
+}
+
+impl Server {
+  /// Creates a server whose sessions are created with the settings returned
+  /// by `new_config`, which is called on the thread of each client and named
+  /// session.
+  pub fn new(
+    new_config: impl Fn() -> ServerConfig + Send + Sync + 'static,
+  ) -> Self {
+    Self {
+      new_config: Arc::new(new_config),
+      sessions: Mutex::new(Sessions::default()),
+    }
+  }
+
+  /// Cancels the request with `key`, returning whether there was one.
+  ///
+  /// The request is looked for in the named session called `session`, which
+  /// can be cancelled by any connection, or in `runs` if [`None`].
+  pub fn cancel(&self, runs: &Runs, session: Option<&str>, key: &str) -> bool {
+    let Some(name) = session else {
+      return runs.cancel(key);
+    };
+
+    let Some(worker) = self.sessions().named.get(name).cloned() else {
+      return false;
+    };
+    let running = worker.running();
+    match &*running {
+      Some((Some(running), token)) if running == key => {
+        token.cancel();
+        true
+      }
+      _ => false,
+    }
+  }
+
+  fn create_session(&self, payload: CreateSessionPayload) -> Outgoing {
+    let CreateSessionPayload { id, name, modules } = payload;
+    let mut sessions = self.sessions();
+
+    let name = name.unwrap_or_else(|| {
+      sessions.next_id += 1;
+      format!("session-{}", sessions.next_id)
+    });
+
+    if sessions.named.contains_key(&name) {
+      return error(
+        OutgoingError::SessionError,
+        id,
+        format!("session '{name}' already exists"),
+      );
+    }
+
+    match Worker::spawn(self.new_config.clone(), modules) {
+      Ok(worker) => {
+        sessions.named.insert(name.clone(), worker);
+        Outgoing::Ok(OkPayload::Session(SessionPayload {
+          for_id: id,
+          value: name,
+        }))
+      }
+      Err(e) => error(OutgoingError::SessionError, id, e),
+    }
+  }
+
+  fn close_session(&self, payload: CloseSessionPayload) -> Outgoing {
+    let CloseSessionPayload { id, session } = payload;
+    let mut sessions = self.sessions();
+
+    match sessions.named.get(&session) {
+      Some(worker) if worker.running().is_some() => busy(id),
+      Some(_) => {
+        sessions.named.remove(&session);
+        Outgoing::Ok(OkPayload::Null(NullPayload { for_id: id }))
+      }
+      None => unknown_session(id, &session),
+    }
+  }
+
+  /// Calls `f` on the thread of the session called `name`, and returns its
+  /// result once it has finished.
+  ///
+  /// Fails with [`OutgoingError::Busy`] if the session is handling another
+  /// request, rather than waiting for it. The request can be cancelled with
+  /// `key` by any connection while it runs.
+  fn with_session<R: Send + 'static>(
+    &self,
+    id: u32,
+    name: &str,
+    key: Option<&str>,
+    token: CancellationToken,
+    f: impl FnOnce(&mut Session, &ServerConfig) -> R + Send + 'static,
+  ) -> Result<R, Outgoing> {
+    let Some(worker) = self.sessions().named.get(name).cloned() else {
+      return Err(unknown_session(id, name));
+    };
+
+    {
+      let mut running = worker.running();
+      if running.is_some() {
+        return Err(busy(id));
+      }
+      *running = Some((key.map(str::to_owned), token));
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let running = worker.running.clone();
+    let job: Job = Box::new(move |session, config| {
+      let result = f(session, config);
+      // The session is freed before the result is sent, so that the next
+      // request of the connection doesn't find it busy.
+      *running.lock().unwrap_or_else(PoisonError::into_inner) = None;
+      let _ = tx.send(result);
+    });
+
+    // A job which isn't sent is dropped, which fails the receive below.
+    let _ = worker.jobs.send(job);
+    rx.recv().map_err(|_| {
+      *worker.running() = None;
+      error(
+        OutgoingError::InternalError,
+        id,
+        "the session stopped while handling the request".into(),
+      )
+    })
+  }
+
+  fn sessions(&self) -> MutexGuard<'_, Sessions> {
+    // A request which panicked only leaves its own session in a bad state, so
+    // the others can still be used.
+    self.sessions.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+/// A connection to a [`Server`], which has its own session and handles its
+/// requests in order, on the thread which it was created on.
+pub struct Client {
+  server: Arc<Server>,
+  config: ServerConfig,
+  own: Session,
+  runs: Runs,
+}
+
+impl Client {
+  /// Creates a client of `server`, whose requests can be cancelled through
+  /// `runs`.
+  pub fn new(server: Arc<Server>, runs: Runs) -> Self {
+    let config = (server.new_config)();
+    let own = config.default_session();
+
+    Self {
+      server,
+      config,
+      own,
+      runs,
+    }
+  }
+
+  /// Parses `message` as an [`Incoming`] and responds to it, as JSON.
+  pub fn respond_to_text(&mut self, message: &str, emit: Emit) -> String {
+    let render = |outgoing: Outgoing| serde_json::to_string(&outgoing).unwrap();
+
+    match serde_json::from_str::<Incoming>(message) {
+      Ok(incoming) => {
+        let key = incoming.id().to_string();
+        self.respond(incoming, Some(&key), emit, render)
+      }
+      Err(e) => render(error(
+        OutgoingError::CommandError,
+        recover_id(message),
+        e.to_string(),
+      )),
+    }
+  }
+
+  /// Responds to `incoming`, using the client's own session unless it names
+  /// another, and returns the response passed to `render`.
+  ///
+  /// The [`Event`]s of a run are passed to `emit` as they happen, and the run
+  /// stops once it's cancelled with `key`. The requests of a named session are
+  /// rendered on its thread, since their responses can't be sent between
+  /// threads.
+  pub fn respond<R: Send + 'static>(
+    &mut self,
+    incoming: Incoming,
+    key: Option<&str>,
+    emit: Emit,
+    render: impl Fn(Outgoing) -> R + Clone + Send + 'static,
+  ) -> R {
+    let id = incoming.id();
+    let token = key.map(|key| self.runs.token(key)).unwrap_or_default();
+    let name = match &incoming {
+      Incoming::Run(payload) | Incoming::RunNew(payload) => {
+        payload.session.clone()
+      }
+      Incoming::Stack(payload) | Incoming::Context(payload) => {
+        payload.session.clone()
+      }
+      _ => None,
+    };
+
+    let response = match incoming {
+      Incoming::CreateSession(payload) => {
+        render(self.server.create_session(payload))
+      }
+      Incoming::CloseSession(payload) => {
+        render(self.server.close_session(payload))
+      }
+
+      // Cancels are handled by the thread which reads the connection, since
+      // this one may be busy with the run they cancel.
+      Incoming::Cancel(CancelPayload { id, .. }) => render(not_running(id)),
+
+      incoming => match name {
+        None => render(respond_in(
+          &mut self.own,
+          &self.config,
+          incoming,
+          emit,
+          token,
+        )),
+        Some(name) => {
+          let f = {
+            let render = render.clone();
+            let token = token.clone();
+            move |session: &mut Session, config: &ServerConfig| {
+              render(respond_in(session, config, incoming, emit, token))
+            }
+          };
+
+          self
+            .server
+            .with_session(id, &name, key, token, f)
+            .unwrap_or_else(render)
+        }
+      },
+    };
+
+    if let Some(key) = key {
+      self.runs.finish(key);
+    }
+
+    response
+  }
+}
+
+/// Responds to a request which uses a single session, such as a run.
+fn respond_in(
+  session: &mut Session,
+  config: &ServerConfig,
+  incoming: Incoming,
+  emit: Emit,
+  token: CancellationToken,
+) -> Outgoing {
+  let id = incoming.id();
+
+  // A panic is sent back as an error, rather than stopping the thread for
+  // every other request.
+  panic::catch_unwind(AssertUnwindSafe(|| match incoming {
+    Incoming::RunNew(payload) => {
+      run(payload, session, config, true, emit, token)
+    }
+    Incoming::Run(payload) => run(payload, session, config, false, emit, token),
+
+    Incoming::Stack(BasePayload { id, .. }) => {
+      Outgoing::Ok(OkPayload::Many(ManyPayload {
+        for_id: id,
+        value: session.context.stack().to_vec(),
+      }))
+    }
+    Incoming::Context(BasePayload { id, .. }) => {
+      Outgoing::Ok(OkPayload::Context(Box::new(ContextPayload {
+        for_id: id,
+        value: session.context.clone(),
+      })))
+    }
+
+    Incoming::CreateSession(_)
+    | Incoming::CloseSession(_)
+    | Incoming::Cancel(_) => {
+      unreachable!("requests without a session are handled by the client")
+    }
+  }))
+  .unwrap_or_else(|_| {
+    error(
+      OutgoingError::InternalError,
+      id,
+      "the server panicked while handling the request".into(),
+    )
+  })
+}
+
+fn error(
+  error: fn(CommandErrorPayload) -> OutgoingError,
   id: u32,
+  value: String,
+) -> Outgoing {
+  Outgoing::Error(error(CommandErrorPayload { for_id: id, value }))
+}
+
+fn busy(id: u32) -> Outgoing {
+  error(
+    OutgoingError::Busy,
+    id,
+    "the session is handling another request".into(),
+  )
+}
+
+fn unknown_session(id: u32, name: &str) -> Outgoing {
+  error(
+    OutgoingError::SessionError,
+    id,
+    format!("unknown session '{name}'"),
+  )
+}
+
+fn not_running(id: u32) -> Outgoing {
+  error(
+    OutgoingError::CommandError,
+    id,
+    format!("no run with id {id} is running"),
+  )
+}
+
+/// Returns the id of a message which isn't a valid command, if it's JSON with
+/// an `id`, or `0` otherwise.
+fn recover_id(message: &str) -> u32 {
+  serde_json::from_str::<serde_json::Value>(message)
+    .ok()
+    .and_then(|value| value.get("id")?.as_u64())
+    .and_then(|id| u32::try_from(id).ok())
+    .unwrap_or(0)
+}
+
+fn run(
+  payload: RunPayload,
+  session: &mut Session,
+  config: &ServerConfig,
   reset: bool,
-) -> ws::Result<()> {
+  emit: Emit,
+  token: CancellationToken,
+) -> Outgoing {
+  let RunPayload {
+    id, code, journal, ..
+  } = payload;
+
   let source = Source::new("runner", code);
   let mut lexer = Lexer::new(source);
   let exprs = match parse(&mut lexer) {
     Ok(e) => e,
     Err(err) => {
-      return out.send(
-        serde_json::to_string(&Outgoing::Error(OutgoingError::ParseError(
-          ParseErrorPayload {
-            for_id: id,
-            value: err,
-          },
-        )))
-        .unwrap(),
-      );
+      return Outgoing::Error(OutgoingError::ParseError(ParseErrorPayload {
+        for_id: id,
+        value: err,
+      }));
     }
   };
 
-  match (eng_mutex.try_lock(), ctx_mutex.try_lock()) {
-    (Ok(engine), Ok(mut guard)) => {
-      if reset {
-        let _ = mem::replace(&mut *guard, Context::new());
+  if reset {
+    session.context = config.context.clone();
+  }
+
+  let mut context = mem::take(&mut session.context);
+
+  // A journal is only kept for this run, unless the session already has one.
+  let had_journal = context.journal().is_some();
+  if journal && !had_journal {
+    context = context.with_journal(None);
+  }
+  let journal_len = context.journal().as_ref().map_or(0, |j| j.entries().len());
+
+  // The output is sent to the client instead of the server's stdout.
+  let event = |event: fn(String) -> Event| {
+    let emit = emit.clone();
+    Some(Arc::new(move |s: String| {
+      emit(EventPayload {
+        for_id: id,
+        event: event(s),
+      })
+    }) as Arc<dyn Fn(String)>)
+  };
+  let engine = session
+    .engine
+    .clone()
+    .with_print_hook(event(Event::Print))
+    .with_debug_hook(event(Event::Debug))
+    .with_cancellation_token(Some(token));
+
+  // The context was taken out of the session, so a panic would leave it with
+  // the default one, without the configured fuel, journal or limits.
+  let result =
+    panic::catch_unwind(AssertUnwindSafe(|| engine.run(context, exprs)))
+      .unwrap_or_else(|e| {
+        session.context = config.context.clone();
+        panic::resume_unwind(e)
+      });
+
+  let (mut context, error) = match result {
+    Ok(context) => (context, None),
+    Err(mut e) => (mem::take(&mut *e.context), Some(e)),
+  };
+
+  if journal {
+    if let Some(j) = context.journal_mut() {
+      j.commit();
+      for entry in j.entries().iter().skip(journal_len) {
+        emit(EventPayload {
+          for_id: id,
+          event: Event::Journal(entry.clone()),
+        });
       }
+    }
 
-      let context = mem::take(&mut *guard);
-      let result = engine.run(context, exprs);
-
-      match result {
-        Ok(ctx) => {
-          print_stack(&ctx);
-          *guard = ctx;
-
-          match guard.stack().last().cloned() {
-            Some(expr) => out.send(
-              serde_json::to_string(&Outgoing::Ok(OkPayload::Single(
-                SinglePayload {
-                  for_id: id,
-                  value: expr,
-                },
-              )))
-              .unwrap(),
-            ),
-            None => out.send(
-              serde_json::to_string(&Outgoing::Ok(OkPayload::Null(
-                NullPayload { for_id: id },
-              )))
-              .unwrap(),
-            ),
+    if !had_journal {
+      *context.journal_mut() = None;
+    }
+  }
+
+  // Logs go to stderr, since stdout carries the responses over stdio.
+  if let Some(e) = &error {
+    eprintln!("error: {e}");
+  }
+  eprint_stack(&context);
+  session.context = context;
+
+  match error {
+    None => match session.context.stack().last().cloned() {
+      Some(expr) => Outgoing::Ok(OkPayload::Single(SinglePayload {
+        for_id: id,
+        value: expr,
+      })),
+      None => Outgoing::Ok(OkPayload::Null(NullPayload { for_id: id })),
+    },
+    Some(mut e) => {
+      *e.context = session.context.clone();
+
+      Outgoing::Error(OutgoingError::RunError(RunErrorPayload {
+        for_id: id,
+        value: e,
+      }))
+    }
+  }
+}
+
+/// A websocket client of the server, which passes its messages on to the
+/// thread which handles them, except for cancels.
+struct Connection {
+  out: Sender,
+  server: Arc<Server>,
+  runs: Runs,
+  tx: mpsc::Sender<Message>,
+}
+
+impl Handler for Connection {
+  fn on_message(&mut self, msg: Message) -> ws::Result<()> {
+    if let Message::Text(text) = &msg {
+      match serde_json::from_str::<Incoming>(text) {
+        Ok(Incoming::Cancel(CancelPayload { id, session })) => {
+          // The cancelled run responds with its error instead.
+          let key = id.to_string();
+          if self.server.cancel(&self.runs, session.as_deref(), &key) {
+            return Ok(());
           }
+
+          return self
+            .out
+            .send(serde_json::to_string(&not_running(id)).unwrap());
         }
-        Err(e) => {
-          eprintln!("error: {e}");
-          eprint_stack(&e.context);
-
-          out.send(
-            serde_json::to_string(&Outgoing::Error(OutgoingError::RunError(
-              RunErrorPayload {
-                for_id: id,
-                value: e,
-              },
-            )))
-            .unwrap(),
-          )
-        }
+        Ok(incoming) => self.runs.start(incoming.id().to_string()),
+        Err(_) => {}
       }
     }
-    _ => todo!("mutex not lock"),
+
+    let _ = self.tx.send(msg);
+    Ok(())
   }
 }
 
+/// Runs a websocket server on `host` and `port`.
+///
+/// Each connection has its own session, and can create named sessions which
+/// are shared with every other connection until they're closed.
+///
+/// The requests of each connection are handled on their own thread, so that
+/// the sockets are still served, and runs can be cancelled, while a request
+/// is running.
 #[allow(clippy::result_large_err)]
-pub fn handle(
-  out: &Sender,
-  msg: &Message,
-  eng_mutex: Rc<Mutex<Engine>>,
-  ctx_mutex: Rc<Mutex<Context>>,
-) -> ws::Result<()> {
-  if let Message::Text(string) = msg {
-    let request = serde_json::from_str::<Incoming>(string);
-
-    match request {
-      Ok(incoming) => match incoming {
-        Incoming::RunNew(RunPayload { id, code }) => {
-          run(code, eng_mutex, ctx_mutex, out, id, true)
-        }
-        Incoming::Run(RunPayload { id, code }) => {
-          run(code, eng_mutex, ctx_mutex, out, id, false)
-        }
+pub fn listen(host: &str, port: u16, server: Server) -> ws::Result<()> {
+  let address = format!("{host}:{port}");
+  let server = Arc::new(server);
 
-        Incoming::Stack(BasePayload { id }) => match ctx_mutex.try_lock() {
-          Ok(context) => out.send(
-            serde_json::to_string(&Outgoing::Ok(OkPayload::Many(
-              ManyPayload {
-                for_id: id,
-                value: context.stack().to_vec(),
-              },
-            )))
-            .unwrap(),
-          ),
-          Err(_) => todo!(),
-        },
-        Incoming::Context(BasePayload { id }) => match ctx_mutex.try_lock() {
-          Ok(context) => out.send(
-            serde_json::to_string(&Outgoing::Ok(OkPayload::Context(
-              ContextPayload {
-                for_id: id,
-                value: context.clone(),
-              },
-            )))
+  println!("Websocket server running on ws://{address}");
+  ws::listen(address, |out| {
+    let runs = Runs::default();
+    let (tx, rx) = mpsc::channel();
+
+    {
+      let server = server.clone();
+      let runs = runs.clone();
+      let out = out.clone();
+
+      // The thread stops once the connection has closed, which drops the
+      // sender of its messages.
+      thread::spawn(move || {
+        let mut client = Client::new(server, runs);
+        for msg in rx {
+          let response = match msg {
+            Message::Text(text) => {
+              let events = out.clone();
+              let emit: Emit = Arc::new(move |payload| {
+                let event = Outgoing::Event(payload);
+                let _ = events.send(serde_json::to_string(&event).unwrap());
+              });
+
+              client.respond_to_text(&text, emit)
+            }
+            Message::Binary(_) => serde_json::to_string(&error(
+              OutgoingError::InvalidMessage,
+              0,
+              "messages must be text".into(),
+            ))
             .unwrap(),
-          ),
-          Err(_) => todo!(),
-        },
-      },
-      Err(parse_error) => out.send(
-        serde_json::to_string(&Outgoing::Error(OutgoingError::CommandError(
-          CommandErrorPayload {
-            // TODO: we don't get an ID here so this is a special case
-            for_id: 0,
-            value: parse_error.to_string(),
-          },
-        )))
-        .unwrap(),
-      ),
+          };
+
+          let _ = out.send(response);
+        }
+      });
     }
-  } else {
-    todo!("message not text")
-  }
+
+    Connection {
+      out,
+      server: server.clone(),
+      runs,
+      tx,
+    }
+  })
 }
 
-pub fn listen() {
-  let eng_mutex = Rc::new(Mutex::new(Engine::new()));
-  let ctx_mutex = Rc::new(Mutex::new(Context::new()));
+#[cfg(test)]
+mod tests {
+  use std::time::{Duration, Instant};
 
-  println!("Websocket server running on ws://localhost:5501");
-  ws::listen("localhost:5001", |out| {
-    let eng_mutex = eng_mutex.clone();
-    let ctx_mutex = ctx_mutex.clone();
+  use super::*;
 
-    move |msg| handle(&out, &msg, eng_mutex.clone(), ctx_mutex.clone())
-  })
-  .unwrap();
+  fn server() -> Arc<Server> {
+    Arc::new(Server::new(|| ServerConfig {
+      engine: Engine::new(),
+      context: Context::new(),
+      modules: Vec::new(),
+      default_modules: Vec::new(),
+    }))
+  }
+
+  fn respond(client: &mut Client, incoming: Incoming) -> serde_json::Value {
+    let key = incoming.id().to_string();
+    client.respond(incoming, Some(&key), Arc::new(|_| {}), |outgoing| {
+      serde_json::to_value(outgoing).unwrap()
+    })
+  }
+
+  fn run(id: u32, code: &str, session: Option<&str>) -> Incoming {
+    Incoming::Run(RunPayload {
+      id,
+      code: code.into(),
+      session: session.map(Into::into),
+      journal: false,
+    })
+  }
+
+  #[test]
+  fn panics_keep_the_configured_context() {
+    let limits = Limits::new().with_stack_len(8);
+    let server = Server::new(move || {
+      let module = Module::new(Symbol::from_ref("test")).with_func(
+        Symbol::from_ref("panic"),
+        Arc::new(|_, _, _| panic!("the module panicked")),
+      );
+
+      ServerConfig {
+        engine: Engine::new().with_module(module),
+        context: Context::new().with_fuel(100).with_limits(limits),
+        modules: Vec::new(),
+        default_modules: Vec::new(),
+      }
+    });
+    let mut client = Client::new(Arc::new(server), Runs::default());
+
+    let outgoing = respond(&mut client, run(1, "1 test:panic", None));
+
+    assert_eq!(outgoing["error"], "internal_error");
+    assert_eq!(client.own.context.fuel(), Some(100));
+    assert_eq!(client.own.context.limits(), &limits);
+  }
+
+  #[test]
+  fn clients_dont_wait_for_each_others_runs() {
+    let server = server();
+    let runs = Runs::default();
+    let mut client = Client::new(server.clone(), runs.clone());
+
+    let created = respond(
+      &mut client,
+      Incoming::CreateSession(CreateSessionPayload {
+        id: 1,
+        name: Some("shared".into()),
+        modules: None,
+      }),
+    );
+    assert_eq!(created["value"], "shared");
+
+    let looping = {
+      let server = server.clone();
+      thread::spawn(move || {
+        let mut other = Client::new(server, Runs::default());
+        respond(&mut other, run(1, "(fn! recur)", Some("shared")))
+      })
+    };
+
+    // Polling with requests could make the other client's run find the
+    // session busy instead.
+    let start = Instant::now();
+    while server.sessions().named["shared"].running().is_none() {
+      assert!(start.elapsed() < Duration::from_secs(5));
+      thread::sleep(Duration::from_millis(10));
+    }
+
+    let stack = Incoming::Stack(BasePayload {
+      id: 2,
+      session: Some("shared".into()),
+    });
+    assert_eq!(respond(&mut client, stack)["error"], "busy");
+
+    let own = respond(&mut client, run(3, "1 2 +", None));
+    assert_eq!(
+      own["value"],
+      serde_json::to_value(Expr::from(ExprKind::Integer(3))).unwrap()
+    );
+
+    assert!(server.cancel(&runs, Some("shared"), "1"));
+    let cancelled = looping.join().unwrap();
+    assert_eq!(cancelled["error"], "run_error");
+    assert_eq!(cancelled["value"]["reason"], "Cancelled");
+
+    let shared = respond(&mut client, run(4, "1", Some("shared")));
+    assert_eq!(shared["status"], "ok");
+  }
 }
@@ -0,0 +1,616 @@
+//! Serves the requests of a [`Server`] as [JSON-RPC 2.0], over stdio or a Unix
+//! domain socket.
+//!
+//! Each message is a single line of JSON. The methods are the `type`s of an
+//! [`Incoming`], such as `run`, and their params are its other fields, except
+//! for the `id`. The [`Event`]s of a request are sent as `event`
+//! notifications before its response, with the id of the request in their
+//! params.
+//!
+//! A request can be cancelled with the `cancel` method, whose `id` param is the
+//! id of the request. A request in a named session can be cancelled by any
+//! connection, if the `session` param names it. The cancelled request responds
+//! with a run error.
+//!
+//! [JSON-RPC 2.0]: https://www.jsonrpc.org/specification
+
+use std::{
+  io::{self, BufRead, Write},
+  sync::{mpsc, Arc},
+  thread,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::{
+  Client, Emit, Event, EventPayload, Incoming, NullPayload, OkPayload,
+  Outgoing, OutgoingError, Runs, Server,
+};
+
+/// The methods which can be called, which are the `type`s of an [`Incoming`].
+const METHODS: &[&str] = &[
+  "run",
+  "run_new",
+  "stack",
+  "context",
+  "create_session",
+  "close_session",
+];
+
+// The error codes which are defined by the specification.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+// The error codes of the [`OutgoingError`]s which don't have one above.
+const RUN_ERROR: i64 = 1;
+const SYNTAX_ERROR: i64 = 2;
+const SESSION_ERROR: i64 = 3;
+const BUSY: i64 = 4;
+
+/// Writes a line to the client.
+pub type Notify = Arc<dyn Fn(String) + Send + Sync>;
+
+#[derive(Debug, Clone, Deserialize)]
+struct Request {
+  jsonrpc: String,
+  method: String,
+  #[serde(default)]
+  params: Option<Value>,
+  /// The id of the request, or [`None`] if it's a notification.
+  #[serde(default)]
+  id: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Response {
+  jsonrpc: &'static str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  result: Option<Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<ResponseError>,
+  id: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Notification {
+  jsonrpc: &'static str,
+  method: &'static str,
+  params: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ResponseError {
+  code: i64,
+  message: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  data: Option<Value>,
+}
+
+impl Response {
+  fn ok(id: Value, result: Value) -> Self {
+    Self {
+      jsonrpc: "2.0",
+      result: Some(result),
+      error: None,
+      id,
+    }
+  }
+
+  fn error(id: Value, code: i64, message: String, data: Option<Value>) -> Self {
+    Self {
+      jsonrpc: "2.0",
+      result: None,
+      error: Some(ResponseError {
+        code,
+        message,
+        data,
+      }),
+      id,
+    }
+  }
+
+  /// Converts the response of the [`Server`] to `id`.
+  fn from_outgoing(id: Value, outgoing: Outgoing) -> Self {
+    match outgoing {
+      Outgoing::Ok(payload) => Self::ok(id, without_for_id(&payload)),
+      Outgoing::Error(error) => {
+        let (code, message) = match &error {
+          OutgoingError::RunError(p) => (RUN_ERROR, p.value.to_string()),
+          OutgoingError::ParseError(p) => (SYNTAX_ERROR, p.value.to_string()),
+          OutgoingError::CommandError(p) => (INVALID_PARAMS, p.value.clone()),
+          OutgoingError::InvalidMessage(p) => {
+            (INVALID_REQUEST, p.value.clone())
+          }
+          OutgoingError::SessionError(p) => (SESSION_ERROR, p.value.clone()),
+          OutgoingError::Busy(p) => (BUSY, p.value.clone()),
+          OutgoingError::InternalError(p) => (INTERNAL_ERROR, p.value.clone()),
+        };
+
+        Self::error(id, code, message, Some(without_for_id(&error)))
+      }
+      // Events are sent as notifications instead.
+      Outgoing::Event(_) => unreachable!(),
+    }
+  }
+}
+
+/// Returns an `event` notification of `event`, for the request with `id`.
+fn event_notification(id: &Value, event: &Event) -> String {
+  let mut params = serde_json::to_value(event).unwrap();
+  if let Value::Object(object) = &mut params {
+    object.insert("id".into(), id.clone());
+  }
+
+  serde_json::to_string(&Notification {
+    jsonrpc: "2.0",
+    method: "event",
+    params,
+  })
+  .unwrap()
+}
+
+/// Serializes a payload of an [`Outgoing`], without its `for_id`, since the
+/// response has the id instead.
+fn without_for_id<T: Serialize>(payload: &T) -> Value {
+  let mut value = serde_json::to_value(payload).unwrap();
+  if let Value::Object(object) = &mut value {
+    object.remove("for_id");
+  }
+
+  value
+}
+
+/// Reads a line of JSON on the thread which reads the connection, before it's
+/// passed on to [`respond`].
+///
+/// A `cancel` is responded to here, via `notify`, since the thread which
+/// handles requests may be busy with the one it cancels. Returns whether the
+/// line was a `cancel`. The requests of other lines are added to `runs`.
+fn intercept(
+  server: &Server,
+  runs: &Runs,
+  line: &str,
+  notify: &Notify,
+) -> bool {
+  let value = match serde_json::from_str::<Value>(line) {
+    Ok(value) => value,
+    Err(_) => return false,
+  };
+
+  if let Ok(request) = serde_json::from_value::<Request>(value.clone()) {
+    if request.jsonrpc == "2.0" && request.method == "cancel" {
+      let response = cancel(server, runs, request.params);
+      if let Some(id) = request.id {
+        notify(serde_json::to_string(&to_response(id, response)).unwrap());
+      }
+
+      return true;
+    }
+  }
+
+  let requests = match value {
+    Value::Array(batch) => batch,
+    value => vec![value],
+  };
+  for request in requests {
+    let id = request.get("id").filter(|id| !id.is_null());
+    let method = request.get("method").and_then(Value::as_str);
+    if let (Some(id), false) = (id, method == Some("cancel")) {
+      runs.start(id.to_string());
+    }
+  }
+
+  false
+}
+
+/// Cancels the request with the `id` in `params`, in the named session of its
+/// `session` if it has one.
+fn cancel(
+  server: &Server,
+  runs: &Runs,
+  params: Option<Value>,
+) -> Result<Outgoing, (i64, String)> {
+  let Some(id) = params.as_ref().and_then(|params| params.get("id")) else {
+    return Err((INVALID_PARAMS, "missing the id of the request".into()));
+  };
+  let session = match params.as_ref().and_then(|params| params.get("session")) {
+    None | Some(Value::Null) => None,
+    Some(Value::String(session)) => Some(session.as_str()),
+    Some(_) => {
+      return Err((INVALID_PARAMS, "the session must be a string".into()))
+    }
+  };
+
+  if server.cancel(runs, session, &id.to_string()) {
+    Ok(Outgoing::Ok(OkPayload::Null(NullPayload { for_id: 0 })))
+  } else {
+    Err((
+      INVALID_PARAMS,
+      format!("no request with id {id} is running"),
+    ))
+  }
+}
+
+fn to_response(
+  id: Value,
+  response: Result<Outgoing, (i64, String)>,
+) -> Response {
+  match response {
+    Ok(outgoing) => Response::from_outgoing(id, outgoing),
+    Err((code, message)) => Response::error(id, code, message, None),
+  }
+}
+
+/// Responds to a line of JSON, which is either a request or a batch of them.
+///
+/// Lines of notifications are passed to `notify` while the requests run.
+/// Returns [`None`] if there is nothing to respond with, such as when every
+/// request is a notification.
+pub fn respond(
+  client: &mut Client,
+  line: &str,
+  notify: Notify,
+) -> Option<String> {
+  let response = match serde_json::from_str::<Value>(line) {
+    Ok(Value::Array(batch)) if batch.is_empty() => {
+      serde_json::to_string(&Response::error(
+        Value::Null,
+        INVALID_REQUEST,
+        "a batch must have at least one request".into(),
+        None,
+      ))
+    }
+    Ok(Value::Array(batch)) => {
+      let responses = batch
+        .into_iter()
+        .filter_map(|value| respond_to_value(client, value, notify.clone()))
+        .collect::<Vec<_>>();
+
+      if responses.is_empty() {
+        return None;
+      }
+
+      serde_json::to_string(&responses)
+    }
+    Ok(value) => {
+      let response = respond_to_value(client, value, notify)?;
+      serde_json::to_string(&response)
+    }
+    Err(e) => serde_json::to_string(&Response::error(
+      Value::Null,
+      PARSE_ERROR,
+      e.to_string(),
+      None,
+    )),
+  };
+
+  Some(response.unwrap())
+}
+
+fn respond_to_value(
+  client: &mut Client,
+  value: Value,
+  notify: Notify,
+) -> Option<Response> {
+  let request = match serde_json::from_value::<Request>(value) {
+    Ok(request) if request.jsonrpc == "2.0" => request,
+    Ok(request) => {
+      return Some(Response::error(
+        request.id.unwrap_or(Value::Null),
+        INVALID_REQUEST,
+        "the jsonrpc version must be 2.0".into(),
+        None,
+      ))
+    }
+    Err(e) => {
+      return Some(Response::error(
+        Value::Null,
+        INVALID_REQUEST,
+        e.to_string(),
+        None,
+      ))
+    }
+  };
+
+  // Notifications have no id to tag their events with, so they aren't sent.
+  let id = request.id.clone();
+  let emit: Emit = Arc::new(move |payload: EventPayload| {
+    if let Some(id) = &id {
+      notify(event_notification(id, &payload.event));
+    }
+  });
+
+  // A cancel in a batch can only cancel the requests after it.
+  if request.method == "cancel" {
+    let response = cancel(&client.server, &client.runs, request.params);
+    return request.id.map(|id| to_response(id, response));
+  }
+
+  let id = request.id;
+  let incoming = match to_incoming(request.method, request.params) {
+    Ok(incoming) => incoming,
+    Err((code, message)) => {
+      return id.map(|id| Response::error(id, code, message, None))
+    }
+  };
+
+  // The response is rendered by the thread which handles the request.
+  let key = id.as_ref().map(Value::to_string);
+  client.respond(incoming, key.as_deref(), emit, move |outgoing| {
+    id.clone().map(|id| Response::from_outgoing(id, outgoing))
+  })
+}
+
+/// Converts a request to the [`Incoming`] of its method.
+fn to_incoming(
+  method: String,
+  params: Option<Value>,
+) -> Result<Incoming, (i64, String)> {
+  if !METHODS.contains(&method.as_str()) {
+    return Err((METHOD_NOT_FOUND, format!("unknown method '{method}'")));
+  }
+
+  let mut params = match params {
+    Some(Value::Object(params)) => params,
+    None => Map::new(),
+    Some(_) => {
+      return Err((INVALID_PARAMS, "params must be an object".into()));
+    }
+  };
+
+  // The responses use the id of the request instead.
+  params.insert("type".into(), Value::String(method));
+  params.insert("id".into(), json!(0));
+
+  serde_json::from_value::<Incoming>(Value::Object(params))
+    .map_err(|e| (INVALID_PARAMS, e.to_string()))
+}
+
+/// Serves requests from stdin, writing the responses to stdout, until stdin is
+/// closed.
+///
+/// Stdin is read on its own thread, so that requests can be cancelled while
+/// they run on this one.
+pub fn serve_stdio(server: Server) -> io::Result<()> {
+  let server = Arc::new(server);
+  let runs = Runs::default();
+  let mut client = Client::new(server.clone(), runs.clone());
+  let notify: Notify = Arc::new(|line: String| {
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{line}").and_then(|_| stdout.flush());
+  });
+
+  let (tx, rx) = mpsc::channel();
+  let reader = {
+    let notify = notify.clone();
+    thread::spawn(move || -> io::Result<()> {
+      for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() || intercept(&server, &runs, &line, &notify) {
+          continue;
+        }
+
+        if tx.send(line).is_err() {
+          break;
+        }
+      }
+
+      Ok(())
+    })
+  };
+
+  for line in rx {
+    if let Some(response) = respond(&mut client, &line, notify.clone()) {
+      let mut stdout = io::stdout().lock();
+      writeln!(stdout, "{response}")?;
+      stdout.flush()?;
+    }
+  }
+
+  reader
+    .join()
+    .unwrap_or_else(|e| std::panic::resume_unwind(e))
+}
+
+#[cfg(unix)]
+pub use unix::serve_unix;
+
+/// Fails, since Unix domain sockets aren't supported on this platform.
+#[cfg(not(unix))]
+pub fn serve_unix(_: Server, _: &std::path::Path) -> io::Result<()> {
+  Err(io::Error::new(
+    io::ErrorKind::Unsupported,
+    "unix domain sockets aren't supported on this platform",
+  ))
+}
+
+#[cfg(unix)]
+mod unix {
+  use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+      fs::FileTypeExt,
+      net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    process,
+    sync::mpsc,
+    thread,
+  };
+
+  use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM},
+    iterator::Signals,
+  };
+
+  use super::{intercept, respond, Arc, Client, Notify, Runs, Server};
+
+  /// Serves requests from the connections to a Unix domain socket at `path`.
+  ///
+  /// Each connection has its own session. Connections are read on their own
+  /// threads, which handle cancels, and their other requests are handled on
+  /// another thread of their own, so that one connection doesn't wait for the
+  /// runs of another.
+  ///
+  /// A socket left at `path` by an earlier server which has stopped is
+  /// replaced, but this fails with [`io::ErrorKind::AddrInUse`] if a server
+  /// is still listening on it. The socket is removed once the server stops.
+  pub fn serve_unix(server: Server, path: &Path) -> io::Result<()> {
+    let is_socket = fs::symlink_metadata(path)
+      .is_ok_and(|metadata| metadata.file_type().is_socket());
+    if is_socket {
+      match UnixStream::connect(path) {
+        Ok(_) => {
+          return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("a server is already listening on {}", path.display()),
+          ))
+        }
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+          fs::remove_file(path)?
+        }
+        Err(e) => return Err(e),
+      }
+    }
+
+    let listener = UnixListener::bind(path)?;
+    let _socket = SocketFile(path.to_path_buf());
+    remove_on_signal(path)?;
+    let server = Arc::new(server);
+
+    for stream in listener.incoming() {
+      let Ok(stream) = stream else {
+        continue;
+      };
+      let (Ok(writer), Ok(canceller)) =
+        (stream.try_clone(), stream.try_clone())
+      else {
+        continue;
+      };
+
+      let runs = Runs::default();
+      let (tx, rx) = mpsc::channel::<String>();
+
+      // The thread stops once the connection's reader has, which drops the
+      // sender of its lines.
+      {
+        let server = server.clone();
+        let runs = runs.clone();
+        thread::spawn(move || {
+          let mut client = Client::new(server, runs);
+          let notify = notifier(writer);
+          for line in rx {
+            if let Some(response) = respond(&mut client, &line, notify.clone())
+            {
+              notify(response);
+            }
+          }
+        });
+      }
+
+      let server = server.clone();
+      thread::spawn(move || {
+        let notify = notifier(canceller);
+        for line in BufReader::new(stream).lines() {
+          let Ok(line) = line else {
+            break;
+          };
+
+          if line.trim().is_empty() || intercept(&server, &runs, &line, &notify)
+          {
+            continue;
+          }
+
+          if tx.send(line).is_err() {
+            break;
+          }
+        }
+      });
+    }
+
+    Ok(())
+  }
+
+  /// Removes the socket file at its path when it's dropped.
+  struct SocketFile(PathBuf);
+
+  impl Drop for SocketFile {
+    fn drop(&mut self) {
+      let _ = fs::remove_file(&self.0);
+    }
+  }
+
+  /// Removes the socket file at `path` and exits when the server is
+  /// interrupted, since that doesn't unwind to drop the [`SocketFile`].
+  fn remove_on_signal(path: &Path) -> io::Result<()> {
+    let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM])?;
+    let path = path.to_path_buf();
+
+    thread::spawn(move || {
+      if let Some(signal) = signals.forever().next() {
+        let _ = fs::remove_file(&path);
+        process::exit(128 + signal);
+      }
+    });
+
+    Ok(())
+  }
+
+  /// Writes lines to `stream`, each with a single write, since both the
+  /// reading and handling threads write to the connection.
+  fn notifier(stream: UnixStream) -> Notify {
+    Arc::new(move |line: String| {
+      let _ = (&stream).write_all(format!("{line}\n").as_bytes());
+    })
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use std::{env, fs, os::unix::net::UnixStream, path::Path, time::Duration};
+
+  use stack_core::prelude::*;
+
+  use super::*;
+  use crate::server::ServerConfig;
+
+  fn serve(path: &Path) -> io::Result<()> {
+    let server = Server::new(|| ServerConfig {
+      engine: Engine::new(),
+      context: Context::new(),
+      modules: Vec::new(),
+      default_modules: Vec::new(),
+    });
+
+    serve_unix(server, path)
+  }
+
+  #[test]
+  fn doesnt_replace_a_running_server() {
+    let path =
+      env::temp_dir().join(format!("stack-server-{}.sock", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    {
+      let path = path.clone();
+      thread::spawn(move || serve(&path));
+    }
+    for _ in 0..100 {
+      if UnixStream::connect(&path).is_ok() {
+        break;
+      }
+      thread::sleep(Duration::from_millis(10));
+    }
+
+    let err = serve(&path).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+    assert!(UnixStream::connect(&path).is_ok());
+
+    fs::remove_file(path).unwrap();
+  }
+}
@@ -26,6 +26,16 @@ impl<T> Chain<T> {
     }
   }
 
+  /// Returns a chain which isn't linked to any other, holding the same value
+  /// as `value`.
+  pub fn from_root(value: Rc<RefCell<T>>) -> Self {
+    Self {
+      value,
+      child: None,
+      root: true,
+    }
+  }
+
   pub fn link(&mut self) -> Rc<RefCell<Self>> {
     let child = Rc::new(RefCell::new(Self {
       value: self.value.clone(),
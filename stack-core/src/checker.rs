@@ -0,0 +1,865 @@
+//! Checks the stack effects of expressions without running them.
+//!
+//! The checker follows the stack through a program, knowing the
+//! [`StackEffect`] of each [`Intrinsic`] and inferring those of functions,
+//! and reports [`Diagnostic`]s for underflows which are guaranteed to happen
+//! and for `if` bodies which change the size of the stack.
+//!
+//! Functions can be annotated with a comment such as `;; ( a b -- c )`, either
+//! before the function (or its `def`) or as the first line of its body. The
+//! checker verifies annotations against the inferred effect, and uses them for
+//! functions whose effect can't be inferred.
+
+use core::{fmt, str::FromStr};
+use std::collections::HashMap;
+
+use crate::{
+  expr::{Expr, ExprKind},
+  intrinsic::Intrinsic,
+  lexer::{Lexer, Span},
+  parser::{parse, ParseError},
+  source::Source,
+  symbol::Symbol,
+};
+
+/// The number of items which something takes from the stack, and the number
+/// of items which it leaves in their place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StackEffect {
+  pub inputs: usize,
+  pub outputs: usize,
+}
+
+impl StackEffect {
+  #[inline]
+  pub const fn new(inputs: usize, outputs: usize) -> Self {
+    Self { inputs, outputs }
+  }
+
+  /// Returns the change in the size of the stack.
+  #[inline]
+  pub const fn net(self) -> isize {
+    self.outputs as isize - self.inputs as isize
+  }
+
+  /// Parses an annotation such as `( a b -- c )`, where the names before the
+  /// `--` are the inputs, and those after are the outputs.
+  pub fn from_annotation(s: &str) -> Option<Self> {
+    let s = s.trim().strip_prefix('(')?.strip_suffix(')')?;
+    let words = s.split_whitespace().collect::<Vec<_>>();
+    let separator = words.iter().position(|word| *word == "--")?;
+
+    Some(Self::new(separator, words.len() - separator - 1))
+  }
+}
+
+impl fmt::Display for StackEffect {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "({} -- {})", self.inputs, self.outputs)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+  pub kind: DiagnosticKind,
+  pub span: Span,
+}
+
+impl fmt::Display for Diagnostic {
+  #[inline]
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.kind)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticKind {
+  /// A call needs more items than the stack will have.
+  StackUnderflow {
+    name: String,
+    expected: usize,
+    found: usize,
+  },
+  /// The body of an `if` changes the size of the stack, so the size differs
+  /// depending on whether the body runs.
+  MismatchedIf { effect: StackEffect },
+  /// The inferred effect of a function differs to its annotation.
+  MismatchedAnnotation {
+    declared: StackEffect,
+    inferred: StackEffect,
+  },
+  /// An annotation which isn't attached to a function.
+  UnusedAnnotation,
+}
+
+impl fmt::Display for DiagnosticKind {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::StackUnderflow {
+        name,
+        expected,
+        found,
+      } => write!(
+        f,
+        "stack underflow: {name} needs {expected} items, but the stack has {found}"
+      ),
+      Self::MismatchedIf { effect } => write!(
+        f,
+        "mismatched if: the body has the effect {effect}, which changes the size of the stack"
+      ),
+      Self::MismatchedAnnotation { declared, inferred } => write!(
+        f,
+        "mismatched annotation: declared {declared}, but the body has the effect {inferred}"
+      ),
+      Self::UnusedAnnotation => {
+        write!(f, "unused annotation: it isn't followed by a function")
+      }
+    }
+  }
+}
+
+/// Parses and checks the code of a [`Source`], returning the
+/// [`Diagnostic`]s, ordered by where they occur.
+pub fn check(source: Source) -> Result<Vec<Diagnostic>, ParseError> {
+  let mut lexer = Lexer::new(source.clone());
+  let exprs = parse(&mut lexer)?;
+
+  let annotations = lexer
+    .comments()
+    .iter()
+    .filter_map(|span| {
+      let comment = &source.source()[span.start..span.end];
+      let effect =
+        StackEffect::from_annotation(comment.trim_start_matches(';'))?;
+
+      Some((*span, effect))
+    })
+    .collect::<Vec<_>>();
+
+  let mut checker = Checker::default();
+  checker.attach_annotations(&exprs, annotations);
+
+  let mut frame = Frame::new(true);
+  checker.sequence(&exprs, &mut frame);
+
+  let mut diagnostics = checker.diagnostics;
+  diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+  diagnostics.dedup();
+
+  Ok(diagnostics)
+}
+
+/// What happens when something runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+  Effect(StackEffect),
+  /// It never returns, such as after a `throw`.
+  Diverges,
+  /// Its effect isn't known.
+  Unknown,
+}
+
+/// What is known about an item on the stack.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+  Any,
+  /// An item which isn't code, such as an integer.
+  Data,
+  Symbol(Symbol),
+  /// A list, function or s-expression which can be called.
+  Code(Expr),
+}
+
+/// What is known about a variable.
+#[derive(Debug, Clone, PartialEq)]
+enum Definition {
+  /// A variable which is pushed to the stack when used.
+  Value(Value),
+  /// A function which is called when used.
+  Function(Outcome),
+}
+
+/// The stack of a sequence of expressions.
+///
+/// Only the items which the sequence pushes are tracked. Items below them are
+/// the inputs of the sequence, which are counted as they're popped.
+#[derive(Debug, Clone, PartialEq)]
+struct Frame {
+  items: Vec<Value>,
+  inputs: usize,
+  /// Whether the size of the stack below the items is known.
+  is_known: bool,
+  is_diverged: bool,
+  /// Whether the stack starts empty, so popping past the items underflows.
+  is_strict: bool,
+}
+
+impl Frame {
+  fn new(is_strict: bool) -> Self {
+    Self {
+      items: Vec::new(),
+      inputs: 0,
+      is_known: true,
+      is_diverged: false,
+      is_strict,
+    }
+  }
+
+  fn outcome(&self) -> Outcome {
+    if self.is_diverged {
+      Outcome::Diverges
+    } else if self.is_known {
+      Outcome::Effect(StackEffect::new(self.inputs, self.items.len()))
+    } else {
+      Outcome::Unknown
+    }
+  }
+
+  /// Returns whether popping `count` items would underflow a strict stack.
+  #[inline]
+  fn underflows(&self, count: usize) -> bool {
+    self.is_strict && self.is_known && self.items.len() < count
+  }
+
+  fn pop(&mut self) -> Value {
+    match self.items.pop() {
+      Some(value) => value,
+      None => {
+        if self.is_known {
+          self.inputs += 1;
+        }
+
+        Value::Any
+      }
+    }
+  }
+
+  #[inline]
+  fn push(&mut self, value: Value) {
+    self.items.push(value);
+  }
+
+  /// Forgets the size of the stack, such as after calling something unknown.
+  fn forget(&mut self) {
+    self.items.clear();
+    self.is_known = false;
+  }
+
+  /// Applies an effect which might not happen, such as the body of an `if`.
+  fn apply_maybe(&mut self, outcome: Outcome) {
+    match outcome {
+      Outcome::Effect(effect) if self.underflows(effect.inputs) => {
+        self.forget()
+      }
+      Outcome::Effect(effect) => {
+        for _ in 0..effect.inputs {
+          self.pop();
+        }
+        for _ in 0..effect.outputs {
+          self.push(Value::Any);
+        }
+      }
+      Outcome::Diverges => {}
+      Outcome::Unknown => self.forget(),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Checker {
+  definitions: HashMap<Symbol, Definition>,
+  /// The annotations of functions, keyed by the start of their span.
+  annotations: HashMap<usize, StackEffect>,
+  diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+  fn sequence(&mut self, exprs: &[Expr], frame: &mut Frame) {
+    for expr in exprs {
+      if frame.is_diverged {
+        break;
+      }
+
+      self.expr(expr, frame);
+    }
+  }
+
+  fn expr(&mut self, expr: &Expr, frame: &mut Frame) {
+    match expr.kind {
+      ExprKind::Nil
+      | ExprKind::Boolean(_)
+      | ExprKind::Integer(_)
+      | ExprKind::BigInt(_)
+      | ExprKind::Float(_)
+      | ExprKind::Decimal(_)
+      | ExprKind::String(_)
+      | ExprKind::Bytes(_)
+      | ExprKind::Record(_)
+      | ExprKind::Set(_) => frame.push(Value::Data),
+      ExprKind::List(_) => frame.push(Value::Code(expr.clone())),
+      ExprKind::Lazy(ref x) => frame.push(match x.kind {
+        ExprKind::Symbol(x) => Value::Symbol(x),
+        ExprKind::List(_)
+        | ExprKind::Function { .. }
+        | ExprKind::SExpr { .. } => Value::Code((**x).clone()),
+        ExprKind::Lazy(_) => Value::Any,
+        _ => Value::Data,
+      }),
+      ExprKind::Symbol(x) => self.symbol(x, expr, frame),
+      ExprKind::Function { .. } => {
+        let outcome = self.function(expr);
+        self.apply(outcome, "the function", expr, frame);
+      }
+      ExprKind::SExpr { call, ref body } => {
+        let mut args = Vec::with_capacity(body.len());
+        for arg in body {
+          if let ExprKind::Underscore = arg.kind {
+            if !self.require(1, call.as_str(), expr, frame) {
+              return;
+            }
+          } else {
+            self.expr(arg, frame);
+          }
+
+          args.push(frame.pop());
+        }
+
+        if Intrinsic::from_str(call.as_str())
+          .is_ok_and(|x| x.has_flipped_s_expr_args())
+        {
+          args.reverse();
+        }
+        args.into_iter().for_each(|arg| frame.push(arg));
+
+        self.symbol(call, expr, frame);
+      }
+      ExprKind::Underscore => {}
+    }
+  }
+
+  fn symbol(&mut self, symbol: Symbol, expr: &Expr, frame: &mut Frame) {
+    if let Ok(intrinsic) = Intrinsic::from_str(symbol.as_str()) {
+      self.intrinsic(intrinsic, expr, frame);
+    } else {
+      match self.definitions.get(&symbol).cloned() {
+        Some(Definition::Value(value)) => frame.push(value),
+        Some(Definition::Function(outcome)) => {
+          self.apply(outcome, symbol.as_str(), expr, frame)
+        }
+        // Module functions and variables which are defined elsewhere.
+        None => frame.forget(),
+      }
+    }
+  }
+
+  fn intrinsic(
+    &mut self,
+    intrinsic: Intrinsic,
+    expr: &Expr,
+    frame: &mut Frame,
+  ) {
+    let name = intrinsic.as_str();
+
+    match intrinsic {
+      Intrinsic::If => {
+        if !self.require(2, name, expr, frame) {
+          return;
+        }
+
+        let body = frame.pop();
+        frame.pop();
+
+        match self.call_outcome(&body) {
+          Outcome::Effect(effect) if effect.net() != 0 => {
+            self.report(DiagnosticKind::MismatchedIf { effect }, expr);
+            frame.forget();
+          }
+          outcome => frame.apply_maybe(outcome),
+        }
+      }
+      Intrinsic::Call => {
+        if !self.require(1, name, expr, frame) {
+          return;
+        }
+
+        let item = frame.pop();
+        let outcome = self.call_outcome(&item);
+        self.apply(outcome, "the called function", expr, frame);
+      }
+      Intrinsic::Try => {
+        if !self.require(2, name, expr, frame) {
+          return;
+        }
+
+        let handler = frame.pop();
+        let body = frame.pop();
+
+        // The handler runs with the error on top of the stack from before the
+        // body, so both paths must leave the same number of items.
+        match (self.call_outcome(&body), self.call_outcome(&handler)) {
+          (Outcome::Effect(body), Outcome::Effect(handler))
+            if body.net() == handler.net() + 1 =>
+          {
+            frame.apply_maybe(Outcome::Effect(body))
+          }
+          (Outcome::Effect(body), Outcome::Diverges) => {
+            frame.apply_maybe(Outcome::Effect(body))
+          }
+          (Outcome::Diverges, Outcome::Effect(handler)) => {
+            let effect = match handler.inputs {
+              0 => StackEffect::new(0, handler.outputs + 1),
+              inputs => StackEffect::new(inputs - 1, handler.outputs),
+            };
+
+            frame.apply_maybe(Outcome::Effect(effect))
+          }
+          _ => frame.forget(),
+        }
+      }
+      Intrinsic::Catch => {
+        if !self.require(1, name, expr, frame) {
+          return;
+        }
+
+        let body = frame.pop();
+        match self.call_outcome(&body) {
+          Outcome::Effect(effect) if effect.net() == 0 => {
+            frame.apply_maybe(Outcome::Effect(effect))
+          }
+          _ => frame.forget(),
+        }
+
+        frame.push(Value::Any);
+      }
+      Intrinsic::Let => {
+        if !self.require(2, name, expr, frame) {
+          return;
+        }
+
+        let names = frame.pop();
+        let body = frame.pop();
+
+        let names = match names {
+          Value::Code(Expr {
+            kind: ExprKind::List(ref x),
+            ..
+          }) => x
+            .iter()
+            .map(|x| match x.kind {
+              ExprKind::Symbol(x) => Some(x),
+              _ => None,
+            })
+            .collect::<Option<Vec<_>>>(),
+          _ => None,
+        };
+        let Some(names) = names else {
+          frame.forget();
+          return;
+        };
+
+        if !self.require(names.len(), name, expr, frame) {
+          return;
+        }
+
+        let mut previous = Vec::with_capacity(names.len());
+        for name in names.into_iter().rev() {
+          let definition = self.definition(frame.pop());
+          previous.push((name, self.definitions.insert(name, definition)));
+        }
+
+        let outcome = self.call_outcome(&body);
+        self.apply(outcome, name, expr, frame);
+
+        for (name, definition) in previous.into_iter().rev() {
+          match definition {
+            Some(definition) => self.definitions.insert(name, definition),
+            None => self.definitions.remove(&name),
+          };
+        }
+      }
+      Intrinsic::Def | Intrinsic::Set => {
+        if !self.require(2, name, expr, frame) {
+          return;
+        }
+
+        let symbol = frame.pop();
+        let value = frame.pop();
+
+        if let Value::Symbol(symbol) = symbol {
+          let definition = self.definition(value);
+          self.definitions.insert(symbol, definition);
+        }
+      }
+      Intrinsic::Halt => frame.is_diverged = true,
+      Intrinsic::Throw => {
+        if self.require(1, name, expr, frame) {
+          frame.is_diverged = true;
+        }
+      }
+      // A recur is consumed by the function it's in, which loops.
+      Intrinsic::Recur => {}
+      Intrinsic::Import => {
+        if self.require(1, name, expr, frame) {
+          frame.forget();
+        }
+      }
+      intrinsic => {
+        let Some(effect) = intrinsic.stack_effect() else {
+          frame.forget();
+          return;
+        };
+
+        if !self.require(effect.inputs, name, expr, frame) {
+          return;
+        }
+
+        let mut inputs =
+          (0..effect.inputs).map(|_| frame.pop()).collect::<Vec<_>>();
+        inputs.reverse();
+
+        match intrinsic {
+          Intrinsic::Dupe => {
+            frame.push(inputs[0].clone());
+            frame.push(inputs.remove(0));
+          }
+          Intrinsic::Swap => {
+            frame.push(inputs.remove(1));
+            frame.push(inputs.remove(0));
+          }
+          Intrinsic::Rot => {
+            let first = inputs.remove(0);
+            inputs.into_iter().for_each(|x| frame.push(x));
+            frame.push(first);
+          }
+          Intrinsic::Nth
+          | Intrinsic::Pop
+          | Intrinsic::Prop
+          | Intrinsic::Get
+          | Intrinsic::OrElse
+          | Intrinsic::Fold
+          | Intrinsic::Reduce
+          | Intrinsic::Find
+          | Intrinsic::Lazy
+          | Intrinsic::Curry
+          | Intrinsic::Partial
+          | Intrinsic::Capture => {
+            (0..effect.outputs).for_each(|_| frame.push(Value::Any))
+          }
+          _ => (0..effect.outputs).for_each(|_| frame.push(Value::Data)),
+        }
+      }
+    }
+  }
+
+  /// Returns whether the stack has `count` items for `name`, reporting an
+  /// underflow if it doesn't.
+  fn require(
+    &mut self,
+    count: usize,
+    name: &str,
+    expr: &Expr,
+    frame: &mut Frame,
+  ) -> bool {
+    if !frame.underflows(count) {
+      return true;
+    }
+
+    self.report(
+      DiagnosticKind::StackUnderflow {
+        name: name.into(),
+        expected: count,
+        found: frame.items.len(),
+      },
+      expr,
+    );
+    frame.forget();
+
+    false
+  }
+
+  /// Applies an effect which is certain to happen.
+  fn apply(
+    &mut self,
+    outcome: Outcome,
+    name: &str,
+    expr: &Expr,
+    frame: &mut Frame,
+  ) {
+    match outcome {
+      Outcome::Effect(effect) => {
+        if self.require(effect.inputs, name, expr, frame) {
+          frame.apply_maybe(outcome);
+        }
+      }
+      Outcome::Diverges => frame.is_diverged = true,
+      Outcome::Unknown => frame.forget(),
+    }
+  }
+
+  /// Returns the outcome of calling an item, such as with `call`.
+  fn call_outcome(&mut self, value: &Value) -> Outcome {
+    match value {
+      Value::Code(Expr {
+        kind: ExprKind::List(ref x),
+        ..
+      }) => self.block(x),
+      Value::Code(expr) => self.block(core::slice::from_ref(expr)),
+      Value::Symbol(symbol) => {
+        let expr = Expr {
+          kind: ExprKind::Symbol(*symbol),
+          info: None,
+        };
+        self.block(&[expr])
+      }
+      Value::Any | Value::Data => Outcome::Unknown,
+    }
+  }
+
+  fn block(&mut self, exprs: &[Expr]) -> Outcome {
+    let mut frame = Frame::new(false);
+    self.sequence(exprs, &mut frame);
+
+    frame.outcome()
+  }
+
+  /// Returns the outcome of calling a function, verifying its annotation.
+  fn function(&mut self, expr: &Expr) -> Outcome {
+    let ExprKind::Function { ref body, .. } = expr.kind else {
+      return Outcome::Unknown;
+    };
+    let outcome = self.block(body);
+
+    let declared = expr
+      .info
+      .as_ref()
+      .and_then(|info| self.annotations.get(&info.span.start))
+      .copied();
+
+    match (declared, outcome) {
+      (Some(declared), Outcome::Effect(inferred)) => {
+        if declared.net() != inferred.net() || declared.inputs < inferred.inputs
+        {
+          self.report(
+            DiagnosticKind::MismatchedAnnotation { declared, inferred },
+            expr,
+          );
+        }
+
+        Outcome::Effect(declared)
+      }
+      (Some(declared), Outcome::Unknown) => Outcome::Effect(declared),
+      (_, outcome) => outcome,
+    }
+  }
+
+  /// Returns the definition of a variable which is set to `value`.
+  fn definition(&mut self, value: Value) -> Definition {
+    match value {
+      Value::Code(
+        ref expr @ Expr {
+          kind: ExprKind::Function { .. },
+          ..
+        },
+      ) => Definition::Function(self.function(expr)),
+      Value::Code(
+        ref expr @ Expr {
+          kind: ExprKind::SExpr { .. },
+          ..
+        },
+      ) => Definition::Function(self.block(core::slice::from_ref(expr))),
+      value => Definition::Value(value),
+    }
+  }
+
+  fn report(&mut self, kind: DiagnosticKind, expr: &Expr) {
+    let span = expr
+      .info
+      .as_ref()
+      .map(|info| info.span)
+      .unwrap_or(Span { start: 0, end: 0 });
+
+    self.diagnostics.push(Diagnostic { kind, span });
+  }
+
+  /// Attaches each annotation to the function which follows it, or to the
+  /// function whose body it starts.
+  fn attach_annotations(
+    &mut self,
+    exprs: &[Expr],
+    annotations: Vec<(Span, StackEffect)>,
+  ) {
+    let mut starts = Vec::new();
+    visit(exprs, &mut starts);
+
+    for (span, effect) in annotations {
+      let next = starts
+        .iter()
+        .filter(|(start, _)| *start >= span.end)
+        .min_by_key(|(start, _)| *start)
+        .map(|(_, expr)| *expr);
+
+      let function = next.and_then(|next| {
+        annotated_function(next).or_else(|| {
+          // The annotation is on the first line of a function body.
+          starts.iter().find_map(|(_, expr)| match expr.kind {
+            ExprKind::Function { ref body, .. }
+              if body.first().and_then(|x| x.info.as_ref())
+                == next.info.as_ref() =>
+            {
+              Some(*expr)
+            }
+            _ => None,
+          })
+        })
+      });
+
+      match function.and_then(|function| function.info.as_ref()) {
+        Some(info) => {
+          self.annotations.insert(info.span.start, effect);
+        }
+        None => self.diagnostics.push(Diagnostic {
+          kind: DiagnosticKind::UnusedAnnotation,
+          span,
+        }),
+      }
+    }
+  }
+}
+
+/// Collects every expression along with the start of its span.
+fn visit<'a>(exprs: &'a [Expr], starts: &mut Vec<(usize, &'a Expr)>) {
+  for expr in exprs {
+    if let Some(ref info) = expr.info {
+      starts.push((info.span.start, expr));
+    }
+
+    match expr.kind {
+      ExprKind::Lazy(ref x) => visit(core::slice::from_ref(x), starts),
+      ExprKind::List(ref x)
+      | ExprKind::Function { body: ref x, .. }
+      | ExprKind::SExpr { body: ref x, .. } => visit(x, starts),
+      ExprKind::Record(ref x) => x
+        .values()
+        .for_each(|x| visit(core::slice::from_ref(x), starts)),
+      _ => {}
+    }
+  }
+}
+
+/// Returns the function which an annotation before `expr` describes, either
+/// `expr` itself or the value of a `(def 'name value)`.
+fn annotated_function(expr: &Expr) -> Option<&Expr> {
+  match expr.kind.unlazy() {
+    ExprKind::Function { .. } => match expr.kind {
+      ExprKind::Lazy(ref x) => Some(x),
+      _ => Some(expr),
+    },
+    ExprKind::SExpr { call, ref body }
+      if matches!(call.as_str(), "def" | "set") =>
+    {
+      body.iter().find_map(|x| match x.kind {
+        ExprKind::Lazy(ref x) if x.kind.is_function() => Some(&**x),
+        _ => None,
+      })
+    }
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use test_case::case;
+
+  fn check_kinds(source: &str) -> Vec<DiagnosticKind> {
+    check(Source::new("", source))
+      .unwrap()
+      .into_iter()
+      .map(|diagnostic| diagnostic.kind)
+      .collect()
+  }
+
+  fn underflow(name: &str, expected: usize, found: usize) -> DiagnosticKind {
+    DiagnosticKind::StackUnderflow {
+      name: name.into(),
+      expected,
+      found,
+    }
+  }
+
+  #[case("1 2 swap rot" => vec![underflow("rot", 3, 2)] ; "intrinsic")]
+  #[case("1 2 3 rot + + 'a def a drop" => Vec::<DiagnosticKind>::new() ; "valid")]
+  #[case("'(fn swap +) 'f def 1 f" => vec![underflow("f", 2, 1)] ; "defined function")]
+  #[case("'(fn! 0 'i def (fn! i 1 + 'i set i 5 < '[recur] if)) call" => Vec::<DiagnosticKind>::new() ; "recur")]
+  #[case("(+ _ 1)" => vec![underflow("+", 1, 0)] ; "s-expr underscore")]
+  #[case("true '[1] if" => vec![DiagnosticKind::MismatchedIf { effect: StackEffect::new(0, 1) }] ; "mismatched if")]
+  #[case("1 true '[drop 2] if drop" => Vec::<DiagnosticKind>::new() ; "matched if")]
+  #[case("'[\"oops\" throw] '[drop] try" => Vec::<DiagnosticKind>::new() ; "try catches throw")]
+  #[case("'[\"oops\" throw] '[] try drop drop" => vec![underflow("drop", 1, 0)] ; "try handler")]
+  #[case("1 2 '[a b +] '[a b] let drop" => Vec::<DiagnosticKind>::new() ; "let binds names")]
+  #[case("str:len drop drop" => Vec::<DiagnosticKind>::new() ; "unknown call")]
+  #[case("\"oops\" throw drop" => Vec::<DiagnosticKind>::new() ; "unreachable")]
+  fn checks(source: &str) -> Vec<DiagnosticKind> {
+    check_kinds(source)
+  }
+
+  #[test]
+  fn reports_spans() {
+    let diagnostics = check(Source::new("", "1 '(fn 1 +) call\nswap")).unwrap();
+
+    assert_eq!(
+      diagnostics,
+      vec![Diagnostic {
+        kind: underflow("swap", 2, 1),
+        span: Span { start: 17, end: 21 },
+      }]
+    );
+  }
+
+  #[test]
+  fn reports_called_functions() {
+    let diagnostics = check(Source::new("", "1 '(fn drop drop) call")).unwrap();
+
+    assert_eq!(
+      diagnostics,
+      vec![Diagnostic {
+        kind: underflow("the called function", 2, 1),
+        span: Span { start: 18, end: 22 },
+      }]
+    );
+    assert_eq!(
+      diagnostics[0].to_string(),
+      "stack underflow: the called function needs 2 items, but the stack has 1"
+    );
+  }
+
+  #[test]
+  fn checks_annotations() {
+    assert_eq!(
+      check_kinds(
+        ";; ( a b -- c )
+        '(fn +) 'add def
+        '(fn ;; ( a -- b c )
+          dupe
+        ) 'dupe2 def
+        (def 'bad '(fn ;; ( a -- )
+          1 +
+        ))
+        1 2 add dupe2 bad"
+      ),
+      vec![DiagnosticKind::MismatchedAnnotation {
+        declared: StackEffect::new(1, 0),
+        inferred: StackEffect::new(1, 1),
+      }]
+    );
+
+    assert_eq!(
+      check_kinds(";; ( a -- )\n'(fn str:trim) 'f def f"),
+      vec![underflow("f", 1, 0)]
+    );
+    assert_eq!(
+      check_kinds("1 ;; ( a -- b )"),
+      vec![DiagnosticKind::UnusedAnnotation]
+    );
+  }
+}
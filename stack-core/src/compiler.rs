@@ -0,0 +1,390 @@
+//! Lowers [`Expr`]s into [`Program`]s, which are run by the [`Vm`].
+//!
+//! [`Vm`]: crate::vm::Vm
+
+use core::{
+  fmt,
+  hash::{Hash, Hasher},
+  str::FromStr,
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+  engine::Engine,
+  expr::{Expr, ExprInfo, ExprKind},
+  intrinsic::Intrinsic,
+  lexer::Span,
+  module::Func,
+  source::Source,
+  symbol::Symbol,
+};
+
+/// A compiled list of [`Instr`]s, along with the constants they refer to.
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+  instrs: Vec<Instr>,
+  consts: Vec<Expr>,
+}
+
+impl Program {
+  /// Returns the [`Instr`]s of this [`Program`].
+  #[inline]
+  pub fn instrs(&self) -> &[Instr] {
+    &self.instrs
+  }
+
+  /// Returns the constant pool of this [`Program`].
+  #[inline]
+  pub fn consts(&self) -> &[Expr] {
+    &self.consts
+  }
+
+  /// Returns the constant at `index`.
+  #[inline]
+  pub fn constant(&self, index: usize) -> &Expr {
+    &self.consts[index]
+  }
+}
+
+/// A single instruction of a [`Program`].
+///
+/// Each `usize` is an index into the constant pool of the [`Program`].
+#[derive(Clone)]
+pub enum Instr {
+  /// Pushes a constant onto the stack, unwrapping it if it's lazy.
+  Push(usize),
+  /// Runs a pre-resolved [`Intrinsic`].
+  Intrinsic(Intrinsic, usize),
+  /// Runs a pre-resolved function from a [`Module`].
+  ///
+  /// [`Module`]: crate::module::Module
+  Module(Func, usize),
+  /// Resolves a symbol from the scope and calls or pushes it.
+  Symbol(usize),
+  /// Scans and calls a function.
+  ///
+  /// The body is pre-compiled when scanning the function can't change it.
+  Function(usize, Option<Rc<Program>>),
+  /// Starts collecting the arguments of an s-expression.
+  SExprStart(usize),
+  /// Marks the stack length before an argument is run.
+  Mark,
+  /// Pops an argument from the stack.
+  Arg(usize),
+  /// Pops an argument from the stack, which must be the only item pushed
+  /// since the last [`Instr::Mark`].
+  MarkedArg(usize),
+  /// Pushes the collected arguments of an s-expression back onto the stack.
+  SExprEnd { flipped: bool },
+}
+
+impl fmt::Debug for Instr {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Push(x) => f.debug_tuple("Push").field(x).finish(),
+      Self::Intrinsic(intrinsic, x) => f
+        .debug_tuple("Intrinsic")
+        .field(intrinsic)
+        .field(x)
+        .finish(),
+      Self::Module(_, x) => f.debug_tuple("Module").field(x).finish(),
+      Self::Symbol(x) => f.debug_tuple("Symbol").field(x).finish(),
+      Self::Function(x, body) => f
+        .debug_tuple("Function")
+        .field(x)
+        .field(&body.is_some())
+        .finish(),
+      Self::SExprStart(x) => f.debug_tuple("SExprStart").field(x).finish(),
+      Self::Mark => write!(f, "Mark"),
+      Self::Arg(x) => f.debug_tuple("Arg").field(x).finish(),
+      Self::MarkedArg(x) => f.debug_tuple("MarkedArg").field(x).finish(),
+      Self::SExprEnd { flipped } => f
+        .debug_struct("SExprEnd")
+        .field("flipped", flipped)
+        .finish(),
+    }
+  }
+}
+
+/// Compiles `exprs` into a [`Program`].
+///
+/// Symbols which name an [`Intrinsic`] or a function from one of the modules
+/// of the [`Engine`] are resolved ahead of time.
+pub fn compile(engine: &Engine, exprs: Vec<Expr>) -> Program {
+  let mut compiler = Compiler {
+    engine,
+    program: Program::default(),
+  };
+
+  for expr in exprs {
+    compiler.compile_expr(expr);
+  }
+
+  compiler.program
+}
+
+/// Returns whether scanning a function with this body leaves the body as-is.
+///
+/// Scanning only changes the functions directly within a body, so a compiled
+/// body can be reused between calls when there are none.
+pub(crate) fn is_scan_invariant(body: &[Expr]) -> bool {
+  !body.iter().any(|expr| expr.kind.unlazy().is_function())
+}
+
+struct Compiler<'e> {
+  engine: &'e Engine,
+  program: Program,
+}
+
+impl<'e> Compiler<'e> {
+  fn constant(&mut self, expr: Expr) -> usize {
+    self.program.consts.push(expr);
+    self.program.consts.len() - 1
+  }
+
+  fn emit(&mut self, instr: Instr) {
+    self.program.instrs.push(instr);
+  }
+
+  fn compile_expr(&mut self, expr: Expr) {
+    match expr.kind {
+      ExprKind::SExpr { call, ref body } => {
+        let body = body.clone();
+        let info = expr.info.clone();
+        let flipped = Intrinsic::from_str(call.as_str())
+          .map(|intrinsic| intrinsic.has_flipped_s_expr_args())
+          .unwrap_or(false);
+
+        let index = self.constant(expr);
+        self.emit(Instr::SExprStart(index));
+
+        for arg in body {
+          match arg.kind {
+            ExprKind::Underscore => {
+              let index = self.constant(arg);
+              self.emit(Instr::Arg(index));
+            }
+            ExprKind::SExpr { .. } => {
+              self.compile_expr(arg.clone());
+              let index = self.constant(arg);
+              self.emit(Instr::Arg(index));
+            }
+            _ => {
+              self.emit(Instr::Mark);
+              self.compile_expr(arg.clone());
+              let index = self.constant(arg);
+              self.emit(Instr::MarkedArg(index));
+            }
+          }
+        }
+
+        self.emit(Instr::SExprEnd { flipped });
+        self.compile_symbol(Expr {
+          kind: ExprKind::Symbol(call),
+          info,
+        });
+      }
+      ExprKind::Symbol(_) => self.compile_symbol(expr),
+      ExprKind::Function { ref body, .. } => {
+        let body = is_scan_invariant(body).then(|| {
+          let key =
+            ProgramKey::new(&expr.info, ProgramKind::FnBody, body.len());
+          self
+            .engine
+            .programs()
+            .get_or_compile(key, || compile(self.engine, body.clone()))
+        });
+
+        let index = self.constant(expr);
+        self.emit(Instr::Function(index, body));
+      }
+      ExprKind::Underscore => {}
+      _ => {
+        let index = self.constant(expr);
+        self.emit(Instr::Push(index));
+      }
+    }
+  }
+
+  fn compile_symbol(&mut self, expr: Expr) {
+    let ExprKind::Symbol(symbol) = expr.kind else {
+      unreachable!()
+    };
+
+    let instr = if let Ok(intrinsic) = Intrinsic::from_str(symbol.as_str()) {
+      Instr::Intrinsic(intrinsic, self.constant(expr))
+    } else if let Some(func) =
+      symbol
+        .as_str()
+        .split_once(':')
+        .and_then(|(namespace, func)| {
+          self
+            .engine
+            .module(&Symbol::from_ref(namespace))
+            .and_then(|module| module.func(Symbol::from_ref(func)))
+        })
+    {
+      Instr::Module(func, self.constant(expr))
+    } else {
+      Instr::Symbol(self.constant(expr))
+    };
+
+    self.emit(instr);
+  }
+}
+
+/// The kind of code a cached [`Program`] was compiled from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum ProgramKind {
+  /// The items of a list which was called.
+  Block,
+  /// The body of a function.
+  FnBody,
+}
+
+/// Identifies a [`Program`] by the source code it was compiled from.
+#[derive(Debug, Clone)]
+pub(crate) struct ProgramKey {
+  // This keeps the source alive, so its pointer can't be reused.
+  source: Source,
+  span: Span,
+  len: usize,
+  kind: ProgramKind,
+}
+
+impl ProgramKey {
+  pub(crate) fn new(
+    info: &Option<ExprInfo>,
+    kind: ProgramKind,
+    len: usize,
+  ) -> Option<Self> {
+    info.as_ref().map(|info| Self {
+      source: info.source.clone(),
+      span: info.span,
+      len,
+      kind,
+    })
+  }
+}
+
+impl PartialEq for ProgramKey {
+  fn eq(&self, other: &Self) -> bool {
+    self.source.ptr_eq(&other.source)
+      && self.span == other.span
+      && self.len == other.len
+      && self.kind == other.kind
+  }
+}
+
+impl Eq for ProgramKey {}
+
+impl Hash for ProgramKey {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.source.as_ptr().hash(state);
+    self.span.hash(state);
+    self.len.hash(state);
+    self.kind.hash(state);
+  }
+}
+
+/// A cache of [`Program`]s, shared between clones of an [`Engine`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ProgramCache(Rc<RefCell<HashMap<ProgramKey, Rc<Program>>>>);
+
+impl ProgramCache {
+  /// The amount of [`Program`]s kept before the cache is cleared.
+  const CAPACITY: usize = 4096;
+
+  /// Returns the cached [`Program`] for `key`, or compiles and caches it.
+  ///
+  /// Programs without a key are compiled every time.
+  pub(crate) fn get_or_compile<F>(
+    &self,
+    key: Option<ProgramKey>,
+    compile: F,
+  ) -> Rc<Program>
+  where
+    F: FnOnce() -> Program,
+  {
+    let Some(key) = key else {
+      return Rc::new(compile());
+    };
+
+    if let Some(program) = self.0.borrow().get(&key) {
+      return program.clone();
+    }
+
+    // The borrow must be released first, since compiling may use the cache.
+    let program = Rc::new(compile());
+
+    let mut programs = self.0.borrow_mut();
+    if programs.len() >= Self::CAPACITY {
+      programs.clear();
+    }
+    programs.insert(key, program.clone());
+
+    program
+  }
+
+  #[inline]
+  pub(crate) fn clear(&self) {
+    self.0.borrow_mut().clear();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::prelude::*;
+
+  fn compile_source(source: &str) -> Program {
+    let source = Source::new("", source);
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    compile(&Engine::new(), exprs)
+  }
+
+  #[test]
+  fn resolves_intrinsics() {
+    let program = compile_source("1 2 + a");
+
+    assert!(matches!(
+      program.instrs(),
+      [
+        Instr::Push(0),
+        Instr::Push(1),
+        Instr::Intrinsic(Intrinsic::Add, 2),
+        Instr::Symbol(3)
+      ]
+    ));
+  }
+
+  #[test]
+  fn flattens_s_exprs() {
+    let program = compile_source("(- 1 _)");
+
+    assert!(matches!(
+      program.instrs(),
+      [
+        Instr::SExprStart(0),
+        Instr::Mark,
+        Instr::Push(1),
+        Instr::MarkedArg(2),
+        Instr::Arg(3),
+        Instr::SExprEnd { flipped: false },
+        Instr::Intrinsic(Intrinsic::Sub, 4),
+      ]
+    ));
+  }
+
+  #[test]
+  fn precompiles_function_bodies() {
+    let program = compile_source("(fn 1 2 +) (fn '(fn))");
+
+    assert!(matches!(
+      program.instrs(),
+      [Instr::Function(0, Some(_)), Instr::Function(1, None)]
+    ));
+  }
+}
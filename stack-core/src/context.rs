@@ -1,13 +1,18 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+  cell::RefCell,
+  collections::{HashMap, HashSet},
+  path::{Path, PathBuf},
+  rc::Rc,
+};
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
   chain::Chain,
   engine::{RunError, RunErrorReason},
-  expr::{Expr, ExprKind},
+  expr::{Expr, ExprKind, Key},
   journal::{Journal, JournalOp},
-  scope::{Scanner, Scope},
+  scope::{Detacher, Scanner, Scope},
   source::Source,
   symbol::Symbol,
   vec_one::VecOne,
@@ -20,6 +25,86 @@ pub struct Context {
   scopes: VecOne<Scope>,
   journal: Option<Journal>,
   sources: HashMap<Symbol, Source>,
+  /// The exports of the files which have been loaded with `use`, keyed by
+  /// their canonical path.
+  file_modules: HashMap<PathBuf, Expr>,
+  fuel: Option<u64>,
+  limits: Limits,
+  #[serde(skip)]
+  call_depth: usize,
+  /// The canonical paths of the files currently being imported, from the
+  /// outermost to the innermost.
+  #[serde(skip)]
+  imports: Vec<PathBuf>,
+  /// The approximate size of the stack, when it's known.
+  #[serde(skip)]
+  stack_size: Option<usize>,
+  /// An upper bound on the approximate size of the items in the scopes, when
+  /// it's known. They're measured again once this exceeds the heap size limit,
+  /// since items are freed when their scopes are popped.
+  #[serde(skip)]
+  scope_size: Option<usize>,
+}
+
+/// Limits on the resources a script can use, which are all disabled by
+/// default.
+///
+/// Exceeding a limit fails with an error which can't be caught by scripts.
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize,
+)]
+pub struct Limits {
+  /// The maximum amount of items on the stack.
+  pub stack_len: Option<usize>,
+  /// The maximum amount of items in a list or record, or bytes in a string.
+  pub collection_len: Option<usize>,
+  /// The maximum depth of nested calls.
+  ///
+  /// The tree-walker recurses for each call, so this should be low enough
+  /// that the native stack doesn't overflow first.
+  pub call_depth: Option<usize>,
+  /// The maximum approximate amount of bytes used by the items on the stack
+  /// and in the scopes, such as those stored with `def` and `set`.
+  ///
+  /// The items of collections are counted, but the scopes of functions
+  /// aren't.
+  pub heap_size: Option<usize>,
+}
+
+impl Limits {
+  #[inline]
+  pub const fn new() -> Self {
+    Self {
+      stack_len: None,
+      collection_len: None,
+      call_depth: None,
+      heap_size: None,
+    }
+  }
+
+  #[inline]
+  pub const fn with_stack_len(mut self, stack_len: usize) -> Self {
+    self.stack_len = Some(stack_len);
+    self
+  }
+
+  #[inline]
+  pub const fn with_collection_len(mut self, collection_len: usize) -> Self {
+    self.collection_len = Some(collection_len);
+    self
+  }
+
+  #[inline]
+  pub const fn with_call_depth(mut self, call_depth: usize) -> Self {
+    self.call_depth = Some(call_depth);
+    self
+  }
+
+  #[inline]
+  pub const fn with_heap_size(mut self, heap_size: usize) -> Self {
+    self.heap_size = Some(heap_size);
+    self
+  }
 }
 
 impl Context {
@@ -30,9 +115,35 @@ impl Context {
       scopes: VecOne::new(Scope::new()),
       journal: None,
       sources: HashMap::new(),
+      file_modules: HashMap::new(),
+      fuel: None,
+      limits: Limits::new(),
+      call_depth: 0,
+      imports: Vec::new(),
+      stack_size: None,
+      scope_size: None,
     }
   }
 
+  /// Returns a copy of the context which doesn't share any scope items with
+  /// it, so that running code against the copy can't change the context.
+  pub fn detached(&self) -> Self {
+    let mut detacher = Detacher::default();
+    let mut context = self.clone();
+
+    for scope in context.scopes.iter_mut() {
+      *scope = detacher.scope(scope);
+    }
+    for expr in context.stack.iter_mut() {
+      *expr = detacher.expr(expr);
+    }
+    for expr in context.file_modules.values_mut() {
+      *expr = detacher.expr(expr);
+    }
+
+    context
+  }
+
   #[inline]
   pub fn with_stack_capacity(mut self, capacity: usize) -> Self {
     self.stack = Vec::with_capacity(capacity);
@@ -49,6 +160,89 @@ impl Context {
     self
   }
 
+  /// Limits how much work can be done with this [`Context`].
+  ///
+  /// Each intrinsic call, module call and function entry consumes one unit of
+  /// fuel, and running fails with [`RunErrorReason::OutOfFuel`] once there is
+  /// none left.
+  #[inline]
+  pub fn with_fuel(mut self, fuel: u64) -> Self {
+    self.fuel = Some(fuel);
+    self
+  }
+
+  #[inline]
+  pub fn with_limits(mut self, limits: Limits) -> Self {
+    self.limits = limits;
+    self
+  }
+
+  #[inline]
+  pub fn limits(&self) -> &Limits {
+    &self.limits
+  }
+
+  /// Returns the depth of the calls currently being run.
+  #[inline]
+  pub fn call_depth(&self) -> usize {
+    self.call_depth
+  }
+
+  /// Enters a call, failing if it would exceed [`Limits::call_depth`].
+  pub(crate) fn enter_call(&mut self, expr: &Expr) -> Result<(), RunError> {
+    if self
+      .limits
+      .call_depth
+      .is_some_and(|max| self.call_depth >= max)
+    {
+      return Err(RunError {
+        reason: RunErrorReason::CallDepthLimitExceeded,
+        context: Box::new(self.clone()),
+        expr: Box::new(expr.clone()),
+      });
+    }
+
+    self.call_depth += 1;
+    Ok(())
+  }
+
+  #[inline]
+  pub(crate) fn exit_call(&mut self) {
+    self.call_depth = self.call_depth.saturating_sub(1);
+  }
+
+  #[inline]
+  pub(crate) fn set_call_depth(&mut self, call_depth: usize) {
+    self.call_depth = call_depth;
+  }
+
+  /// Returns the remaining fuel, or [`None`] if fuel isn't being consumed.
+  #[inline]
+  pub fn fuel(&self) -> Option<u64> {
+    self.fuel
+  }
+
+  /// Adds to the remaining fuel.
+  ///
+  /// If fuel wasn't being consumed, this starts consuming it.
+  #[inline]
+  pub fn refuel(&mut self, fuel: u64) {
+    self.fuel = Some(self.fuel.unwrap_or_default().saturating_add(fuel));
+  }
+
+  /// Consumes one unit of fuel, returning whether there was any left.
+  #[inline]
+  pub(crate) fn consume_fuel(&mut self) -> bool {
+    match self.fuel {
+      Some(0) => false,
+      Some(ref mut fuel) => {
+        *fuel -= 1;
+        true
+      }
+      None => true,
+    }
+  }
+
   #[inline]
   pub fn add_source(&mut self, source: Source) {
     self.sources.insert(Symbol::from_ref(source.name()), source);
@@ -71,6 +265,51 @@ impl Context {
     self.sources.remove(name);
   }
 
+  /// Returns the exports of the file at the canonical `path`, if it has been
+  /// loaded.
+  #[inline]
+  pub fn file_module(&self, path: &Path) -> Option<&Expr> {
+    self.file_modules.get(path)
+  }
+
+  #[inline]
+  pub fn add_file_module(&mut self, path: PathBuf, exports: Expr) {
+    self.file_modules.insert(path, exports);
+  }
+
+  /// Returns the canonical paths of the files currently being imported.
+  #[inline]
+  pub fn imports(&self) -> &[PathBuf] {
+    &self.imports
+  }
+
+  /// Starts importing the file at the canonical `path`, failing with
+  /// [`RunErrorReason::ImportCycle`] if it's already being imported.
+  pub(crate) fn enter_import(
+    &mut self,
+    path: PathBuf,
+    expr: &Expr,
+  ) -> Result<(), RunError> {
+    if let Some(i) = self.imports.iter().position(|import| *import == path) {
+      let mut cycle = self.imports[i..].to_vec();
+      cycle.push(path);
+
+      return Err(RunError {
+        reason: RunErrorReason::ImportCycle(cycle),
+        context: Box::new(self.clone()),
+        expr: Box::new(expr.clone()),
+      });
+    }
+
+    self.imports.push(path);
+    Ok(())
+  }
+
+  #[inline]
+  pub(crate) fn exit_import(&mut self) {
+    self.imports.pop();
+  }
+
   #[inline]
   pub fn stack(&self) -> &[Expr] {
     &self.stack
@@ -78,9 +317,17 @@ impl Context {
 
   #[inline]
   pub fn stack_mut(&mut self) -> &mut Vec<Expr> {
+    // The stack could change in any way, so its size must be recalculated.
+    self.stack_size = None;
     &mut self.stack
   }
 
+  /// Swaps two items on the stack.
+  #[inline]
+  pub(crate) fn stack_swap(&mut self, a: usize, b: usize) {
+    self.stack.swap(a, b);
+  }
+
   #[inline]
   pub fn journal(&self) -> &Option<Journal> {
     &self.journal
@@ -100,7 +347,7 @@ impl Context {
         Ok(expr) => Ok(expr),
         Err((expr, reason)) => Err(RunError {
           reason,
-          context: self.clone(),
+          context: Box::new(self.clone()),
           expr,
         }),
       }
@@ -111,6 +358,7 @@ impl Context {
 
   pub fn stack_push(&mut self, expr: Expr) -> Result<(), RunError> {
     let expr = self.scan_expr(expr)?;
+    self.check_limits(&expr)?;
 
     if let Some(journal) = self.journal_mut() {
       journal.push_op(JournalOp::Push(expr.clone()));
@@ -123,6 +371,7 @@ impl Context {
 
   pub fn stack_silent_push(&mut self, expr: Expr) -> Result<(), RunError> {
     let expr = self.scan_expr(expr)?;
+    self.check_limits(&expr)?;
 
     self.stack.push(expr);
 
@@ -132,6 +381,7 @@ impl Context {
   pub fn stack_pop(&mut self, expr: &Expr) -> Result<Expr, RunError> {
     match self.stack.pop() {
       Some(expr) => {
+        self.release(&expr);
         if let Some(journal) = self.journal_mut() {
           journal.push_op(JournalOp::Pop(expr.clone()));
         }
@@ -139,26 +389,168 @@ impl Context {
       }
       None => Err(RunError {
         reason: RunErrorReason::StackUnderflow,
-        context: self.clone(),
-        expr: expr.clone(),
+        context: Box::new(self.clone()),
+        expr: Box::new(expr.clone()),
       }),
     }
   }
 
   pub fn stack_silent_pop(&mut self, expr: &Expr) -> Result<Expr, RunError> {
     match self.stack.pop() {
-      Some(expr) => Ok(expr),
+      Some(expr) => {
+        self.release(&expr);
+        Ok(expr)
+      }
       None => Err(RunError {
         reason: RunErrorReason::StackUnderflow,
-        context: self.clone(),
-        expr: expr.clone(),
+        context: Box::new(self.clone()),
+        expr: Box::new(expr.clone()),
+      }),
+    }
+  }
+
+  /// Checks that pushing `expr` wouldn't exceed any [`Limits`], and accounts
+  /// for its size.
+  fn check_limits(&mut self, expr: &Expr) -> Result<(), RunError> {
+    let collection_len = match expr.kind {
+      ExprKind::String(ref x) => x.len(),
+      ExprKind::List(ref x) => x.len(),
+      ExprKind::Bytes(ref x) => x.len(),
+      ExprKind::Record(ref x) => x.len(),
+      ExprKind::Set(ref x) => x.len(),
+      _ => 0,
+    };
+    let size = self.heap_size_of(expr);
+
+    let reason = if self
+      .limits
+      .stack_len
+      .is_some_and(|max| self.stack.len() >= max)
+    {
+      Some(RunErrorReason::StackLimitExceeded)
+    } else if self
+      .limits
+      .collection_len
+      .is_some_and(|max| collection_len > max)
+    {
+      Some(RunErrorReason::CollectionLimitExceeded)
+    } else if !self.fits_heap_size(size) {
+      Some(RunErrorReason::HeapLimitExceeded)
+    } else {
+      None
+    };
+
+    match reason {
+      Some(reason) => Err(RunError {
+        reason,
+        context: Box::new(self.clone()),
+        expr: Box::new(expr.clone()),
       }),
+      None => {
+        if let Some(ref mut stack_size) = self.stack_size {
+          *stack_size += size;
+        }
+
+        Ok(())
+      }
     }
   }
 
+  /// Checks that storing `expr` in a scope wouldn't exceed the heap size
+  /// limit, and accounts for its size.
+  fn check_scope_limits(&mut self, expr: &Expr) -> Result<(), RunError> {
+    let size = self.heap_size_of(expr);
+
+    if self.fits_heap_size(size) {
+      if let Some(ref mut scope_size) = self.scope_size {
+        *scope_size += size;
+      }
+
+      Ok(())
+    } else {
+      Err(RunError {
+        reason: RunErrorReason::HeapLimitExceeded,
+        context: Box::new(self.clone()),
+        expr: Box::new(expr.clone()),
+      })
+    }
+  }
+
+  /// Returns the approximate size of `expr`, or zero if the heap size isn't
+  /// limited, since measuring it isn't free.
   #[inline]
+  fn heap_size_of(&self, expr: &Expr) -> usize {
+    match self.limits.heap_size {
+      Some(_) => expr.approx_size(),
+      None => 0,
+    }
+  }
+
+  /// Returns whether another `size` bytes fit within the heap size limit.
+  ///
+  /// The scopes are only measured when the upper bound of their size doesn't
+  /// fit, since that means walking every item in them.
+  fn fits_heap_size(&mut self, size: usize) -> bool {
+    let Some(max) = self.limits.heap_size else {
+      return true;
+    };
+
+    let stack_size = *self
+      .stack_size
+      .get_or_insert_with(|| self.stack.iter().map(Expr::approx_size).sum());
+    let fits = |scope_size: usize| stack_size + scope_size + size <= max;
+
+    match self.scope_size {
+      Some(scope_size) if fits(scope_size) => true,
+      _ => {
+        let scope_size = self.measure_scopes();
+        self.scope_size = Some(scope_size);
+
+        fits(scope_size)
+      }
+    }
+  }
+
+  /// Returns the approximate size of the items in the scopes, counting those
+  /// which are shared between scopes once.
+  fn measure_scopes(&self) -> usize {
+    let mut measured = HashSet::new();
+
+    self
+      .scopes
+      .iter()
+      .flat_map(|scope| scope.items.values())
+      .map(|item| item.borrow().root())
+      .filter(|value| measured.insert(Rc::as_ptr(value)))
+      .map(|value| value.borrow().as_ref().map_or(0, Expr::approx_size))
+      .sum()
+  }
+
+  /// Accounts for an item which was popped from the stack.
+  #[inline]
+  fn release(&mut self, expr: &Expr) {
+    if let Some(ref mut stack_size) = self.stack_size {
+      *stack_size = stack_size.saturating_sub(expr.approx_size());
+    }
+  }
+
+  /// Returns the item called `symbol` in the current scope.
+  ///
+  /// If there isn't one and `symbol` is namespaced, such as `math:add`, this
+  /// returns the `add` item of the `math` record instead.
   pub fn scope_item(&self, symbol: Symbol) -> Option<Expr> {
-    self.scopes.last().get_val(symbol)
+    let scope = self.scopes.last();
+
+    scope.get_val(symbol).or_else(|| {
+      let (namespace, name) = symbol.as_str().split_once(':')?;
+
+      match scope.get_val(Symbol::from_ref(namespace))?.kind {
+        ExprKind::Record(record) => {
+          record.get(&Key::Symbol(Symbol::from_ref(name))).cloned()
+        }
+        _ => None,
+      }
+    })
   }
 
   #[inline]
@@ -173,13 +565,24 @@ impl Context {
     self.scopes.last()
   }
 
+  /// Returns the scopes, from the root scope to the current one.
+  #[inline]
+  pub fn scopes(&self) -> impl DoubleEndedIterator<Item = &Scope> {
+    self.scopes.iter()
+  }
+
   #[inline]
   pub fn scope_mut(&mut self) -> &mut Scope {
     self.scopes.last_mut()
   }
 
-  #[inline]
-  pub fn def_scope_item(&mut self, symbol: Symbol, value: Expr) {
+  pub fn def_scope_item(
+    &mut self,
+    symbol: Symbol,
+    value: Expr,
+  ) -> Result<(), RunError> {
+    self.check_scope_limits(&value)?;
+
     let layer = self.scopes.last_mut();
     let val = layer.define(symbol, value);
 
@@ -189,6 +592,8 @@ impl Context {
         val.borrow().val().unwrap_or(ExprKind::Nil.into()),
       ));
     }
+
+    Ok(())
   }
 
   pub fn set_scope_item(
@@ -196,6 +601,8 @@ impl Context {
     symbol: Symbol,
     expr: Expr,
   ) -> Result<(), RunError> {
+    self.check_scope_limits(&expr)?;
+
     let layer = self.scopes.last_mut();
     let old = layer.get_val(symbol);
     match layer.set(symbol, expr.clone()) {
@@ -212,8 +619,8 @@ impl Context {
       }
       Err(reason) => Err(RunError {
         reason,
-        context: self.clone(),
-        expr,
+        context: Box::new(self.clone()),
+        expr: Box::new(expr),
       }),
     }
   }
@@ -233,6 +640,24 @@ impl Context {
     //     ))
     //   }
     // }
+
+    // Items which aren't shared with the current scope, such as those bound
+    // by `let`, are new to the scopes.
+    if let Some(ref mut scope_size) = self.scope_size {
+      let current = self.scopes.last();
+      *scope_size += scope
+        .items
+        .iter()
+        .filter(|(name, item)| {
+          current.get_ref(**name).is_none_or(|current| {
+            !Rc::ptr_eq(&current.borrow().root(), &item.borrow().root())
+          })
+        })
+        .filter_map(|(_, item)| item.borrow().val())
+        .map(|item| item.approx_size())
+        .sum::<usize>();
+    }
+
     self.scopes.push(scope);
   }
 
@@ -240,24 +665,208 @@ impl Context {
   pub fn pop_scope(&mut self) {
     self.scopes.try_pop();
   }
+
+  /// Returns the number of scopes, including the root scope.
+  #[inline]
+  pub fn scope_depth(&self) -> usize {
+    self.scopes.len()
+  }
+
+  /// Pops scopes until there are at most `depth` left.
+  ///
+  /// The root scope is never popped.
+  #[inline]
+  pub fn truncate_scopes(&mut self, depth: usize) {
+    while self.scopes.len() > depth && self.scopes.try_pop().is_some() {}
+  }
 }
 
 #[cfg(test)]
 mod tests {
+  use indexmap::IndexMap;
+
   use super::*;
+  use crate::prelude::*;
+
+  fn run_with_limits(
+    source: &str,
+    limits: Limits,
+  ) -> Result<Vec<Expr>, RunErrorReason> {
+    let source = Source::new("", source);
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    let results = [Backend::TreeWalker, Backend::Vm].map(|backend| {
+      let engine = Engine::new().with_backend(backend);
+      let context = Context::new().with_limits(limits);
+
+      engine
+        .run(context, exprs.clone())
+        .map(|context| context.stack().to_vec())
+        .map_err(|err| err.reason)
+    });
+
+    assert_eq!(results[0], results[1]);
+    results[0].clone()
+  }
+
+  #[test]
+  fn stack_len_is_limited() {
+    let limits = Limits::new().with_stack_len(3);
+
+    assert!(run_with_limits("1 2 3", limits).is_ok());
+    assert_eq!(
+      run_with_limits("1 2 3 4", limits),
+      Err(RunErrorReason::StackLimitExceeded)
+    );
+  }
+
+  #[test]
+  fn collection_len_is_limited() {
+    let limits = Limits::new().with_collection_len(2);
+
+    assert!(run_with_limits("[1 2] \"ab\"", limits).is_ok());
+    assert_eq!(
+      run_with_limits("3 [1 2] push", limits),
+      Err(RunErrorReason::CollectionLimitExceeded)
+    );
+    assert_eq!(
+      run_with_limits("'[\"ab\" \"c\" concat] catch", limits),
+      Err(RunErrorReason::CollectionLimitExceeded)
+    );
+  }
+
+  #[test]
+  fn ranges_are_limited() {
+    let limits = Limits::new().with_collection_len(3);
+
+    assert!(run_with_limits("0 3 range 5 0 range", limits).is_ok());
+    assert_eq!(
+      run_with_limits("0 4 range", limits),
+      Err(RunErrorReason::CollectionLimitExceeded)
+    );
+    assert_eq!(
+      run_with_limits("0 9223372036854775807 range", limits),
+      Err(RunErrorReason::CollectionLimitExceeded)
+    );
+    assert_eq!(
+      run_with_limits(
+        "-9223372036854775807 9223372036854775807 range",
+        Limits::new()
+      ),
+      Err(RunErrorReason::CollectionLimitExceeded)
+    );
+  }
+
+  #[test]
+  fn format_widths_are_limited() {
+    assert_eq!(
+      run_with_limits("1 \"{:10000000000}\" format", Limits::new()),
+      Err(RunErrorReason::CollectionLimitExceeded)
+    );
+    assert_eq!(
+      run_with_limits("1.5 \"{:.70000}\" format", Limits::new()),
+      Err(RunErrorReason::CollectionLimitExceeded)
+    );
+
+    let limits = Limits::new().with_collection_len(4);
+    assert!(run_with_limits("1 \"{:4}\" format", limits).is_ok());
+    assert_eq!(
+      run_with_limits("1 \"{:5}\" format", limits),
+      Err(RunErrorReason::CollectionLimitExceeded)
+    );
+  }
+
+  #[test]
+  fn call_depth_is_limited() {
+    let limits = Limits::new().with_call_depth(16);
+
+    assert!(run_with_limits("'[[[1] call] call] call", limits).is_ok());
+    assert_eq!(
+      run_with_limits("'(fn! f) 'f def '[f] catch", limits),
+      Err(RunErrorReason::CallDepthLimitExceeded)
+    );
+  }
+
+  #[test]
+  fn heap_size_is_limited() {
+    let limits = Limits::new().with_heap_size(1024);
+
+    assert!(run_with_limits(
+      "0 'i def
+      (fn! [1 2 3] drop i 1 + 'i set i 100 < '[recur] if)",
+      limits
+    )
+    .is_ok());
+    assert_eq!(
+      run_with_limits("(fn! [1 2 3] recur)", limits),
+      Err(RunErrorReason::HeapLimitExceeded)
+    );
+  }
+
+  #[test]
+  fn heap_size_counts_scopes() {
+    let list = "[[1 2 3] [4 5 6] [7 8 9]]";
+    let mut lexer = Lexer::new(Source::new("", list));
+    let size = crate::parser::parse(&mut lexer).unwrap()[0].approx_size();
+    let limits = Limits::new().with_heap_size(size * 3 / 2);
+
+    assert!(run_with_limits(&format!("'{list} 'a def"), limits).is_ok());
+    assert_eq!(
+      run_with_limits(&format!("'{list} 'a def '{list} 'b def"), limits),
+      Err(RunErrorReason::HeapLimitExceeded)
+    );
+    assert_eq!(
+      run_with_limits(
+        &format!("'[] 'a def (fn! a {list} push 'a set recur)"),
+        limits
+      ),
+      Err(RunErrorReason::HeapLimitExceeded)
+    );
+
+    // The items of a function's scope are freed when it returns, though the
+    // function holds a copy of the list in its body.
+    let limits = Limits::new().with_heap_size(size * 5 / 2);
+    assert!(run_with_limits(
+      &format!("'(fn '{list} 'a def) 'f def f f f f"),
+      limits
+    )
+    .is_ok());
+  }
 
   #[test]
   fn test_ser_and_de() {
     let mut context = Context::new();
     context.stack_push(ExprKind::Integer(2).into()).unwrap();
-    context.def_scope_item(
-      Symbol::from_ref("foo"),
-      ExprKind::Symbol(Symbol::from_ref("bar")).into(),
-    );
+    context
+      .def_scope_item(
+        Symbol::from_ref("foo"),
+        ExprKind::Symbol(Symbol::from_ref("bar")).into(),
+      )
+      .unwrap();
 
     let json = serde_json::to_string(&context).unwrap();
     let ser_context: Context = serde_json::from_str(json.as_str()).unwrap();
 
     assert_eq!(context, ser_context);
   }
+
+  #[test]
+  fn finds_namespaced_items_in_records() {
+    let mut context = Context::new();
+    let record = ExprKind::Record(IndexMap::from([(
+      Key::Symbol(Symbol::from_ref("add")),
+      ExprKind::Integer(1).into(),
+    )]));
+    context
+      .def_scope_item(Symbol::from_ref("math"), record.into())
+      .unwrap();
+
+    assert_eq!(
+      context.scope_item(Symbol::from_ref("math:add")),
+      Some(ExprKind::Integer(1).into())
+    );
+    assert_eq!(context.scope_item(Symbol::from_ref("math:sub")), None);
+    assert_eq!(context.scope_item(Symbol::from_ref("other:add")), None);
+  }
 }
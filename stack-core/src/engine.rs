@@ -1,27 +1,90 @@
 use core::{fmt, str::FromStr};
 use std::{
   collections::HashMap,
-  sync::Arc,
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
   time::{Duration, Instant},
 };
 
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+  compiler::ProgramCache,
   context::Context,
-  expr::{Expr, ExprKind, FnScope},
+  execution::Execution,
+  expr::{Expr, ExprKind, FnScope, Key},
   intrinsic::Intrinsic,
   journal::JournalOp,
   module::Module,
+  parser::ParseError,
+  source::Source,
   symbol::Symbol,
+  vm::Vm,
 };
 
 #[derive(Clone, Default)]
 pub struct Engine {
   modules: HashMap<Symbol, Module>,
+  search_paths: Vec<PathBuf>,
   start_time: Option<Instant>,
   timeout: Option<Duration>,
+  cancellation_token: Option<CancellationToken>,
   debug_hook: Option<Arc<dyn Fn(String)>>,
+  print_hook: Option<Arc<dyn Fn(String)>>,
+  backend: Backend,
+  arithmetic_mode: ArithmeticMode,
+  programs: ProgramCache,
+}
+
+/// Selects how an [`Engine`] runs expressions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Backend {
+  /// Interprets the expressions directly, via [`Engine::run_expr`].
+  #[default]
+  TreeWalker,
+  /// Compiles the expressions and runs them with a [`Vm`].
+  Vm,
+}
+
+/// Selects what happens when integer arithmetic overflows an [`i64`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ArithmeticMode {
+  /// Wraps the result around at the bounds of an [`i64`].
+  Wrapping,
+  /// Fails with [`RunErrorReason::ArithmeticOverflow`].
+  Checked,
+  /// Promotes the result to an [`ExprKind::BigInt`].
+  #[default]
+  Promote,
+}
+
+/// Cancels the runs of every [`Engine`] which has a clone of it.
+///
+/// Cancelling a run stops it with [`RunErrorReason::Cancelled`] at the next
+/// point where it would check for a [`RunErrorReason::Timeout`]. Tokens can
+/// be cancelled from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+  #[inline]
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  #[inline]
+  pub fn cancel(&self) {
+    self.0.store(true, Ordering::Relaxed);
+  }
+
+  #[inline]
+  pub fn is_cancelled(&self) -> bool {
+    self.0.load(Ordering::Relaxed)
+  }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -36,9 +99,15 @@ impl Engine {
   pub fn new() -> Self {
     Self {
       modules: HashMap::new(),
+      search_paths: Vec::new(),
       start_time: None,
       timeout: None,
+      cancellation_token: None,
       debug_hook: None,
+      print_hook: None,
+      backend: Backend::TreeWalker,
+      arithmetic_mode: ArithmeticMode::Promote,
+      programs: ProgramCache::default(),
     }
   }
 
@@ -51,9 +120,55 @@ impl Engine {
   #[inline]
   pub fn add_module(&mut self, module: Module) -> &mut Self {
     self.modules.insert(module.name(), module);
+    // Compiled programs may have resolved the functions of the old module.
+    self.programs.clear();
+    self
+  }
+
+  /// Adds a directory which `import` and `use` search for files in, after
+  /// the directory of the file which imports them.
+  #[inline]
+  pub fn with_search_path<P>(mut self, path: P) -> Self
+  where
+    P: Into<PathBuf>,
+  {
+    self.add_search_path(path);
+    self
+  }
+
+  #[inline]
+  pub fn add_search_path<P>(&mut self, path: P) -> &mut Self
+  where
+    P: Into<PathBuf>,
+  {
+    self.search_paths.push(path.into());
     self
   }
 
+  #[inline]
+  pub fn search_paths(&self) -> &[PathBuf] {
+    &self.search_paths
+  }
+
+  /// Resolves the `path` of a file imported from `source`.
+  ///
+  /// The path is relative to the directory of `source`, or to each of the
+  /// search paths in order. Returns the first of these files which exists.
+  pub fn resolve_path(
+    &self,
+    source: Option<&Source>,
+    path: &Path,
+  ) -> Option<PathBuf> {
+    let dir = source
+      .and_then(|source| Path::new(source.name()).parent())
+      .unwrap_or(Path::new(""));
+
+    core::iter::once(dir)
+      .chain(self.search_paths.iter().map(PathBuf::as_path))
+      .map(|dir| dir.join(path))
+      .find(|path| path.is_file())
+  }
+
   #[inline]
   pub fn with_debug_hook(
     mut self,
@@ -63,42 +178,117 @@ impl Engine {
     self
   }
 
+  /// Sets a hook which receives the output of `print` and `pretty`, instead
+  /// of it being written to stdout.
+  #[inline]
+  pub fn with_print_hook(
+    mut self,
+    print_hook: Option<Arc<dyn Fn(String)>>,
+  ) -> Self {
+    self.print_hook = print_hook;
+    self
+  }
+
+  /// Sets a token which stops the runs of this engine once it's cancelled.
+  #[inline]
+  pub fn with_cancellation_token(
+    mut self,
+    cancellation_token: Option<CancellationToken>,
+  ) -> Self {
+    self.cancellation_token = cancellation_token;
+    self
+  }
+
+  #[inline]
+  pub fn cancellation_token(&self) -> Option<&CancellationToken> {
+    self.cancellation_token.as_ref()
+  }
+
+  #[inline]
+  pub fn with_backend(mut self, backend: Backend) -> Self {
+    self.backend = backend;
+    self
+  }
+
+  #[inline]
+  pub fn backend(&self) -> Backend {
+    self.backend
+  }
+
+  #[inline]
+  pub fn with_arithmetic_mode(
+    mut self,
+    arithmetic_mode: ArithmeticMode,
+  ) -> Self {
+    self.arithmetic_mode = arithmetic_mode;
+    self
+  }
+
+  #[inline]
+  pub fn arithmetic_mode(&self) -> ArithmeticMode {
+    self.arithmetic_mode
+  }
+
+  #[inline]
+  pub(crate) fn programs(&self) -> &ProgramCache {
+    &self.programs
+  }
+
   #[inline]
   pub fn module(&self, symbol: &Symbol) -> Option<&Module> {
     self.modules.get(symbol)
   }
 
+  /// Returns the [`Module`]s, in no particular order.
+  #[inline]
+  pub fn modules(&self) -> impl Iterator<Item = &Module> {
+    self.modules.values()
+  }
+
   #[inline]
   pub fn debug_hook(&self) -> Option<Arc<dyn Fn(String)>> {
     self.debug_hook.clone()
   }
 
+  #[inline]
+  pub fn print_hook(&self) -> Option<Arc<dyn Fn(String)>> {
+    self.print_hook.clone()
+  }
+
   pub fn run(
     &self,
     mut context: Context,
     exprs: Vec<Expr>,
   ) -> Result<Context, RunError> {
-    for expr in exprs {
-      context = self.run_expr(context, expr)?;
+    match self.backend {
+      Backend::TreeWalker => {
+        for expr in exprs {
+          context = self.run_expr(context, expr)?;
+        }
+
+        Ok(context)
+      }
+      Backend::Vm => Vm::new(context).with_exprs(self, exprs).run(self),
     }
+  }
 
-    Ok(context)
+  /// Starts running `exprs` as an [`Execution`], which can be paused.
+  ///
+  /// This always uses the [`Vm`], regardless of the [`Backend`].
+  pub fn execute(&self, context: Context, exprs: Vec<Expr>) -> Execution {
+    Execution::new(self.clone(), context, exprs)
   }
 
   pub fn run_with_timeout(
     &mut self,
-    mut context: Context,
+    context: Context,
     exprs: Vec<Expr>,
     timeout: Duration,
   ) -> Result<Context, RunError> {
     self.start_time = Some(Instant::now());
     self.timeout = Some(timeout);
 
-    for expr in exprs {
-      context = self.run_expr(context, expr)?;
-    }
-
-    Ok(context)
+    self.run(context, exprs)
   }
 
   pub fn call_expr(
@@ -106,11 +296,22 @@ impl Engine {
     mut context: Context,
     expr: Expr,
   ) -> Result<Context, RunError> {
+    if self.backend == Backend::Vm {
+      let mut vm = Vm::new(context);
+      vm.call(self, expr)?;
+      return vm.run(self);
+    }
+
+    context.enter_call(&expr)?;
+
     let expr = context.scan_expr(expr)?;
-    match expr.kind {
+    let mut context = match expr.kind {
       ExprKind::List(exprs) => self.run(context, exprs),
       _ => self.run_expr(context, expr),
-    }
+    }?;
+
+    context.exit_call();
+    Ok(context)
   }
 
   pub fn run_expr(
@@ -118,14 +319,12 @@ impl Engine {
     mut context: Context,
     expr: Expr,
   ) -> Result<Context, RunError> {
-    if let (Some(start_time), Some(timeout)) = (self.start_time, self.timeout) {
-      if start_time.elapsed() > timeout {
-        return Err(RunError {
-          context,
-          expr,
-          reason: RunErrorReason::Timeout,
-        });
-      }
+    if let Some(reason) = self.interrupt() {
+      return Err(RunError {
+        context: Box::new(context),
+        expr: Box::new(expr),
+        reason,
+      });
     }
 
     let expr = context.scan_expr(expr)?;
@@ -148,8 +347,14 @@ impl Engine {
           _ => {
             context = self.run_expr(context, expr.clone())?;
 
+            // Each argument must push exactly one value, such as a
+            // placeholder of a format string.
             if context.stack().len() != stack_len + 1 {
-              todo!("throw an error when stack is different");
+              return Err(RunError {
+                reason: RunErrorReason::InvalidArgument,
+                context: Box::new(context),
+                expr: Box::new(expr.clone()),
+              });
             }
 
             args.push(context.stack_pop(expr)?);
@@ -181,10 +386,14 @@ impl Engine {
       ExprKind::Nil
       | ExprKind::Boolean(_)
       | ExprKind::Integer(_)
+      | ExprKind::BigInt(_)
       | ExprKind::Float(_)
+      | ExprKind::Decimal(_)
       | ExprKind::String(_)
+      | ExprKind::Bytes(_)
       | ExprKind::List(_)
-      | ExprKind::Record(_) => {
+      | ExprKind::Record(_)
+      | ExprKind::Set(_) => {
         context.stack_push(expr)?;
         Ok(context)
       }
@@ -195,6 +404,7 @@ impl Engine {
         }
 
         if let Ok(intrinsic) = Intrinsic::from_str(x.as_str()) {
+          context = self.consume_fuel(context, &expr)?;
           if let Some(journal) = context.journal_mut() {
             journal.commit();
             journal.push_op(JournalOp::FnCall(expr.clone()));
@@ -205,27 +415,23 @@ impl Engine {
           }
 
           Ok(context)
-        } else if let Some((namespace, func)) = x.as_str().split_once(':') {
-          if let Some(func) = self
-            .modules
-            .get(&Symbol::from_ref(namespace))
-            .and_then(|module| module.func(Symbol::from_ref(func)))
-          {
-            if let Some(journal) = context.journal_mut() {
-              journal.push_op(JournalOp::FnCall(expr.clone()));
-            }
-            context = func(self, context, expr)?;
-            if let Some(journal) = context.journal_mut() {
-              journal.commit();
-            }
-            Ok(context)
-          } else {
-            Err(RunError {
-              context: context.clone(),
-              expr,
-              reason: RunErrorReason::UnknownCall,
-            })
+        } else if let Some(func) =
+          x.as_str().split_once(':').and_then(|(namespace, func)| {
+            self
+              .modules
+              .get(&Symbol::from_ref(namespace))
+              .and_then(|module| module.func(Symbol::from_ref(func)))
+          })
+        {
+          context = self.consume_fuel(context, &expr)?;
+          if let Some(journal) = context.journal_mut() {
+            journal.push_op(JournalOp::FnCall(expr.clone()));
           }
+          context = func(self, context, expr)?;
+          if let Some(journal) = context.journal_mut() {
+            journal.commit();
+          }
+          Ok(context)
         } else if let Some(item) = context.scope_item(x) {
           if let ExprKind::Function { scope, body } = item.kind {
             let mut _call_result = CallResult::None;
@@ -257,8 +463,8 @@ impl Engine {
           }
         } else {
           Err(RunError {
-            context: context.clone(),
-            expr,
+            context: Box::new(context.clone()),
+            expr: Box::new(expr),
             reason: RunErrorReason::UnknownCall,
           })
         }
@@ -289,6 +495,40 @@ impl Engine {
     }
   }
 
+  /// Returns the reason to stop running, if the engine should stop.
+  pub(crate) fn interrupt(&self) -> Option<RunErrorReason> {
+    if let Some(token) = &self.cancellation_token {
+      if token.is_cancelled() {
+        return Some(RunErrorReason::Cancelled);
+      }
+    }
+
+    if let (Some(start_time), Some(timeout)) = (self.start_time, self.timeout) {
+      if start_time.elapsed() > timeout {
+        return Some(RunErrorReason::Timeout);
+      }
+    }
+
+    None
+  }
+
+  /// Consumes one unit of fuel from the [`Context`] before calling `expr`.
+  pub(crate) fn consume_fuel(
+    &self,
+    mut context: Context,
+    expr: &Expr,
+  ) -> Result<Context, RunError> {
+    if context.consume_fuel() {
+      Ok(context)
+    } else {
+      Err(RunError {
+        reason: RunErrorReason::OutOfFuel,
+        context: Box::new(context),
+        expr: Box::new(expr.clone()),
+      })
+    }
+  }
+
   /// Handles auto-calling symbols (calls) when they're pushed to the stack
   /// This is also triggered by the `call` keyword
   pub fn call_fn(
@@ -299,11 +539,20 @@ impl Engine {
     mut context: Context,
     is_recur: bool,
   ) -> CallResult {
+    context = match self.consume_fuel(context, expr) {
+      Ok(context) => context,
+      Err(err) => return CallResult::Once(Err(err)),
+    };
+
     if let Some(journal) = context.journal_mut() {
       journal.push_op(JournalOp::FnCall(expr.clone()));
     }
 
     if !is_recur {
+      if let Err(err) = context.enter_call(expr) {
+        return CallResult::Once(Err(err));
+      }
+
       if let FnScope::Scoped(scope) = fn_scope {
         context.push_scope(scope.clone());
       }
@@ -344,6 +593,7 @@ impl Engine {
         if fn_scope.is_scoped() {
           context.pop_scope();
         }
+        context.exit_call();
 
         CallResult::Once(Ok(context))
       }
@@ -355,12 +605,52 @@ impl Engine {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RunError {
   pub reason: RunErrorReason,
-  pub context: Context,
-  pub expr: Expr,
+  // Boxed, so that every result which can fail with this stays small.
+  pub context: Box<Context>,
+  pub expr: Box<Expr>,
 }
 
 impl std::error::Error for RunError {}
 
+impl RunError {
+  /// Converts this [`RunError`] into a record which can be handled by scripts.
+  ///
+  /// The record contains the `reason`, a human-readable `message`, the `value`
+  /// that caused the error, and the `span` and `location` when available.
+  pub fn to_record(&self) -> Expr {
+    let message = match self.reason {
+      RunErrorReason::Thrown => self.expr.to_string(),
+      _ => self.to_string(),
+    };
+
+    let (span, location) = match self.expr.info {
+      Some(ref info) => (
+        ExprKind::List(vec![
+          ExprKind::Integer(info.span.start as i64).into(),
+          ExprKind::Integer(info.span.end as i64).into(),
+        ]),
+        ExprKind::String(info.to_string().into()),
+      ),
+      None => (ExprKind::Nil, ExprKind::Nil),
+    };
+
+    let mut record = IndexMap::new();
+    record.insert(
+      Key::Symbol(Symbol::from_ref("reason")),
+      ExprKind::String(self.reason.as_str().into()).into(),
+    );
+    record.insert(
+      Key::Symbol(Symbol::from_ref("message")),
+      ExprKind::String(message.into()).into(),
+    );
+    record.insert(Key::Symbol(Symbol::from_ref("value")), (*self.expr).clone());
+    record.insert(Key::Symbol(Symbol::from_ref("span")), span.into());
+    record.insert(Key::Symbol(Symbol::from_ref("location")), location.into());
+
+    ExprKind::Record(record).into()
+  }
+}
+
 impl fmt::Display for RunError {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     write!(f, "{} caused by ", self.reason)?;
@@ -373,20 +663,94 @@ impl fmt::Display for RunError {
   }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum RunErrorReason {
   StackUnderflow,
   DoubleError,
   AssertionFailed,
   Halt,
   InvalidLet,
+  InvalidMatch,
+  NoMatch,
+  InvalidArgument,
+  InvalidImport,
+  ArithmeticOverflow,
   Timeout,
+  Cancelled,
+  OutOfFuel,
+  Thrown,
+
+  // Limit Errors
+  StackLimitExceeded,
+  CollectionLimitExceeded,
+  CallDepthLimitExceeded,
+  HeapLimitExceeded,
 
   // Scope Errors
   UnknownCall,
   InvalidDefinition,
   InvalidFunction,
   CannotSetBeforeDef,
+
+  // Import Errors
+  FileNotFound(PathBuf),
+  ParseError(ParseError),
+  ImportCycle(Vec<PathBuf>),
+}
+
+impl RunErrorReason {
+  /// Returns the kebab-case name of this [`RunErrorReason`].
+  pub const fn as_str(&self) -> &'static str {
+    match self {
+      Self::StackUnderflow => "stack-underflow",
+      Self::DoubleError => "double-error",
+      Self::AssertionFailed => "assertion-failed",
+      Self::Halt => "halt",
+      Self::InvalidLet => "invalid-let",
+      Self::InvalidMatch => "invalid-match",
+      Self::NoMatch => "no-match",
+      Self::InvalidArgument => "invalid-argument",
+      Self::InvalidImport => "invalid-import",
+      Self::ArithmeticOverflow => "arithmetic-overflow",
+      Self::Timeout => "timeout",
+      Self::Cancelled => "cancelled",
+      Self::OutOfFuel => "out-of-fuel",
+      Self::Thrown => "thrown",
+      Self::StackLimitExceeded => "stack-limit-exceeded",
+      Self::CollectionLimitExceeded => "collection-limit-exceeded",
+      Self::CallDepthLimitExceeded => "call-depth-limit-exceeded",
+      Self::HeapLimitExceeded => "heap-limit-exceeded",
+      Self::UnknownCall => "unknown-call",
+      Self::InvalidDefinition => "invalid-definition",
+      Self::InvalidFunction => "invalid-function",
+      Self::CannotSetBeforeDef => "cannot-set-before-def",
+      Self::FileNotFound(_) => "file-not-found",
+      Self::ParseError(_) => "parse-error",
+      Self::ImportCycle(_) => "import-cycle",
+    }
+  }
+
+  /// Returns whether this [`RunErrorReason`] can be handled by `try` and
+  /// `catch`.
+  ///
+  /// Errors which stop a script from the outside, such as [`Self::Halt`],
+  /// [`Self::Timeout`], [`Self::Cancelled`], [`Self::OutOfFuel`] and exceeded
+  /// [`Limits`], can't be caught.
+  ///
+  /// [`Limits`]: crate::context::Limits
+  pub const fn is_catchable(&self) -> bool {
+    !matches!(
+      self,
+      Self::Halt
+        | Self::Timeout
+        | Self::Cancelled
+        | Self::OutOfFuel
+        | Self::StackLimitExceeded
+        | Self::CollectionLimitExceeded
+        | Self::CallDepthLimitExceeded
+        | Self::HeapLimitExceeded
+    )
+  }
 }
 
 impl std::error::Error for RunErrorReason {}
@@ -399,13 +763,43 @@ impl fmt::Display for RunErrorReason {
       Self::AssertionFailed => write!(f, "assertion failed"),
       Self::Halt => write!(f, "halt"),
       Self::InvalidLet => write!(f, "invalid let"),
+      Self::InvalidMatch => write!(f, "invalid match"),
+      Self::NoMatch => write!(f, "no pattern matched"),
+      Self::InvalidArgument => {
+        write!(f, "an argument must push exactly one value")
+      }
+      Self::InvalidImport => write!(f, "invalid import"),
+      Self::ArithmeticOverflow => write!(f, "arithmetic overflow"),
       Self::Timeout => write!(f, "exceeded timeout"),
+      Self::Cancelled => write!(f, "cancelled"),
+      Self::OutOfFuel => write!(f, "ran out of fuel"),
+      Self::Thrown => write!(f, "uncaught throw"),
+      Self::StackLimitExceeded => write!(f, "exceeded the stack length limit"),
+      Self::CollectionLimitExceeded => {
+        write!(f, "exceeded the collection length limit")
+      }
+      Self::CallDepthLimitExceeded => {
+        write!(f, "exceeded the call depth limit")
+      }
+      Self::HeapLimitExceeded => write!(f, "exceeded the heap size limit"),
       Self::UnknownCall => write!(f, "unknown call"),
       Self::InvalidDefinition => write!(f, "invalid definition"),
       Self::InvalidFunction => write!(f, "invalid function"),
       Self::CannotSetBeforeDef => {
         write!(f, "cannot set to a nonexistent variable")
       }
+      Self::FileNotFound(path) => {
+        write!(f, "file not found: {}", path.display())
+      }
+      Self::ParseError(err) => write!(f, "failed to parse: {err}"),
+      Self::ImportCycle(paths) => {
+        write!(f, "import cycle: ")?;
+
+        core::iter::once("")
+          .chain(core::iter::repeat(" -> "))
+          .zip(paths)
+          .try_for_each(|(sep, path)| write!(f, "{sep}{}", path.display()))
+      }
     }
   }
 }
@@ -616,6 +1010,69 @@ mod tests {
     );
   }
 
+  #[test]
+  fn fuel_is_consumed_by_calls() {
+    let source = Source::new("", "1 2 + (fn 3) 'a def");
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+      let engine = Engine::new().with_backend(backend);
+      let context = Context::new().with_fuel(10);
+      let context = engine.run(context, exprs.clone()).unwrap();
+
+      assert_eq!(context.fuel(), Some(7));
+    }
+  }
+
+  #[test]
+  fn running_out_of_fuel_cannot_be_caught() {
+    let source = Source::new(
+      "",
+      "0 'i def
+      '[(fn! i 1 + 'i set i 1000 < '[recur] if)] catch",
+    );
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+      let engine = Engine::new().with_backend(backend);
+      let context = Context::new().with_fuel(100);
+      let err = engine.run(context, exprs.clone()).unwrap_err();
+
+      assert_eq!(err.reason, RunErrorReason::OutOfFuel);
+      assert_eq!(err.context.fuel(), Some(0));
+
+      let mut context = err.context;
+      context.refuel(5);
+      assert_eq!(context.fuel(), Some(5));
+    }
+  }
+
+  #[test]
+  fn cancelled_runs_cannot_be_caught() {
+    let source = Source::new("", "'[(fn! 1 drop recur)] catch");
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+      let token = CancellationToken::new();
+      let engine = Engine::new()
+        .with_backend(backend)
+        .with_cancellation_token(Some(token.clone()));
+
+      let canceller = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        token.cancel();
+      });
+
+      let err = engine.run(Context::new(), exprs.clone()).unwrap_err();
+      canceller.join().unwrap();
+
+      assert_eq!(err.reason, RunErrorReason::Cancelled);
+    }
+  }
+
   #[test]
   fn test_ser_and_de() {
     let source = Source::new("", "0 'a def 2 2 + '(fn)");
@@ -631,4 +1088,145 @@ mod tests {
 
     assert_eq!(context, ser_context);
   }
+
+  #[test]
+  fn decimals_round_trip_through_json() {
+    let source = Source::new("", "1.10d");
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    let json = serde_json::to_string(&exprs[0]).unwrap();
+    let expr: Expr = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(expr.kind, exprs[0].kind);
+    assert_eq!(expr.kind.to_string(), "1.10");
+  }
+
+  #[test]
+  fn bytes_serialize_as_hex() {
+    let expr: Expr = ExprKind::Bytes(vec![0, 104, 255]).into();
+
+    let json = serde_json::to_value(&expr).unwrap();
+    assert_eq!(json["kind"]["Bytes"], "0068ff");
+
+    let de_expr: Expr = serde_json::from_value(json).unwrap();
+    assert_eq!(de_expr, expr);
+  }
+
+  #[test]
+  fn records_and_sets_round_trip_through_json_in_order() {
+    let source = Source::new("", "{b 1 2 \"two\" a 3} #{3 1 2}");
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    let context = Engine::new().run(Context::new(), exprs).unwrap();
+
+    for expr in context.stack() {
+      let json = serde_json::to_string(expr).unwrap();
+      let de_expr: Expr = serde_json::from_str(&json).unwrap();
+
+      assert_eq!(de_expr.to_string(), expr.to_string());
+    }
+
+    assert_eq!(context.stack()[0].to_string(), "{b: 1, 2: two, a: 3}");
+    assert_eq!(context.stack()[1].to_string(), "#{3 1 2}");
+  }
+
+  #[test]
+  fn journals_each_item() {
+    let source = Source::new("", "[1 2 3] '(fn 2 *) map");
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    let engine = Engine::new();
+    let context = Context::new().with_journal(None);
+    let context = engine.run(context, exprs).unwrap();
+
+    // The list is pushed in the first step, then each item gets its own.
+    let items = context
+      .journal()
+      .as_ref()
+      .unwrap()
+      .entries()
+      .iter()
+      .filter_map(|entry| match entry.ops.first() {
+        Some(crate::journal::JournalOp::Push(expr)) => Some(expr.kind.clone()),
+        _ => None,
+      })
+      .skip(1)
+      .collect::<Vec<_>>();
+
+    assert_eq!(
+      items,
+      vec![
+        ExprKind::Integer(1),
+        ExprKind::Integer(2),
+        ExprKind::Integer(3)
+      ]
+    );
+  }
+
+  #[test]
+  fn import_cycles_list_the_chain_of_files() {
+    let source = Source::new(
+      "tests/intrinsics/import.stack",
+      "\"modules/cycle_a.stack\" import",
+    );
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+      let engine = Engine::new().with_backend(backend);
+      let err = engine.run(Context::new(), exprs.clone()).unwrap_err();
+
+      let RunErrorReason::ImportCycle(paths) = err.reason else {
+        panic!("expected an import cycle, found {}", err.reason);
+      };
+      let names = paths
+        .iter()
+        .map(|path| path.file_name().unwrap().to_str().unwrap())
+        .collect::<Vec<_>>();
+
+      assert_eq!(names, ["cycle_a.stack", "cycle_b.stack", "cycle_a.stack"]);
+      assert!(err.context.imports().is_empty());
+    }
+  }
+
+  #[test]
+  fn arithmetic_modes_handle_overflow() {
+    let source =
+      Source::new("", "9223372036854775807 1 + 9223372036854775807 2 *");
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    for backend in [Backend::TreeWalker, Backend::Vm] {
+      let engine = Engine::new()
+        .with_backend(backend)
+        .with_arithmetic_mode(ArithmeticMode::Wrapping);
+      let context = engine.run(Context::new(), exprs.clone()).unwrap();
+      assert_eq!(
+        context
+          .stack()
+          .iter()
+          .map(|expr| &expr.kind)
+          .collect::<Vec<_>>(),
+        vec![&ExprKind::Integer(i64::MIN), &ExprKind::Integer(-2)]
+      );
+
+      let engine = engine.with_arithmetic_mode(ArithmeticMode::Checked);
+      let err = engine.run(Context::new(), exprs.clone()).unwrap_err();
+      assert_eq!(err.reason, RunErrorReason::ArithmeticOverflow);
+
+      let engine = engine.with_arithmetic_mode(ArithmeticMode::Promote);
+      let context = engine.run(Context::new(), exprs.clone()).unwrap();
+      assert_eq!(
+        context
+          .stack()
+          .iter()
+          .map(|expr| expr.kind.to_string())
+          .collect::<Vec<_>>(),
+        vec!["9223372036854775808", "18446744073709551614"]
+      );
+    }
+  }
 }
@@ -0,0 +1,306 @@
+//! A handle to a running program, which can be paused and resumed.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+  context::Context,
+  engine::{Engine, RunError},
+  expr::Expr,
+  vm::Vm,
+};
+
+/// A program which is run in steps, created by [`Engine::execute`].
+///
+/// Unlike [`Engine::run`], running out of fuel pauses the [`Execution`]
+/// instead of failing, so it can be refueled and resumed.
+///
+/// It can only pause between the steps of the [`Vm`]. Intrinsics and module
+/// functions which call back into the [`Engine`], such as `map` and `filter`,
+/// run the functions they call to completion, so running out of fuel inside
+/// of them fails with [`RunErrorReason::OutOfFuel`] instead.
+///
+/// [`RunErrorReason::OutOfFuel`]: crate::engine::RunErrorReason::OutOfFuel
+pub struct Execution {
+  engine: Engine,
+  vm: Vm,
+}
+
+/// The state of an [`Execution`] after it was run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+  /// There is nothing left to run.
+  Finished,
+  /// There is more to run, once resumed.
+  Paused(PauseReason),
+}
+
+/// Why an [`Execution`] was paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PauseReason {
+  /// A single expression was run.
+  Step,
+  /// The next expression needs fuel, but there is none left.
+  ///
+  /// This is only at the top level of the [`Vm`], and not inside of nested
+  /// calls, such as the function passed to `map`.
+  OutOfFuel,
+  /// The deadline was reached.
+  Deadline,
+}
+
+impl Status {
+  #[inline]
+  pub fn is_finished(self) -> bool {
+    self == Self::Finished
+  }
+
+  #[inline]
+  pub fn is_paused(self) -> bool {
+    matches!(self, Self::Paused(_))
+  }
+}
+
+impl Execution {
+  pub(crate) fn new(
+    engine: Engine,
+    context: Context,
+    exprs: Vec<Expr>,
+  ) -> Self {
+    let vm = Vm::new(context).with_exprs(&engine, exprs);
+    Self { engine, vm }
+  }
+
+  /// Returns the [`Context`] as of the last step.
+  #[inline]
+  pub fn context(&self) -> &Context {
+    self.vm.context()
+  }
+
+  /// Returns the next expression to be run, if there is one.
+  ///
+  /// This is the expression as it was parsed, so its [`ExprInfo`] points to
+  /// where it is in the source.
+  ///
+  /// [`ExprInfo`]: crate::expr::ExprInfo
+  #[inline]
+  pub fn next_expr(&self) -> Option<&Expr> {
+    self.vm.next_expr()
+  }
+
+  /// Returns the expressions which called the functions that are running,
+  /// from the outermost to the innermost.
+  #[inline]
+  pub fn call_stack(&self) -> impl DoubleEndedIterator<Item = &Expr> {
+    self.vm.call_stack()
+  }
+
+  /// Returns whether there is nothing left to run.
+  #[inline]
+  pub fn is_finished(&self) -> bool {
+    self.vm.is_finished()
+  }
+
+  /// Adds to the remaining fuel of the [`Context`].
+  #[inline]
+  pub fn refuel(&mut self, fuel: u64) {
+    self.vm.context_mut().refuel(fuel);
+  }
+
+  /// Returns the [`Context`], stopping the [`Execution`].
+  #[inline]
+  pub fn into_context(self) -> Context {
+    self.vm.into_context()
+  }
+
+  /// Runs a single expression.
+  pub fn step(&mut self) -> Result<Status, RunError> {
+    loop {
+      if let Some(status) = self.pause() {
+        return Ok(status);
+      }
+
+      let is_at_expr = self.vm.is_at_expr();
+      self.vm_step()?;
+
+      if is_at_expr {
+        break;
+      }
+    }
+
+    // Leaves any finished frames, so that returning from a function is part
+    // of its last step.
+    while self.vm.is_at_frame_end() {
+      if self.pause().is_some() {
+        break;
+      }
+
+      self.vm_step()?;
+    }
+
+    if self.vm.is_finished() {
+      Ok(Status::Finished)
+    } else {
+      Ok(Status::Paused(PauseReason::Step))
+    }
+  }
+
+  /// Runs until finished or out of fuel.
+  pub fn run(&mut self) -> Result<Status, RunError> {
+    loop {
+      if let Some(status) = self.pause() {
+        return Ok(status);
+      }
+
+      self.vm_step()?;
+    }
+  }
+
+  /// Runs until finished, out of fuel or the `deadline` is reached.
+  ///
+  /// The deadline is checked between expressions, so a call into a module
+  /// or a long-running intrinsic may overrun it.
+  pub fn run_until(&mut self, deadline: Instant) -> Result<Status, RunError> {
+    loop {
+      if Instant::now() >= deadline {
+        return Ok(Status::Paused(PauseReason::Deadline));
+      }
+
+      let status = self.step()?;
+      if status != Status::Paused(PauseReason::Step) {
+        return Ok(status);
+      }
+    }
+  }
+
+  /// Runs until finished, out of fuel or `duration` has passed.
+  #[inline]
+  pub fn run_for(&mut self, duration: Duration) -> Result<Status, RunError> {
+    self.run_until(Instant::now() + duration)
+  }
+
+  /// Returns why the [`Execution`] can't run any further, if it can't.
+  fn pause(&self) -> Option<Status> {
+    if self.vm.is_finished() {
+      Some(Status::Finished)
+    } else if self.vm.context().fuel() == Some(0) && self.vm.needs_fuel() {
+      Some(Status::Paused(PauseReason::OutOfFuel))
+    } else {
+      None
+    }
+  }
+
+  fn vm_step(&mut self) -> Result<(), RunError> {
+    self.vm.step(&self.engine).inspect_err(|err| {
+      // The failed step took the context, so keep the one from the error.
+      self.vm = Vm::new((*err.context).clone());
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{execution::*, prelude::*};
+
+  fn execute(source: &str, context: Context) -> Execution {
+    let source = Source::new("", source);
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    Engine::new().execute(context, exprs)
+  }
+
+  fn stack(context: &Context) -> Vec<&ExprKind> {
+    context.stack().iter().map(|expr| &expr.kind).collect()
+  }
+
+  #[test]
+  fn steps_one_expression_at_a_time() {
+    let mut execution = execute("1 2 + (fn 3)", Context::new());
+
+    let mut spans = Vec::new();
+    while let Some(expr) = execution.next_expr() {
+      let info = expr.info.as_ref().unwrap();
+      spans.push(info.span.start..info.span.end);
+      execution.step().unwrap();
+    }
+
+    assert_eq!(spans, vec![0..1, 2..3, 4..5, 6..12, 10..11]);
+    assert!(execution.is_finished());
+    assert_eq!(
+      stack(execution.context()),
+      vec![&ExprKind::Integer(3), &ExprKind::Integer(3)]
+    );
+  }
+
+  #[test]
+  fn tracks_the_call_stack() {
+    let mut execution =
+      execute("'(fn 1 'a def (fn a)) 'f def f", Context::new());
+
+    let mut depths = Vec::new();
+    while !execution.is_finished() {
+      depths.push(execution.call_stack().count());
+      execution.step().unwrap();
+    }
+
+    assert_eq!(depths, vec![0, 0, 0, 0, 1, 1, 1, 1, 2]);
+  }
+
+  #[test]
+  fn pauses_when_out_of_fuel() {
+    let source = "0 'i def (fn! i 10 < '[i 1 + 'i set recur] if) i";
+    let mut execution = execute(source, Context::new().with_fuel(5));
+
+    let mut pauses = 0;
+    while execution.run().unwrap() == Status::Paused(PauseReason::OutOfFuel) {
+      assert_eq!(execution.context().fuel(), Some(0));
+      execution.refuel(5);
+      pauses += 1;
+    }
+
+    assert!(pauses > 1);
+    assert_eq!(stack(execution.context()), vec![&ExprKind::Integer(10)]);
+  }
+
+  #[test]
+  fn fails_when_out_of_fuel_in_nested_calls() {
+    let mut execution =
+      execute("[1 2 3] '(fn 2 *) map", Context::new().with_fuel(3));
+
+    assert_eq!(
+      execution.run().map_err(|err| err.reason),
+      Err(RunErrorReason::OutOfFuel)
+    );
+    assert!(execution.is_finished());
+  }
+
+  #[test]
+  fn pauses_at_the_deadline() {
+    let mut execution =
+      execute("(fn! 1 drop recur) 1 2 +", Context::new().with_fuel(100));
+
+    assert_eq!(
+      execution.run_until(Instant::now()).unwrap(),
+      Status::Paused(PauseReason::Deadline)
+    );
+    assert_eq!(
+      execution.run_for(Duration::from_secs(10)).unwrap(),
+      Status::Paused(PauseReason::OutOfFuel)
+    );
+  }
+
+  #[test]
+  fn keeps_the_context_of_errors() {
+    let mut execution = execute("1 2 missing", Context::new());
+
+    assert_eq!(
+      execution.run().map_err(|err| err.reason),
+      Err(RunErrorReason::UnknownCall)
+    );
+    assert!(execution.is_finished());
+    assert_eq!(
+      stack(execution.context()),
+      vec![&ExprKind::Integer(1), &ExprKind::Integer(2)]
+    );
+  }
+}
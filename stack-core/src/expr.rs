@@ -1,8 +1,16 @@
-use core::{cmp::Ordering, fmt, hash::Hash, ops};
-use std::collections::HashMap;
+use core::{
+  cmp::Ordering,
+  fmt,
+  hash::{Hash, Hasher},
+  ops,
+};
 
 use compact_str::CompactString;
+use indexmap::{IndexMap, IndexSet};
 use internment::Intern;
+use num_bigint::{BigInt, Sign};
+use num_traits::ToPrimitive;
+use rust_decimal::Decimal;
 use serde::Deserialize;
 use serde::Serialize;
 
@@ -56,6 +64,35 @@ impl Expr {
 
     self.info = None;
   }
+
+  /// Returns the approximate amount of bytes used by this [`Expr`], including
+  /// the items it contains.
+  pub fn approx_size(&self) -> usize {
+    let size = core::mem::size_of::<Self>();
+
+    match self.kind {
+      ExprKind::String(ref x) => size + x.len(),
+      ExprKind::Bytes(ref x) => size + x.len(),
+      ExprKind::BigInt(ref x) => size + x.iter_u64_digits().len() * 8,
+      ExprKind::Lazy(ref x) => size + x.approx_size(),
+      ExprKind::List(ref x) => {
+        size + x.iter().map(Self::approx_size).sum::<usize>()
+      }
+      ExprKind::Record(ref x) => {
+        size
+          + x
+            .values()
+            .map(|x| core::mem::size_of::<Key>() + x.approx_size())
+            .sum::<usize>()
+      }
+      ExprKind::Set(ref x) => size + x.len() * core::mem::size_of::<Key>(),
+      ExprKind::Function { ref body, .. }
+      | ExprKind::SExpr { ref body, .. } => {
+        size + body.iter().map(Self::approx_size).sum::<usize>()
+      }
+      _ => size,
+    }
+  }
 }
 
 impl From<ExprKind> for Expr {
@@ -123,17 +160,33 @@ pub enum ExprKind {
 
   Boolean(bool),
   Integer(i64),
+  /// An integer which doesn't fit in an [`i64`].
+  BigInt(BigInt),
   Float(f64),
+  /// An exact decimal number, such as `1.10d`.
+  Decimal(Decimal),
   String(CompactString),
+  /// Binary data, such as `b"\x00\xff"`.
+  Bytes(#[serde(with = "hex_bytes")] Vec<u8>),
 
   Symbol(Symbol),
 
   Lazy(Box<Expr>),
   List(Vec<Expr>),
-  Record(HashMap<Symbol, Expr>),
-
-  Function { scope: FnScope, body: Vec<Expr> },
-  SExpr { call: Symbol, body: Vec<Expr> },
+  /// A map of keys to values, which keeps the order they were inserted in.
+  Record(#[serde(with = "indexmap::map::serde_seq")] IndexMap<Key, Expr>),
+  /// A collection of unique keys, such as `#{1 2 3}`, which keeps the order
+  /// they were inserted in.
+  Set(IndexSet<Key>),
+
+  Function {
+    scope: FnScope,
+    body: Vec<Expr>,
+  },
+  SExpr {
+    call: Symbol,
+    body: Vec<Expr>,
+  },
   Underscore,
 }
 
@@ -165,6 +218,15 @@ impl ExprKind {
     }
   }
 
+  /// Creates an [`ExprKind::Integer`] if `x` fits in an [`i64`], otherwise an
+  /// [`ExprKind::BigInt`].
+  pub fn from_bigint(x: BigInt) -> Self {
+    match i64::try_from(&x) {
+      Ok(x) => Self::Integer(x),
+      Err(_) => Self::BigInt(x),
+    }
+  }
+
   pub const fn unlazy(&self) -> &ExprKind {
     match self {
       ExprKind::Lazy(x) => x.kind.unlazy(),
@@ -185,14 +247,18 @@ impl ExprKind {
 
       ExprKind::Boolean(_) => "boolean",
       ExprKind::Integer(_) => "integer",
+      ExprKind::BigInt(_) => "bigint",
       ExprKind::Float(_) => "float",
+      ExprKind::Decimal(_) => "decimal",
       ExprKind::String(_) => "string",
+      ExprKind::Bytes(_) => "bytes",
 
       ExprKind::Symbol(_) => "symbol",
 
       ExprKind::Lazy(_) => "lazy",
       ExprKind::List(_) => "list",
       ExprKind::Record(_) => "record",
+      ExprKind::Set(_) => "set",
 
       ExprKind::Function { .. } => "function",
       ExprKind::SExpr { .. } => "s-expression",
@@ -208,14 +274,22 @@ impl PartialEq for ExprKind {
 
       (Self::Boolean(lhs), Self::Boolean(rhs)) => lhs == rhs,
       (Self::Integer(lhs), Self::Integer(rhs)) => lhs == rhs,
+      (Self::BigInt(lhs), Self::BigInt(rhs)) => lhs == rhs,
+      (Self::Integer(lhs), Self::BigInt(rhs)) => BigInt::from(*lhs) == *rhs,
+      (Self::BigInt(lhs), Self::Integer(rhs)) => *lhs == BigInt::from(*rhs),
       (Self::Float(lhs), Self::Float(rhs)) => lhs == rhs,
+      (Self::Decimal(lhs), Self::Decimal(rhs)) => lhs == rhs,
+      (Self::Integer(lhs), Self::Decimal(rhs)) => Decimal::from(*lhs) == *rhs,
+      (Self::Decimal(lhs), Self::Integer(rhs)) => *lhs == Decimal::from(*rhs),
       (Self::String(lhs), Self::String(rhs)) => lhs == rhs,
+      (Self::Bytes(lhs), Self::Bytes(rhs)) => lhs == rhs,
 
       (Self::Symbol(lhs), Self::Symbol(rhs)) => lhs == rhs,
 
       (Self::Lazy(lhs), Self::Lazy(rhs)) => lhs == rhs,
       (Self::List(lhs), Self::List(rhs)) => lhs == rhs,
       (Self::Record(lhs), Self::Record(rhs)) => lhs == rhs,
+      (Self::Set(lhs), Self::Set(rhs)) => lhs == rhs,
 
       (
         Self::Function {
@@ -255,10 +329,27 @@ impl PartialOrd for ExprKind {
         lhs.eq(rhs).then_some(Ordering::Equal)
       }
       (Self::Integer(lhs), Self::Integer(rhs)) => lhs.partial_cmp(rhs),
+      (Self::BigInt(lhs), Self::BigInt(rhs)) => lhs.partial_cmp(rhs),
+      (Self::Integer(lhs), Self::BigInt(rhs)) => {
+        BigInt::from(*lhs).partial_cmp(rhs)
+      }
+      (Self::BigInt(lhs), Self::Integer(rhs)) => {
+        lhs.partial_cmp(&BigInt::from(*rhs))
+      }
       (Self::Float(lhs), Self::Float(rhs)) => lhs.partial_cmp(rhs),
+      (Self::Decimal(lhs), Self::Decimal(rhs)) => lhs.partial_cmp(rhs),
+      (Self::Integer(lhs), Self::Decimal(rhs)) => {
+        Decimal::from(*lhs).partial_cmp(rhs)
+      }
+      (Self::Decimal(lhs), Self::Integer(rhs)) => {
+        lhs.partial_cmp(&Decimal::from(*rhs))
+      }
       (Self::String(lhs), Self::String(rhs)) => {
         lhs.eq(rhs).then_some(Ordering::Equal)
       }
+      (Self::Bytes(lhs), Self::Bytes(rhs)) => {
+        lhs.eq(rhs).then_some(Ordering::Equal)
+      }
 
       (Self::Symbol(lhs), Self::Symbol(rhs)) => {
         lhs.eq(rhs).then_some(Ordering::Equal)
@@ -274,17 +365,83 @@ impl PartialOrd for ExprKind {
   }
 }
 
+/// Applies an operator to a pair of numbers of different kinds, which are
+/// either decimals or bigints with integers, or bigints with floats. Bigints
+/// are converted into floats in the same way as when comparing them.
+///
+/// Like the operators, both operands are returned if they can't be used
+/// together, or as decimals if the decimal operation overflows.
+#[allow(clippy::result_large_err)]
+fn mixed_op(
+  lhs: ExprKind,
+  rhs: ExprKind,
+  decimal: fn(Decimal, Decimal) -> Option<Decimal>,
+  bigint: fn(BigInt, BigInt) -> BigInt,
+  float: fn(f64, f64) -> f64,
+) -> Result<ExprKind, (ExprKind, ExprKind)> {
+  let decimal = |lhs: Decimal, rhs: Decimal| {
+    decimal(lhs, rhs)
+      .map(ExprKind::Decimal)
+      .ok_or((ExprKind::Decimal(lhs), ExprKind::Decimal(rhs)))
+  };
+  let bigint = |lhs, rhs| Ok(ExprKind::from_bigint(bigint(lhs, rhs)));
+  let to_f64 = |x: BigInt| x.to_f64().unwrap_or(f64::NAN);
+
+  match (lhs, rhs) {
+    (ExprKind::Decimal(lhs), ExprKind::Decimal(rhs)) => decimal(lhs, rhs),
+    (ExprKind::Decimal(lhs), ExprKind::Integer(rhs)) => {
+      decimal(lhs, rhs.into())
+    }
+    (ExprKind::Integer(lhs), ExprKind::Decimal(rhs)) => {
+      decimal(lhs.into(), rhs)
+    }
+
+    (ExprKind::BigInt(lhs), ExprKind::BigInt(rhs)) => bigint(lhs, rhs),
+    (ExprKind::BigInt(lhs), ExprKind::Integer(rhs)) => bigint(lhs, rhs.into()),
+    (ExprKind::Integer(lhs), ExprKind::BigInt(rhs)) => bigint(lhs.into(), rhs),
+
+    (ExprKind::BigInt(lhs), ExprKind::Float(rhs)) => {
+      Ok(ExprKind::Float(float(to_f64(lhs), rhs)))
+    }
+    (ExprKind::Float(lhs), ExprKind::BigInt(rhs)) => {
+      Ok(ExprKind::Float(float(lhs, to_f64(rhs))))
+    }
+
+    (lhs, rhs) => Err((lhs, rhs)),
+  }
+}
+
+/// Returns whether `kind` is an integer or decimal which is zero.
+fn is_zero(kind: &ExprKind) -> bool {
+  match kind {
+    ExprKind::Integer(x) => *x == 0,
+    ExprKind::BigInt(x) => x.sign() == Sign::NoSign,
+    ExprKind::Decimal(x) => x.is_zero(),
+    _ => false,
+  }
+}
+
+// Integer arithmetic is exact, so results which don't fit in an `i64` are
+// promoted to an `ExprKind::BigInt`. The engine decides what to do with them
+// based on its `ArithmeticMode`. Decimals can't be promoted, so they fail to
+// apply when they overflow.
+
 impl ops::Add for ExprKind {
   type Output = Result<Self, (Self, Self)>;
 
   fn add(self, rhs: Self) -> Self::Output {
     match (self, rhs) {
       (Self::Integer(lhs), Self::Integer(rhs)) => {
-        Ok(Self::Integer(lhs.saturating_add(rhs)))
+        Ok(match lhs.checked_add(rhs) {
+          Some(x) => Self::Integer(x),
+          None => Self::BigInt(BigInt::from(lhs) + rhs),
+        })
       }
       (Self::Float(lhs), Self::Float(rhs)) => Ok(Self::Float(lhs + rhs)),
 
-      (lhs, rhs) => Err((lhs, rhs)),
+      (lhs, rhs) => {
+        mixed_op(lhs, rhs, Decimal::checked_add, ops::Add::add, ops::Add::add)
+      }
     }
   }
 }
@@ -295,11 +452,16 @@ impl ops::Sub for ExprKind {
   fn sub(self, rhs: Self) -> Self::Output {
     match (self, rhs) {
       (Self::Integer(lhs), Self::Integer(rhs)) => {
-        Ok(Self::Integer(lhs.saturating_sub(rhs)))
+        Ok(match lhs.checked_sub(rhs) {
+          Some(x) => Self::Integer(x),
+          None => Self::BigInt(BigInt::from(lhs) - rhs),
+        })
       }
       (Self::Float(lhs), Self::Float(rhs)) => Ok(Self::Float(lhs - rhs)),
 
-      (lhs, rhs) => Err((lhs, rhs)),
+      (lhs, rhs) => {
+        mixed_op(lhs, rhs, Decimal::checked_sub, ops::Sub::sub, ops::Sub::sub)
+      }
     }
   }
 }
@@ -310,11 +472,16 @@ impl ops::Mul for ExprKind {
   fn mul(self, rhs: Self) -> Self::Output {
     match (self, rhs) {
       (Self::Integer(lhs), Self::Integer(rhs)) => {
-        Ok(Self::Integer(lhs.saturating_mul(rhs)))
+        Ok(match lhs.checked_mul(rhs) {
+          Some(x) => Self::Integer(x),
+          None => Self::BigInt(BigInt::from(lhs) * rhs),
+        })
       }
       (Self::Float(lhs), Self::Float(rhs)) => Ok(Self::Float(lhs * rhs)),
 
-      (lhs, rhs) => Err((lhs, rhs)),
+      (lhs, rhs) => {
+        mixed_op(lhs, rhs, Decimal::checked_mul, ops::Mul::mul, ops::Mul::mul)
+      }
     }
   }
 }
@@ -324,12 +491,19 @@ impl ops::Div for ExprKind {
 
   fn div(self, rhs: Self) -> Self::Output {
     match (self, rhs) {
+      (lhs, rhs) if is_zero(&rhs) => Err((lhs, rhs)),
+
       (Self::Integer(lhs), Self::Integer(rhs)) => {
-        Ok(Self::Integer(lhs.saturating_div(rhs)))
+        Ok(match lhs.checked_div(rhs) {
+          Some(x) => Self::Integer(x),
+          None => Self::BigInt(BigInt::from(lhs) / rhs),
+        })
       }
       (Self::Float(lhs), Self::Float(rhs)) => Ok(Self::Float(lhs / rhs)),
 
-      (lhs, rhs) => Err((lhs, rhs)),
+      (lhs, rhs) => {
+        mixed_op(lhs, rhs, Decimal::checked_div, ops::Div::div, ops::Div::div)
+      }
     }
   }
 }
@@ -339,10 +513,16 @@ impl ops::Rem for ExprKind {
 
   fn rem(self, rhs: Self) -> Self::Output {
     match (self, rhs) {
-      (Self::Integer(lhs), Self::Integer(rhs)) => Ok(Self::Integer(lhs % rhs)),
+      (lhs, rhs) if is_zero(&rhs) => Err((lhs, rhs)),
+
+      (Self::Integer(lhs), Self::Integer(rhs)) => {
+        Ok(Self::Integer(lhs.wrapping_rem(rhs)))
+      }
       (Self::Float(lhs), Self::Float(rhs)) => Ok(Self::Float(lhs % rhs)),
 
-      (lhs, rhs) => Err((lhs, rhs)),
+      (lhs, rhs) => {
+        mixed_op(lhs, rhs, Decimal::checked_rem, ops::Rem::rem, ops::Rem::rem)
+      }
     }
   }
 }
@@ -358,10 +538,13 @@ impl fmt::Display for ExprKind {
 
         Self::Boolean(x) => write!(f, "{}", x.to_string().green()),
         Self::Integer(x) => write!(f, "{}", x.to_string().blue()),
+        Self::BigInt(x) => write!(f, "{}", x.to_string().blue()),
         Self::Float(x) => write!(f, "{}", x.to_string().blue()),
+        Self::Decimal(x) => write!(f, "{}", x.to_string().blue()),
         Self::String(x) => {
           write!(f, "{}{}{}", "\"".green(), x.green(), "\"".green(),)
         }
+        Self::Bytes(x) => write!(f, "{}", display_bytes(x).green()),
 
         Self::Symbol(x) => write!(f, "{}", x.as_str().blue()),
 
@@ -383,12 +566,21 @@ impl fmt::Display for ExprKind {
             .chain(core::iter::repeat(", "))
             .zip(x.iter())
             .try_for_each(|(sep, (key, value))| {
-              let key: Expr = ExprKind::Symbol(*key).into();
               write!(f, "{sep}{key:#}: {value:#}")
             })?;
 
           write!(f, "}}")
         }
+        Self::Set(x) => {
+          write!(f, "{}", "#{".yellow())?;
+
+          core::iter::once("")
+            .chain(core::iter::repeat(" "))
+            .zip(x.iter())
+            .try_for_each(|(sep, x)| write!(f, "{sep}{x:#}"))?;
+
+          write!(f, "{}", "}".yellow())
+        }
 
         Self::Function { scope, body } => {
           write!(f, "{}", "(".yellow())?;
@@ -424,8 +616,11 @@ impl fmt::Display for ExprKind {
 
         Self::Boolean(x) => write!(f, "{x}"),
         Self::Integer(x) => write!(f, "{x}"),
+        Self::BigInt(x) => write!(f, "{x}"),
         Self::Float(x) => write!(f, "{x}"),
+        Self::Decimal(x) => write!(f, "{x}"),
         Self::String(x) => write!(f, "{x}"),
+        Self::Bytes(x) => write!(f, "{}", display_bytes(x)),
 
         Self::Symbol(x) => write!(f, "{}", x.as_str()),
 
@@ -452,6 +647,16 @@ impl fmt::Display for ExprKind {
 
           write!(f, "}}")
         }
+        Self::Set(x) => {
+          write!(f, "#{{")?;
+
+          core::iter::once("")
+            .chain(core::iter::repeat(" "))
+            .zip(x.iter())
+            .try_for_each(|(sep, x)| write!(f, "{sep}{x}"))?;
+
+          write!(f, "}}")
+        }
 
         Self::Function { scope, body } => {
           write!(f, "(")?;
@@ -485,6 +690,187 @@ impl fmt::Display for ExprKind {
   }
 }
 
+/// An [`ExprKind`] which can be hashed, so that it can be the key of a record
+/// or an item of a set.
+///
+/// Strings and symbols with the same text are the same key, so `"name"` and
+/// `'name` can be used interchangeably.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Key {
+  Nil,
+
+  Boolean(bool),
+  Integer(i64),
+  BigInt(BigInt),
+  String(CompactString),
+  Bytes(#[serde(with = "hex_bytes")] Vec<u8>),
+
+  Symbol(Symbol),
+}
+
+impl Key {
+  /// Returns the text of a string or symbol key.
+  fn name(&self) -> Option<&str> {
+    match self {
+      Self::String(x) => Some(x.as_str()),
+      Self::Symbol(x) => Some(x.as_str()),
+      _ => None,
+    }
+  }
+}
+
+impl PartialEq for Key {
+  fn eq(&self, other: &Self) -> bool {
+    match (self, other) {
+      (Self::Nil, Self::Nil) => true,
+
+      (Self::Boolean(lhs), Self::Boolean(rhs)) => lhs == rhs,
+      (Self::Integer(lhs), Self::Integer(rhs)) => lhs == rhs,
+      (Self::BigInt(lhs), Self::BigInt(rhs)) => lhs == rhs,
+      (Self::Bytes(lhs), Self::Bytes(rhs)) => lhs == rhs,
+
+      (lhs, rhs) => lhs.name().is_some() && lhs.name() == rhs.name(),
+    }
+  }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    match self {
+      Self::Nil => {}
+
+      Self::Boolean(x) => x.hash(state),
+      Self::Integer(x) => x.hash(state),
+      Self::BigInt(x) => x.hash(state),
+      Self::Bytes(x) => x.hash(state),
+
+      // These must hash the same way, since they can be equal.
+      Self::String(x) => x.as_str().hash(state),
+      Self::Symbol(x) => x.as_str().hash(state),
+    }
+  }
+}
+
+impl TryFrom<ExprKind> for Key {
+  type Error = ExprKind;
+
+  fn try_from(value: ExprKind) -> Result<Self, Self::Error> {
+    match value {
+      ExprKind::Nil => Ok(Self::Nil),
+
+      ExprKind::Boolean(x) => Ok(Self::Boolean(x)),
+      ExprKind::Integer(x) => Ok(Self::Integer(x)),
+      ExprKind::BigInt(x) => Ok(Self::BigInt(x)),
+      ExprKind::String(x) => Ok(Self::String(x)),
+      ExprKind::Bytes(x) => Ok(Self::Bytes(x)),
+
+      ExprKind::Symbol(x) => Ok(Self::Symbol(x)),
+
+      x => Err(x),
+    }
+  }
+}
+
+impl From<Key> for ExprKind {
+  fn from(value: Key) -> Self {
+    match value {
+      Key::Nil => Self::Nil,
+
+      Key::Boolean(x) => Self::Boolean(x),
+      Key::Integer(x) => Self::Integer(x),
+      Key::BigInt(x) => Self::BigInt(x),
+      Key::String(x) => Self::String(x),
+      Key::Bytes(x) => Self::Bytes(x),
+
+      Key::Symbol(x) => Self::Symbol(x),
+    }
+  }
+}
+
+impl From<Key> for Expr {
+  #[inline]
+  fn from(value: Key) -> Self {
+    ExprKind::from(value).into()
+  }
+}
+
+impl fmt::Display for Key {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let kind = ExprKind::from(self.clone());
+
+    if f.alternate() {
+      write!(f, "{kind:#}")
+    } else {
+      write!(f, "{kind}")
+    }
+  }
+}
+
+/// Returns the literal syntax of `bytes`, such as `b"ok\xff"`.
+///
+/// Printable ASCII characters are kept, and every other byte is escaped.
+pub fn display_bytes(bytes: &[u8]) -> String {
+  let mut string = String::with_capacity(bytes.len() + 3);
+  string.push_str("b\"");
+
+  for byte in bytes {
+    match byte {
+      b'"' => string.push_str("\\\""),
+      b'\\' => string.push_str("\\\\"),
+      b'\n' => string.push_str("\\n"),
+      b'\t' => string.push_str("\\t"),
+      b'\r' => string.push_str("\\r"),
+      b' '..=b'~' => string.push(*byte as char),
+      _ => string.push_str(&format!("\\x{byte:02x}")),
+    }
+  }
+
+  string.push('"');
+  string
+}
+
+/// Serializes bytes as a hex string, which is much smaller than a sequence of
+/// numbers in formats such as JSON.
+mod hex_bytes {
+  use core::fmt::Write;
+
+  use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+  pub fn serialize<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+      let _ = write!(hex, "{byte:02x}");
+    }
+
+    serializer.serialize_str(&hex)
+  }
+
+  pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let hex = String::deserialize(deserializer)?;
+    if !hex.len().is_multiple_of(2) {
+      return Err(D::Error::custom("odd number of hex digits"));
+    }
+
+    (0..hex.len())
+      .step_by(2)
+      .map(|i| {
+        hex
+          .get(i..i + 2)
+          .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+          .ok_or_else(|| D::Error::custom("invalid hex digit"))
+      })
+      .collect()
+  }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(transparent)]
 pub struct Error(Intern<ErrorInner>);
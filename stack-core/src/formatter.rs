@@ -0,0 +1,475 @@
+//! Formats source code in a canonical style.
+//!
+//! Unlike the [`Display`] implementation of [`ExprKind`], formatting works on
+//! the tokens of a source rather than its expressions, so comments, literals
+//! and the lines which the author chose to break are kept as they are.
+//!
+//! [`Display`]: core::fmt::Display
+//! [`ExprKind`]: crate::expr::ExprKind
+
+use crate::{
+  lexer::{Lexer, Span, Token, TokenKind},
+  parser::{parse, ParseError},
+  source::Source,
+};
+
+/// The width which lines are kept within, where possible.
+pub const MAX_WIDTH: usize = 80;
+
+const INDENT: &str = "  ";
+
+/// Formats the code of a [`Source`].
+///
+/// Spacing inside `[]`, `()` and `{}` is normalized, lists and function
+/// bodies which are too long (or which already span several lines) are broken
+/// across lines, and records are written with a key and value per line when
+/// broken. Comments and single blank lines are kept.
+///
+/// Returns an error if the source fails to parse.
+pub fn format(source: Source) -> Result<String, ParseError> {
+  parse(&mut Lexer::new(source.clone()))?;
+
+  let mut lexer = Lexer::new(source.clone());
+  let mut tokens = Vec::new();
+  loop {
+    let token = lexer.next();
+    if token.kind == TokenKind::Eof {
+      break;
+    }
+
+    tokens.push(token);
+  }
+
+  let mut events = tokens
+    .into_iter()
+    .map(Event::Token)
+    .chain(lexer.comments().iter().copied().map(Event::Comment))
+    .collect::<Vec<_>>();
+  events.sort_by_key(|event| event.span().start);
+
+  let mut builder = Builder {
+    source: source.source(),
+    events,
+    cursor: 0,
+    end: 0,
+  };
+  let items = builder.sequence();
+
+  let mut printer = Printer {
+    out: String::new(),
+    column: 0,
+  };
+  printer.sequence(&items, 0, false);
+
+  let mut out = printer.out;
+  let len = out.trim_end().len();
+  out.truncate(len);
+  if !out.is_empty() {
+    out.push('\n');
+  }
+
+  Ok(out)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Event {
+  Token(Token),
+  Comment(Span),
+}
+
+impl Event {
+  fn span(self) -> Span {
+    match self {
+      Self::Token(token) => token.span,
+      Self::Comment(span) => span,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Item {
+  kind: ItemKind,
+  /// The number of line breaks between the previous item and this one.
+  newlines: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ItemKind {
+  Comment(String),
+  Node(Node),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+  Atom(String),
+  Lazy(Box<Node>),
+  Group { kind: GroupKind, items: Vec<Item> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupKind {
+  List,
+  Parenthetical,
+  Record,
+  Set,
+}
+
+impl GroupKind {
+  fn delimiters(self) -> (&'static str, &'static str) {
+    match self {
+      Self::List => ("[", "]"),
+      Self::Parenthetical => ("(", ")"),
+      Self::Record => ("{", "}"),
+      Self::Set => ("#{", "}"),
+    }
+  }
+}
+
+/// Builds a tree of [`Item`]s from tokens and comments, which are known to
+/// parse.
+struct Builder<'a> {
+  source: &'a str,
+  events: Vec<Event>,
+  cursor: usize,
+  /// The end of the last event.
+  end: usize,
+}
+
+impl<'a> Builder<'a> {
+  /// Collects the items until the end of the source or a closing delimiter,
+  /// which is consumed.
+  fn sequence(&mut self) -> Vec<Item> {
+    let mut items = Vec::new();
+
+    while let Some(event) = self.events.get(self.cursor).copied() {
+      if let Event::Token(token) = event {
+        if matches!(
+          token.kind,
+          TokenKind::RightSquare
+            | TokenKind::RightParen
+            | TokenKind::RightCurly
+        ) {
+          self.next();
+          break;
+        }
+      }
+
+      let newlines = self.newlines(event.span());
+      match event {
+        Event::Comment(_) => {
+          let kind = ItemKind::Comment(self.next().into());
+          items.push(Item { kind, newlines });
+        }
+        Event::Token(_) => {
+          let node = self.node(&mut items);
+          items.push(Item {
+            kind: ItemKind::Node(node),
+            newlines,
+          });
+        }
+      }
+    }
+
+    items
+  }
+
+  /// Builds the node of the next token. Comments between an apostrophe and
+  /// its expression are moved into `items`.
+  fn node(&mut self, items: &mut Vec<Item>) -> Node {
+    let Some(Event::Token(token)) = self.events.get(self.cursor).copied()
+    else {
+      let newlines = self.newlines(self.events[self.cursor].span());
+      let kind = ItemKind::Comment(self.next().into());
+      items.push(Item { kind, newlines });
+
+      return self.node(items);
+    };
+
+    let text = self.next();
+    let kind = match token.kind {
+      TokenKind::Apostrophe => return Node::Lazy(Box::new(self.node(items))),
+      TokenKind::LeftSquare => GroupKind::List,
+      TokenKind::LeftParen => GroupKind::Parenthetical,
+      TokenKind::LeftCurly => GroupKind::Record,
+      TokenKind::HashCurly => GroupKind::Set,
+      _ => return Node::Atom(text.into()),
+    };
+
+    Node::Group {
+      kind,
+      items: self.sequence(),
+    }
+  }
+
+  /// Consumes the next event, returning its text.
+  fn next(&mut self) -> &'a str {
+    let span = self.events[self.cursor].span();
+    self.cursor += 1;
+    self.end = span.end;
+
+    self.source[span.start..span.end].trim_end()
+  }
+
+  /// Returns the number of line breaks between the last event and `span`.
+  fn newlines(&self, span: Span) -> usize {
+    self.source[self.end..span.start].matches('\n').count()
+  }
+}
+
+struct Printer {
+  out: String,
+  /// The number of characters in the current line.
+  column: usize,
+}
+
+impl Printer {
+  /// Writes a sequence of items, such as the body of a function, keeping
+  /// the line breaks between them.
+  fn sequence(&mut self, items: &[Item], depth: usize, indented: bool) {
+    // Whether the next item must go on a new line, which is the case after a
+    // comment.
+    let mut must_break = false;
+
+    for (i, item) in items.iter().enumerate() {
+      let blank = i > 0 && item.newlines > 1;
+
+      match item.kind {
+        ItemKind::Comment(ref comment) => {
+          if item.newlines > 0 || must_break || self.column == 0 {
+            self.newline_if(i > 0 || indented, blank, depth);
+          } else {
+            self.push(" ");
+          }
+
+          self.push(comment);
+          must_break = true;
+        }
+        ItemKind::Node(ref node) => {
+          if item.newlines > 0 || must_break || (i == 0 && indented) {
+            self.newline_if(i > 0 || indented, blank, depth);
+          } else if i > 0 {
+            // Groups which are broken anyway can start on this line.
+            let width = match flat(node) {
+              Some(x) if self.column + 1 + x.chars().count() <= MAX_WIDTH => 0,
+              _ => head_width(node),
+            };
+
+            if self.column + 1 + width > MAX_WIDTH {
+              self.newline(false, depth);
+            } else {
+              self.push(" ");
+            }
+          }
+
+          self.node(node, depth);
+          must_break = false;
+        }
+      }
+    }
+  }
+
+  fn node(&mut self, node: &Node, depth: usize) {
+    if let Some(flat) = flat(node) {
+      if self.column + flat.chars().count() <= MAX_WIDTH {
+        self.push(&flat);
+        return;
+      }
+    }
+
+    match node {
+      Node::Atom(x) => self.push(x),
+      Node::Lazy(x) => {
+        self.push("'");
+        self.node(x, depth);
+      }
+      Node::Group { kind, items } => {
+        let (open, close) = kind.delimiters();
+        self.push(open);
+
+        match kind {
+          GroupKind::List | GroupKind::Set => {
+            self.sequence(items, depth + 1, true)
+          }
+          GroupKind::Parenthetical => {
+            // The call stays on the same line as the parenthesis.
+            let mut items = items.as_slice();
+            if let Some(Item {
+              kind: ItemKind::Node(Node::Atom(call)),
+              ..
+            }) = items.first()
+            {
+              self.push(call);
+              items = &items[1..];
+            }
+
+            self.sequence(items, depth + 1, true);
+          }
+          GroupKind::Record => self.record(items, depth + 1),
+        }
+
+        self.newline(false, depth);
+        self.push(close);
+      }
+    }
+  }
+
+  /// Writes the items of a record, with each key and value on its own line.
+  fn record(&mut self, items: &[Item], depth: usize) {
+    let mut is_key = true;
+
+    for (i, item) in items.iter().enumerate() {
+      match item.kind {
+        ItemKind::Comment(ref comment) => {
+          if item.newlines > 0 {
+            self.newline(i > 0 && item.newlines > 1, depth);
+          } else {
+            self.push(" ");
+          }
+
+          self.push(comment);
+        }
+        ItemKind::Node(ref node) => {
+          if is_key {
+            self.newline(i > 0 && item.newlines > 1, depth);
+          } else if items[i - 1].kind.is_comment() {
+            self.newline(false, depth);
+          } else {
+            self.push(" ");
+          }
+
+          self.node(node, depth);
+          is_key = !is_key;
+        }
+      }
+    }
+  }
+
+  fn newline_if(&mut self, newline: bool, blank: bool, depth: usize) {
+    if newline {
+      self.newline(blank, depth);
+    }
+  }
+
+  fn newline(&mut self, blank: bool, depth: usize) {
+    let len = self.out.trim_end_matches(' ').len();
+    self.out.truncate(len);
+
+    self.out.push('\n');
+    if blank {
+      self.out.push('\n');
+    }
+
+    self.column = 0;
+    for _ in 0..depth {
+      self.push(INDENT);
+    }
+  }
+
+  fn push(&mut self, s: &str) {
+    self.out.push_str(s);
+    self.column += s.chars().count();
+  }
+}
+
+impl ItemKind {
+  fn is_comment(&self) -> bool {
+    matches!(self, Self::Comment(_))
+  }
+}
+
+/// Returns the width of the first line of `node` when it's broken across
+/// lines.
+fn head_width(node: &Node) -> usize {
+  match node {
+    Node::Atom(x) => x.chars().count(),
+    Node::Lazy(x) => 1 + head_width(x),
+    Node::Group {
+      kind: GroupKind::Parenthetical,
+      items,
+    } => match items.first() {
+      Some(Item {
+        kind: ItemKind::Node(Node::Atom(call)),
+        ..
+      }) => 1 + call.chars().count(),
+      _ => 1,
+    },
+    Node::Group { .. } => 1,
+  }
+}
+
+/// Returns `node` written on a single line, or [`None`] if it contains
+/// comments or line breaks.
+fn flat(node: &Node) -> Option<String> {
+  match node {
+    Node::Atom(x) => Some(x.clone()),
+    Node::Lazy(x) => flat(x).map(|x| format!("'{x}")),
+    Node::Group { kind, items } => {
+      let (open, close) = kind.delimiters();
+
+      let items = items
+        .iter()
+        .map(|item| match item.kind {
+          ItemKind::Node(ref node) if item.newlines == 0 => flat(node),
+          _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+      Some(format!("{open}{}{close}", items.join(" ")))
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use test_case::case;
+
+  fn fmt(source: &str) -> String {
+    format(Source::new("", source)).unwrap()
+  }
+
+  #[case("" => "" ; "empty")]
+  #[case("1   2\t+" => "1 2 +\n" ; "spacing")]
+  #[case("[ 1  2 ]  ( + 1 2 ) {  \"a\"   1 }" => "[1 2] (+ 1 2) {\"a\" 1}\n" ; "delimiters")]
+  #[case("#{ 1  2 }" => "#{1 2}\n" ; "set")]
+  #[case("'  [1]  ''a" => "'[1] ''a\n" ; "lazy")]
+  #[case("1\n\n\n\n2\n" => "1\n\n2\n" ; "blank lines")]
+  #[case(";; hi  \n1 ; one\n2" => ";; hi\n1 ; one\n2\n" ; "comments")]
+  #[case("'(fn\n1\n    2 +) 'f def" => "'(fn\n  1\n  2 +\n) 'f def\n" ; "function body")]
+  #[case("'(fn ; doc\n1)" => "'(fn ; doc\n  1\n)\n" ; "comment after call")]
+  #[case("[1 ; one\n2]" => "[\n  1 ; one\n  2\n]\n" ; "comment in list")]
+  #[case("{\"a\" 1 \"b\"\n[2]}" => "{\n  \"a\" 1\n  \"b\" [2]\n}\n" ; "record")]
+  #[case("1.50 \"\\n\"" => "1.50 \"\\n\"\n" ; "literals")]
+  fn formats(source: &str) -> String {
+    fmt(source)
+  }
+
+  #[test]
+  fn breaks_long_lists() {
+    let source = format!("[{}]", "12345 ".repeat(20));
+    let formatted = fmt(&source);
+
+    assert_eq!(
+      formatted,
+      format!(
+        "[\n  {}\n  {}\n]\n",
+        "12345 ".repeat(13).trim_end(),
+        "12345 ".repeat(7).trim_end()
+      )
+    );
+    assert!(formatted.lines().all(|line| line.len() <= MAX_WIDTH));
+  }
+
+  #[test]
+  fn is_idempotent() {
+    let source = ";; Sums a list\n'(fn! 0 'total def ; start\n  '[total + 'total set] each\n\n  total) 'sum def\n{\"a\" {\"b\" 1}\n ; c\n \"c\" 2}\n[1 2 3] sum";
+    let formatted = fmt(source);
+
+    assert_eq!(fmt(&formatted), formatted);
+  }
+
+  #[test]
+  fn fails_to_format_invalid_source() {
+    assert!(format(Source::new("", "(1 2)")).is_err());
+  }
+}
@@ -1,17 +1,24 @@
-use core::{fmt, num::FpCategory, str::FromStr};
-use std::collections::HashMap;
-
-use compact_str::ToCompactString;
+use core::{cmp::Ordering, fmt, num::FpCategory, ops, str::FromStr};
+use std::path::{Path, PathBuf};
+
+use compact_str::{CompactString, ToCompactString};
+use indexmap::IndexMap;
+use num_bigint::{BigInt, Sign};
+use num_traits::ToPrimitive;
+use rust_decimal::Decimal;
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
+  checker::StackEffect,
   context::Context,
-  expr::{Expr, ExprKind},
+  expr::{Expr, ExprKind, FnScope, Key},
   journal::JournalOp,
   lexer::Lexer,
-  prelude::{parse, Engine, RunError, RunErrorReason},
+  prelude::{parse, ArithmeticMode, Engine, RunError, RunErrorReason},
+  scope::Scope,
   source::Source,
   symbol::Symbol,
+  template::{self, Placeholder, Segment},
 };
 
 macro_rules! intrinsics {
@@ -94,21 +101,47 @@ intrinsics! {
   Push => ("push", true),
   Pop => ("pop", false),
 
+  Map => ("map", false),
+  Filter => ("filter", false),
+  Fold => ("fold", false),
+  Reduce => ("reduce", false),
+  Each => ("each", false),
+  Any => ("any", false),
+  All => ("all", false),
+  Find => ("find", false),
+  Sort => ("sort", false),
+  SortBy => ("sort-by", false),
+  Zip => ("zip", false),
+  Enumerate => ("enumerate", false),
+  Range => ("range", false),
+
   Insert => ("insert", true),
   Prop => ("prop", false),
   Has => ("has", false),
   Remove => ("remove", false),
   Keys => ("keys", false),
   Values => ("values", false),
+  Union => ("union", false),
+  Intersection => ("intersection", false),
+  Difference => ("difference", false),
 
   Cast => ("cast", false),
   TypeOf => ("typeof", false),
   Lazy => ("lazy", false),
+  Format => ("format", false),
 
   If => ("if", false),
+  Match => ("match", false),
   Halt => ("halt", false),
 
+  Try => ("try", false),
+  Catch => ("catch", false),
+  Throw => ("throw", false),
+
   Call => ("call", false),
+  Curry => ("curry", true),
+  Partial => ("partial", true),
+  Capture => ("capture", false),
 
   Let => ("let", true),
   Def => ("def", true),
@@ -124,86 +157,210 @@ intrinsics! {
   OrElse => ("orelse", false),
 
   Import => ("import", false),
+  Export => ("export", false),
+  Use => ("use", false),
 }
 
 impl Intrinsic {
+  /// Returns the [`StackEffect`] of this [`Intrinsic`], or [`None`] if it
+  /// depends on the items which it calls, such as with `if`.
+  pub const fn stack_effect(self) -> Option<StackEffect> {
+    let (inputs, outputs) = match self {
+      Self::Add | Self::Sub | Self::Mul | Self::Div | Self::Rem => (2, 1),
+
+      Self::Eq | Self::Ne | Self::Lt | Self::Le | Self::Gt | Self::Ge => (2, 1),
+
+      Self::Or | Self::And => (2, 1),
+      Self::Not => (1, 1),
+
+      Self::Assert => (2, 0),
+
+      Self::Drop => (1, 0),
+      Self::Dupe => (1, 2),
+      Self::Swap => (2, 2),
+      Self::Rot => (3, 3),
+
+      Self::Len => (1, 2),
+      Self::Nth => (2, 2),
+      Self::Split => (2, 2),
+      Self::Concat => (2, 1),
+      Self::Push => (2, 1),
+      Self::Pop => (1, 2),
+
+      Self::Map | Self::Filter | Self::Reduce => (2, 1),
+      Self::Fold => (3, 1),
+      Self::Any | Self::All | Self::Find => (2, 1),
+      Self::Sort => (1, 1),
+      Self::SortBy => (2, 1),
+      Self::Zip => (2, 1),
+      Self::Enumerate => (1, 1),
+      Self::Range => (2, 1),
+
+      Self::Prop => (2, 2),
+      Self::Has => (2, 2),
+      Self::Remove => (2, 1),
+      Self::Keys => (1, 2),
+      Self::Values => (1, 2),
+      Self::Union | Self::Intersection | Self::Difference => (2, 1),
+
+      Self::Cast => (2, 1),
+      Self::TypeOf => (1, 1),
+      Self::Lazy => (1, 1),
+
+      Self::Def | Self::Set => (2, 0),
+      Self::Get => (1, 1),
+
+      Self::Debug => (0, 0),
+      Self::Print | Self::Pretty => (1, 0),
+      Self::Recur => (0, 1),
+
+      Self::OrElse => (2, 1),
+
+      Self::Curry | Self::Partial | Self::Capture => (2, 1),
+
+      Self::Export => (1, 1),
+      Self::Use => (2, 0),
+
+      Self::If
+      | Self::Match
+      | Self::Halt
+      | Self::Try
+      | Self::Catch
+      | Self::Throw
+      | Self::Call
+      | Self::Let
+      | Self::Each
+      | Self::Insert
+      | Self::Format
+      | Self::Import => return None,
+    };
+
+    Some(StackEffect::new(inputs, outputs))
+  }
+
   pub fn run(
     &self,
     engine: &Engine,
     mut context: Context,
     expr: Expr,
   ) -> Result<Context, RunError> {
+    // The intrinsics which call back into the engine are kept out of
+    // [`Self::run_simple`], since its stack frame is large in debug builds.
     match self {
-      // MARK: Add
-      Self::Add => {
-        let rhs = context.stack_pop(&expr)?;
-        let lhs = context.stack_pop(&expr)?;
+      // MARK: Map
+      Self::Map => map(engine, context, expr),
+      // MARK: Filter
+      Self::Filter => filter(engine, context, expr),
+      // MARK: Fold
+      Self::Fold => fold(engine, context, expr),
+      // MARK: Reduce
+      Self::Reduce => reduce(engine, context, expr),
+      // MARK: Each
+      Self::Each => each(engine, context, expr),
+      // MARK: Any
+      Self::Any => any(engine, context, expr),
+      // MARK: All
+      Self::All => all(engine, context, expr),
+      // MARK: Find
+      Self::Find => find(engine, context, expr),
+      // MARK: SortBy
+      Self::SortBy => sort_by(engine, context, expr),
 
-        let kind = match lhs.kind.clone() + rhs.kind.clone() {
-          Ok(res) => res,
-          Err(_) => ExprKind::Nil,
-        };
+      // MARK: If
+      Self::If => {
+        let body = context.stack_pop(&expr)?;
+        let cond = context.stack_pop(&expr)?;
 
-        context.stack_push(kind.into())?;
+        if cond.kind.is_truthy() {
+          context = engine.call_expr(context, body)?;
+        }
 
         Ok(context)
       }
-      // MARK: Sub
-      Self::Sub => {
-        let rhs = context.stack_pop(&expr)?;
-        let lhs = context.stack_pop(&expr)?;
 
-        let kind = match lhs.kind.clone() - rhs.kind.clone() {
-          Ok(res) => res,
-          Err(_) => ExprKind::Nil,
-        };
+      // MARK: Try
+      Self::Try => {
+        let handler = context.stack_pop(&expr)?;
+        let body = context.stack_pop(&expr)?;
 
-        context.stack_push(kind.into())?;
+        match catch_expr(engine, context, body)? {
+          (context, None) => Ok(context),
+          (mut context, Some(record)) => {
+            context.stack_push(record)?;
+            engine.call_expr(context, handler)
+          }
+        }
+      }
+      // MARK: Catch
+      Self::Catch => {
+        let body = context.stack_pop(&expr)?;
+
+        let (mut context, record) = catch_expr(engine, context, body)?;
+        context.stack_push(record.unwrap_or(ExprKind::Nil.into()))?;
 
         Ok(context)
       }
-      // MARK: Mul
-      Self::Mul => {
-        let rhs = context.stack_pop(&expr)?;
-        let lhs = context.stack_pop(&expr)?;
 
-        let kind = match lhs.kind.clone() * rhs.kind.clone() {
-          Ok(res) => res,
-          Err(_) => ExprKind::Nil,
+      // MARK: Call
+      Self::Call => {
+        let item = context.stack_pop(&expr)?;
+        engine.call_expr(context, item)
+      }
+
+      // MARK: Let, Match
+      Self::Let | Self::Match => {
+        let (body, scope) = match self {
+          Self::Let => let_scope(&mut context, &expr)?,
+          _ => match_scope(&mut context, &expr)?,
         };
 
-        context.stack_push(kind.into())?;
+        if let Some(journal) = context.journal_mut() {
+          journal.commit();
+          journal.push_op(JournalOp::ScopelessFnStart(expr.info.clone()));
+        }
 
-        Ok(context)
-      }
-      // MARK: Div
-      Self::Div => {
-        let rhs = context.stack_pop(&expr)?;
-        let lhs = context.stack_pop(&expr)?;
+        context.push_scope(scope);
+        context = engine.call_expr(context, body)?;
 
-        let kind = match lhs.kind.clone() / rhs.kind.clone() {
-          Ok(res) => res,
-          Err(_) => ExprKind::Nil,
-        };
+        if context.journal().is_some() {
+          let scope = context.scope().clone();
+          let journal = context.journal_mut().as_mut().unwrap();
+          journal.commit();
+          journal.push_op(JournalOp::FnEnd(expr.info.clone(), scope.into()));
+        }
 
-        context.stack_push(kind.into())?;
+        context.pop_scope();
 
         Ok(context)
       }
-      // MARK: Rem
-      Self::Rem => {
-        let rhs = context.stack_pop(&expr)?;
-        let lhs = context.stack_pop(&expr)?;
 
-        let kind = match lhs.kind.clone() % rhs.kind.clone() {
-          Ok(res) => res,
-          Err(_) => ExprKind::Nil,
-        };
+      // MARK: Import
+      Self::Import => import_file(engine, context, expr),
+      // MARK: Use
+      Self::Use => use_module(engine, context, expr),
 
-        context.stack_push(kind.into())?;
+      _ => self.run_simple(engine, context, expr),
+    }
+  }
 
-        Ok(context)
-      }
+  /// Runs the intrinsics which don't call back into the [`Engine`].
+  fn run_simple(
+    &self,
+    engine: &Engine,
+    mut context: Context,
+    expr: Expr,
+  ) -> Result<Context, RunError> {
+    match self {
+      // MARK: Add
+      Self::Add => arithmetic(engine, context, expr, ops::Add::add),
+      // MARK: Sub
+      Self::Sub => arithmetic(engine, context, expr, ops::Sub::sub),
+      // MARK: Mul
+      Self::Mul => arithmetic(engine, context, expr, ops::Mul::mul),
+      // MARK: Div
+      Self::Div => arithmetic(engine, context, expr, ops::Div::div),
+      // MARK: Rem
+      Self::Rem => arithmetic(engine, context, expr, ops::Rem::rem),
 
       // MARK: Eq
       Self::Eq => {
@@ -317,8 +474,8 @@ impl Intrinsic {
         } else {
           Err(RunError {
             reason: RunErrorReason::AssertionFailed,
-            context,
-            expr: message.kind.into(),
+            context: Box::new(context),
+            expr: Box::new(message.kind.into()),
           })
         }
       }
@@ -332,8 +489,8 @@ impl Intrinsic {
       Self::Dupe => {
         let item = context.stack().last().cloned().ok_or_else(|| RunError {
           reason: RunErrorReason::StackUnderflow,
-          context: context.clone(),
-          expr,
+          context: Box::new(context.clone()),
+          expr: Box::new(expr),
         })?;
 
         context.stack_push(item)?;
@@ -344,13 +501,13 @@ impl Intrinsic {
         let len = context.stack().len();
 
         if len >= 2 {
-          context.stack_mut().swap(len - 1, len - 2);
+          context.stack_swap(len - 1, len - 2);
           Ok(context)
         } else {
           Err(RunError {
             reason: RunErrorReason::StackUnderflow,
-            context,
-            expr,
+            context: Box::new(context),
+            expr: Box::new(expr),
           })
         }
       }
@@ -359,15 +516,15 @@ impl Intrinsic {
         let len = context.stack().len();
 
         if len >= 3 {
-          context.stack_mut().swap(len - 1, len - 3);
-          context.stack_mut().swap(len - 2, len - 3);
+          context.stack_swap(len - 1, len - 3);
+          context.stack_swap(len - 2, len - 3);
 
           Ok(context)
         } else {
           Err(RunError {
             reason: RunErrorReason::StackUnderflow,
-            context,
-            expr,
+            context: Box::new(context),
+            expr: Box::new(expr),
           })
         }
       }
@@ -390,6 +547,14 @@ impl Intrinsic {
             debug_assert!(x.len() <= i64::MAX as usize);
             ExprKind::Integer(x.len() as i64)
           }
+          ExprKind::Set(ref x) => {
+            debug_assert!(x.len() <= i64::MAX as usize);
+            ExprKind::Integer(x.len() as i64)
+          }
+          ExprKind::Bytes(ref x) => {
+            debug_assert!(x.len() <= i64::MAX as usize);
+            ExprKind::Integer(x.len() as i64)
+          }
           _ => ExprKind::Nil,
         };
 
@@ -415,6 +580,10 @@ impl Intrinsic {
             .nth(i as usize)
             .map(|x| ExprKind::String(x.into()))
             .unwrap_or(ExprKind::Nil),
+          (ExprKind::Bytes(x), ExprKind::Integer(i)) if i >= 0 => x
+            .get(i as usize)
+            .map(|x| ExprKind::Integer(*x as i64))
+            .unwrap_or(ExprKind::Nil),
           _ => ExprKind::Nil,
         };
 
@@ -459,6 +628,19 @@ impl Intrinsic {
               }
             }
           }
+          (ExprKind::Bytes(mut x), ExprKind::Integer(i)) if i >= 0 => {
+            if (i as usize) < x.len() {
+              let rest = x.split_off(i as usize);
+
+              context.stack_push(ExprKind::Bytes(x).into())?;
+
+              context.stack_push(ExprKind::Bytes(rest).into())?;
+            } else {
+              context.stack_push(ExprKind::Bytes(x).into())?;
+
+              context.stack_push(ExprKind::Nil.into())?;
+            }
+          }
           _ => {
             context.stack_push(ExprKind::Nil.into())?;
 
@@ -482,6 +664,10 @@ impl Intrinsic {
             lhs.push_str(&rhs);
             ExprKind::String(lhs)
           }
+          (ExprKind::Bytes(mut lhs), ExprKind::Bytes(rhs)) => {
+            lhs.extend(rhs);
+            ExprKind::Bytes(lhs)
+          }
           _ => ExprKind::Nil,
         };
 
@@ -516,6 +702,16 @@ impl Intrinsic {
               ExprKind::Nil
             }
           }
+          (ExprKind::Bytes(mut x), ExprKind::Integer(byte))
+            if byte >= 0 && byte <= u8::MAX as i64 =>
+          {
+            x.push(byte as u8);
+            ExprKind::Bytes(x)
+          }
+          (ExprKind::Bytes(mut x), ExprKind::Bytes(bytes)) => {
+            x.extend(bytes);
+            ExprKind::Bytes(x)
+          }
           _ => ExprKind::Nil,
         };
 
@@ -543,6 +739,15 @@ impl Intrinsic {
             context.stack_push(ExprKind::String(x).into())?;
             context.stack_push(e)?;
           }
+          ExprKind::Bytes(mut x) => {
+            let e = x
+              .pop()
+              .map(|e| ExprKind::Integer(e as i64).into())
+              .unwrap_or(ExprKind::Nil.into());
+
+            context.stack_push(ExprKind::Bytes(x).into())?;
+            context.stack_push(e)?;
+          }
           _ => {
             context.stack_push(list.clone())?;
             context.stack_push(ExprKind::Nil.into())?;
@@ -552,20 +757,43 @@ impl Intrinsic {
         Ok(context)
       }
 
+      // MARK: Sort
+      Self::Sort => sort(context, expr),
+      // MARK: Zip
+      Self::Zip => zip(context, expr),
+      // MARK: Enumerate
+      Self::Enumerate => enumerate(context, expr),
+      // MARK: Range
+      Self::Range => range(context, expr),
+
       // MARK: Insert
       Self::Insert => {
-        let record = context.stack_pop(&expr)?;
+        let collection = context.stack_pop(&expr)?;
+
+        // Sets only have an item to insert, rather than a key and a value.
+        if let ExprKind::Set(mut set) = collection.kind {
+          let item = context.stack_pop(&expr)?;
+
+          let kind = match Key::try_from(item.kind) {
+            Ok(item) => {
+              set.insert(item);
+              ExprKind::Set(set)
+            }
+            Err(_) => ExprKind::Nil,
+          };
+
+          context.stack_push(kind.into())?;
+          return Ok(context);
+        }
+
         let name = context.stack_pop(&expr)?;
         let value = context.stack_pop(&expr)?;
 
-        match record.kind {
-          ExprKind::Record(ref record) => {
-            let symbol: Symbol = name.kind.into();
-
-            let mut new_record = record.clone();
-            new_record.insert(symbol, value);
+        match (collection.kind, Key::try_from(name.kind)) {
+          (ExprKind::Record(mut record), Ok(key)) => {
+            record.insert(key, value);
 
-            context.stack_push(ExprKind::Record(new_record).into())?;
+            context.stack_push(ExprKind::Record(record).into())?;
 
             Ok(())
           }
@@ -580,12 +808,13 @@ impl Intrinsic {
 
         match record.kind {
           ExprKind::Record(ref r) => {
-            let symbol: Symbol = name.kind.into();
-
-            let result = r.get(&symbol).unwrap_or_else(|| &Expr {
-              info: None,
-              kind: ExprKind::Nil,
-            });
+            let result = Key::try_from(name.kind)
+              .ok()
+              .and_then(|key| r.get(&key))
+              .unwrap_or_else(|| &Expr {
+                info: None,
+                kind: ExprKind::Nil,
+              });
 
             context.stack_push(record.clone())?;
             context.stack_push(result.clone())?;
@@ -601,11 +830,19 @@ impl Intrinsic {
         let name = context.stack_pop(&expr)?;
         let record = context.stack_pop(&expr)?;
 
+        let key = Key::try_from(name.kind).ok();
+
         match record.kind {
           ExprKind::Record(ref r) => {
-            let symbol: Symbol = name.kind.into();
+            let result = key.is_some_and(|key| r.contains_key(&key));
+
+            context.stack_push(record.clone())?;
+            context.stack_push(ExprKind::Boolean(result).into())?;
 
-            let result = r.contains_key(&symbol);
+            Ok(())
+          }
+          ExprKind::Set(ref r) => {
+            let result = key.is_some_and(|key| r.contains(&key));
 
             context.stack_push(record.clone())?;
             context.stack_push(ExprKind::Boolean(result).into())?;
@@ -621,14 +858,24 @@ impl Intrinsic {
         let name = context.stack_pop(&expr)?;
         let record = context.stack_pop(&expr)?;
 
+        let key = Key::try_from(name.kind).ok();
+
         match record.kind {
-          ExprKind::Record(ref record) => {
-            let symbol: Symbol = name.kind.into();
+          ExprKind::Record(mut record) => {
+            if let Some(key) = key {
+              record.shift_remove(&key);
+            }
 
-            let mut new_record = record.clone();
-            new_record.remove(&symbol);
+            context.stack_push(ExprKind::Record(record).into())?;
 
-            context.stack_push(ExprKind::Record(new_record).into())?;
+            Ok(())
+          }
+          ExprKind::Set(mut set) => {
+            if let Some(key) = key {
+              set.shift_remove(&key);
+            }
+
+            context.stack_push(ExprKind::Set(set).into())?;
 
             Ok(())
           }
@@ -642,14 +889,7 @@ impl Intrinsic {
 
         match record.kind {
           ExprKind::Record(ref r) => {
-            let result = r
-              .keys()
-              .copied()
-              .map(|s| Expr {
-                info: None,
-                kind: ExprKind::Symbol(s),
-              })
-              .collect::<Vec<_>>();
+            let result = r.keys().cloned().map(Expr::from).collect::<Vec<_>>();
 
             context.stack_push(record.clone())?;
             context.stack_push(ExprKind::List(result).into())?;
@@ -678,6 +918,16 @@ impl Intrinsic {
         .map(|_| context)
       }
 
+      // MARK: Format
+      Self::Format => format_template(context, expr),
+
+      // MARK: Union
+      Self::Union => combine(context, expr, Combine::Union),
+      // MARK: Intersection
+      Self::Intersection => combine(context, expr, Combine::Intersection),
+      // MARK: Difference
+      Self::Difference => combine(context, expr, Combine::Difference),
+
       // MARK: Cast
       Self::Cast => {
         let ty = context.stack_pop(&expr)?;
@@ -689,11 +939,19 @@ impl Intrinsic {
             (ExprKind::Nil, "boolean") => ExprKind::Boolean(false),
             (ExprKind::Boolean(x), "boolean") => ExprKind::Boolean(x),
             (ExprKind::Integer(x), "boolean") => ExprKind::Boolean(x != 0),
+            (ExprKind::BigInt(_), "boolean") => ExprKind::Boolean(true),
             (ExprKind::Float(x), "boolean") => ExprKind::Boolean(x == 0.0),
+            (ExprKind::Decimal(x), "boolean") => {
+              ExprKind::Boolean(!x.is_zero())
+            }
 
             (ExprKind::Nil, "integer") => ExprKind::Integer(0),
             (ExprKind::Boolean(x), "integer") => ExprKind::Integer(x as i64),
             (ExprKind::Integer(x), "integer") => ExprKind::Integer(x),
+            (ExprKind::BigInt(x), "integer") => ExprKind::BigInt(x),
+            (ExprKind::Decimal(x), "integer") => {
+              ExprKind::from_bigint(x.trunc().mantissa().into())
+            }
             (ExprKind::Float(x), "integer") => {
               let x = x.floor();
 
@@ -711,7 +969,33 @@ impl Intrinsic {
             (ExprKind::Nil, "float") => ExprKind::Float(0.0),
             (ExprKind::Boolean(x), "float") => ExprKind::Float(x as i64 as f64),
             (ExprKind::Integer(x), "float") => ExprKind::Float(x as f64),
+            (ExprKind::BigInt(x), "float") => {
+              x.to_f64().map(ExprKind::Float).unwrap_or(ExprKind::Nil)
+            }
             (ExprKind::Float(x), "float") => ExprKind::Float(x),
+            (ExprKind::Decimal(x), "float") => {
+              x.to_f64().map(ExprKind::Float).unwrap_or(ExprKind::Nil)
+            }
+
+            (ExprKind::Nil, "decimal") => ExprKind::Decimal(Decimal::ZERO),
+            (ExprKind::Boolean(x), "decimal") => {
+              ExprKind::Decimal(Decimal::from(x as i64))
+            }
+            (ExprKind::Integer(x), "decimal") => {
+              ExprKind::Decimal(Decimal::from(x))
+            }
+            (ExprKind::BigInt(x), "decimal") => {
+              Decimal::from_str(&x.to_string())
+                .map(ExprKind::Decimal)
+                .unwrap_or(ExprKind::Nil)
+            }
+            (ExprKind::Float(x), "decimal") => Decimal::try_from(x)
+              .map(ExprKind::Decimal)
+              .unwrap_or(ExprKind::Nil),
+            (ExprKind::Decimal(x), "decimal") => ExprKind::Decimal(x),
+            (ExprKind::String(x), "decimal") => Decimal::from_str(x.trim())
+              .map(ExprKind::Decimal)
+              .unwrap_or(ExprKind::Nil),
 
             (ExprKind::Nil, "string") => ExprKind::String("nil".into()),
             (ExprKind::Boolean(x), "string") => {
@@ -720,10 +1004,19 @@ impl Intrinsic {
             (ExprKind::Integer(x), "string") => {
               ExprKind::String(x.to_compact_string())
             }
+            (ExprKind::BigInt(x), "string") => {
+              ExprKind::String(x.to_compact_string())
+            }
             (ExprKind::Float(x), "string") => {
               ExprKind::String(x.to_compact_string())
             }
+            (ExprKind::Decimal(x), "string") => {
+              ExprKind::String(x.to_compact_string())
+            }
             (ExprKind::String(x), "string") => ExprKind::String(x),
+            (ExprKind::Bytes(x), "string") => CompactString::from_utf8(x)
+              .map(ExprKind::String)
+              .unwrap_or(ExprKind::Nil),
             (ExprKind::Symbol(x), "string") => {
               ExprKind::String(x.as_str().into())
             }
@@ -736,32 +1029,59 @@ impl Intrinsic {
             (ExprKind::String(x), "symbol") => ExprKind::Symbol(Symbol::new(x)),
             (ExprKind::Symbol(x), "symbol") => ExprKind::Symbol(x),
 
+            (ExprKind::String(x), "bytes") => {
+              ExprKind::Bytes(x.as_bytes().to_vec())
+            }
+            (ExprKind::Bytes(x), "bytes") => ExprKind::Bytes(x),
+            (ExprKind::List(x), "bytes") => x
+              .into_iter()
+              .map(|x| match x.kind {
+                ExprKind::Integer(x) => u8::try_from(x).ok(),
+                _ => None,
+              })
+              .collect::<Option<_>>()
+              .map(ExprKind::Bytes)
+              .unwrap_or(ExprKind::Nil),
+            (ExprKind::Bytes(x), "list") => ExprKind::List(
+              x.into_iter()
+                .map(|x| ExprKind::Integer(x as i64).into())
+                .collect(),
+            ),
+
             (ExprKind::Record(x), "record") => ExprKind::Record(x),
             (ExprKind::Record(x), "list") => {
               let mut list: Vec<Expr> = Vec::new();
               x.into_iter().for_each(|(key, value)| {
-                list.push(
-                  ExprKind::List(vec![ExprKind::Symbol(key).into(), value])
-                    .into(),
-                );
+                list.push(ExprKind::List(vec![key.into(), value]).into());
               });
 
               ExprKind::List(list)
             }
 
-            (ExprKind::List(x), "record") => {
-              let mut record: HashMap<Symbol, Expr> = HashMap::new();
-              x.into_iter().for_each(|item| {
-                if let ExprKind::List(chunk) = item.kind {
-                  let key =
-                    Symbol::from_ref(chunk[0].kind.to_string().as_str());
-                  let value = &chunk[1];
-                  record.insert(key, value.clone());
+            (ExprKind::List(x), "record") => x
+              .into_iter()
+              .map(|item| match item.kind {
+                ExprKind::List(chunk) if chunk.len() == 2 => {
+                  let mut chunk = chunk.into_iter();
+                  let key = Key::try_from(chunk.next()?.kind).ok()?;
+                  Some((key, chunk.next()?))
                 }
-              });
+                _ => None,
+              })
+              .collect::<Option<_>>()
+              .map(ExprKind::Record)
+              .unwrap_or(ExprKind::Nil),
 
-              ExprKind::Record(record)
+            (ExprKind::Set(x), "set") => ExprKind::Set(x),
+            (ExprKind::Set(x), "list") => {
+              ExprKind::List(x.into_iter().map(Expr::from).collect())
             }
+            (ExprKind::List(x), "set") => x
+              .into_iter()
+              .map(|item| Key::try_from(item.kind).ok())
+              .collect::<Option<_>>()
+              .map(ExprKind::Set)
+              .unwrap_or(ExprKind::Nil),
 
             _ => ExprKind::Nil,
           },
@@ -790,87 +1110,21 @@ impl Intrinsic {
 
         Ok(context)
       }
-
-      // MARK: If
-      Self::If => {
-        let body = context.stack_pop(&expr)?;
-        let cond = context.stack_pop(&expr)?;
-
-        if cond.kind.is_truthy() {
-          context = engine.call_expr(context, body)?;
-        }
-
-        Ok(context)
-      }
       // MARK: Halt
       Self::Halt => Err(RunError {
         reason: RunErrorReason::Halt,
-        context,
-        expr,
+        context: Box::new(context),
+        expr: Box::new(expr),
       }),
+      // MARK: Throw
+      Self::Throw => {
+        let value = context.stack_pop(&expr)?;
 
-      // MARK: Call
-      Self::Call => {
-        let item = context.stack_pop(&expr)?;
-        engine.call_expr(context, item)
-      }
-
-      // MARK: Let
-      Self::Let => {
-        let names = context.stack_pop(&expr)?;
-        let body = context.stack_pop(&expr)?;
-
-        match names.kind {
-          ExprKind::List(x) => {
-            let x_len = x.len();
-
-            let n = x.into_iter().try_fold(
-              Vec::with_capacity(x_len),
-              |mut v, x| match x.kind {
-                ExprKind::Symbol(x) => {
-                  v.push(x);
-                  Ok(v)
-                }
-                _ => Err(RunError {
-                  reason: RunErrorReason::InvalidLet,
-                  context: context.clone(),
-                  expr: expr.clone(),
-                }),
-              },
-            )?;
-
-            let mut scope = context.scope().duplicate();
-            for name in n.into_iter().rev() {
-              let expr = context.stack_pop(&expr)?;
-              scope.define(name, expr);
-            }
-
-            if let Some(journal) = context.journal_mut() {
-              journal.commit();
-              journal.push_op(JournalOp::ScopelessFnStart(expr.info.clone()));
-            }
-
-            context.push_scope(scope);
-            context = engine.call_expr(context, body)?;
-
-            if context.journal().is_some() {
-              let scope = context.scope().clone();
-              let journal = context.journal_mut().as_mut().unwrap();
-              journal.commit();
-              journal
-                .push_op(JournalOp::FnEnd(expr.info.clone(), scope.into()));
-            }
-
-            context.pop_scope();
-
-            Ok(context)
-          }
-          _ => Err(RunError {
-            reason: RunErrorReason::InvalidLet,
-            context: context.clone(),
-            expr: expr.clone(),
-          }),
-        }
+        Err(RunError {
+          reason: RunErrorReason::Thrown,
+          context: Box::new(context),
+          expr: Box::new(value),
+        })
       }
 
       // MARK: Def
@@ -880,14 +1134,12 @@ impl Intrinsic {
 
         match name.kind {
           ExprKind::Symbol(symbol) => {
-            context.def_scope_item(symbol, value);
-
-            Ok(context)
+            context.def_scope_item(symbol, value).map(|_| context)
           }
           _ => Err(RunError {
             reason: RunErrorReason::InvalidDefinition,
-            context: context.clone(),
-            expr: expr.clone(),
+            context: Box::new(context.clone()),
+            expr: Box::new(expr.clone()),
           }),
         }
       }
@@ -903,8 +1155,8 @@ impl Intrinsic {
           }
           _ => Err(RunError {
             reason: RunErrorReason::InvalidDefinition,
-            context: context.clone(),
-            expr: expr.clone(),
+            context: Box::new(context.clone()),
+            expr: Box::new(expr.clone()),
           }),
         }
       }
@@ -920,20 +1172,52 @@ impl Intrinsic {
 
             context
               .stack_push(item.ok_or_else(|| RunError {
-                context: context.clone(),
-                expr,
+                context: Box::new(context.clone()),
+                expr: Box::new(expr),
                 reason: RunErrorReason::UnknownCall,
               })?)
               .map(|_| context)
           }
           _ => Err(RunError {
             reason: RunErrorReason::UnknownCall,
-            context: context.clone(),
-            expr: expr.clone(),
+            context: Box::new(context.clone()),
+            expr: Box::new(expr.clone()),
           }),
         }
       }
 
+      // MARK: Curry
+      Self::Curry => {
+        let func = context.stack_pop(&expr)?;
+        let arg = context.stack_pop(&expr)?;
+
+        context.stack_push(bind_args(func, vec![arg]))?;
+
+        Ok(context)
+      }
+      // MARK: Partial
+      Self::Partial => {
+        let func = context.stack_pop(&expr)?;
+        let args = context.stack_pop(&expr)?;
+
+        context.stack_push(match args.kind {
+          ExprKind::List(args) => bind_args(func, args),
+          _ => ExprKind::Nil.into(),
+        })?;
+
+        Ok(context)
+      }
+      // MARK: Capture
+      Self::Capture => {
+        let names = context.stack_pop(&expr)?;
+        let func = context.stack_pop(&expr)?;
+
+        let func = capture_values(&context, func, names)?;
+        context.stack_push(func)?;
+
+        Ok(context)
+      }
+
       // MARK: Debug
       Self::Debug => {
         if let Some(debug_hook) = engine.debug_hook() {
@@ -955,7 +1239,10 @@ impl Intrinsic {
       Self::Print => {
         let val = context.stack_pop(&expr)?;
 
-        println!("{}", val);
+        match engine.print_hook() {
+          Some(print_hook) => print_hook(val.to_string()),
+          None => println!("{}", val),
+        }
 
         Ok(context)
       }
@@ -963,7 +1250,10 @@ impl Intrinsic {
       Self::Pretty => {
         let val = context.stack_pop(&expr)?;
 
-        println!("{:#}", val);
+        match engine.print_hook() {
+          Some(print_hook) => print_hook(format!("{val:#}")),
+          None => println!("{:#}", val),
+        }
 
         Ok(context)
       }
@@ -988,49 +1278,1133 @@ impl Intrinsic {
 
         Ok(context)
       }
+      // MARK: Export
+      Self::Export => {
+        let names = context.stack_pop(&expr)?;
+        let exports = export_record(&context, names)?;
 
-      // MARK: Import
-      Self::Import => {
-        let path = context.stack_pop(&expr)?;
+        context.stack_push(exports)?;
 
-        // Imports should trigger a new commit
-        if let Some(journal) = context.journal_mut() {
-          journal.commit();
-          journal.push_op(JournalOp::ScopelessFnStart(expr.info.clone()));
+        Ok(context)
+      }
+      _ => unreachable!(),
+    }
+  }
+}
+
+/// Pops the names and body of a `let`, and binds each name to a value popped
+/// from the stack in a new [`Scope`].
+pub(crate) fn let_scope(
+  context: &mut Context,
+  expr: &Expr,
+) -> Result<(Expr, Scope), RunError> {
+  let names = context.stack_pop(expr)?;
+  let body = context.stack_pop(expr)?;
+
+  match names.kind {
+    ExprKind::List(x) => {
+      let x_len = x.len();
+
+      let n = x.into_iter().try_fold(
+        Vec::with_capacity(x_len),
+        |mut v, x| match x.kind {
+          ExprKind::Symbol(x) => {
+            v.push(x);
+            Ok(v)
+          }
+          _ => Err(RunError {
+            reason: RunErrorReason::InvalidLet,
+            context: Box::new(context.clone()),
+            expr: Box::new(expr.clone()),
+          }),
+        },
+      )?;
+
+      let mut scope = context.scope().duplicate();
+      for name in n.into_iter().rev() {
+        let expr = context.stack_pop(expr)?;
+        scope.define(name, expr);
+      }
+
+      Ok((body, scope))
+    }
+    _ => Err(RunError {
+      reason: RunErrorReason::InvalidLet,
+      context: Box::new(context.clone()),
+      expr: Box::new(expr.clone()),
+    }),
+  }
+}
+
+// The iterators call back into the engine, so they are kept out of
+// [`Intrinsic::run`] to keep its stack frame small.
+/// Calls `func` with each item, and collects the results into a list.
+fn map(
+  engine: &Engine,
+  mut context: Context,
+  expr: Expr,
+) -> Result<Context, RunError> {
+  let func = context.stack_pop(&expr)?;
+  let list = context.stack_pop(&expr)?;
+
+  match into_items(list.kind) {
+    Some((items, _)) => {
+      let mut results = Vec::with_capacity(items.len());
+      for item in items {
+        let result;
+        (context, result) = call_with(engine, context, &expr, &func, [item])?;
+        results.push(result);
+      }
+
+      context.stack_push(ExprKind::List(results).into())?;
+    }
+    None => context.stack_push(ExprKind::Nil.into())?,
+  }
+
+  Ok(context)
+}
+
+/// Keeps the items for which `func` returns `true`.
+fn filter(
+  engine: &Engine,
+  mut context: Context,
+  expr: Expr,
+) -> Result<Context, RunError> {
+  let func = context.stack_pop(&expr)?;
+  let list = context.stack_pop(&expr)?;
+
+  match into_items(list.kind) {
+    Some((items, is_string)) => {
+      let mut results = Vec::with_capacity(items.len());
+      for item in items {
+        let result;
+        (context, result) =
+          call_with(engine, context, &expr, &func, [item.clone()])?;
+
+        if result.kind.is_truthy() {
+          results.push(item);
         }
+      }
 
-        match path.kind {
-          ExprKind::String(str) => {
-            if let Ok(source) = Source::from_path(str.as_str()) {
-              context.add_source(source.clone());
-              let mut lexer = Lexer::new(source);
-              if let Ok(exprs) = parse(&mut lexer) {
-                let mut result = engine.run(context, exprs);
-
-                if let Ok(ref mut context) = result {
-                  if context.journal().is_some() {
-                    let scope = context.scope().clone();
-                    let journal = context.journal_mut().as_mut().unwrap();
-                    journal.commit();
-                    journal.push_op(JournalOp::FnEnd(
-                      expr.info.clone(),
-                      scope.into(),
-                    ));
-                  }
-                }
+      context.stack_push(from_items(results, is_string).into())?;
+    }
+    None => context.stack_push(ExprKind::Nil.into())?,
+  }
 
-                return result;
-              }
-            }
-          }
-          _ => {
-            todo!()
-          }
+  Ok(context)
+}
+
+/// Calls `func` with an accumulator and each item, starting from `init`.
+fn fold(
+  engine: &Engine,
+  mut context: Context,
+  expr: Expr,
+) -> Result<Context, RunError> {
+  let func = context.stack_pop(&expr)?;
+  let init = context.stack_pop(&expr)?;
+  let list = context.stack_pop(&expr)?;
+
+  match into_items(list.kind) {
+    Some((items, _)) => {
+      let mut acc = init;
+      for item in items {
+        (context, acc) = call_with(engine, context, &expr, &func, [acc, item])?;
+      }
+
+      context.stack_push(acc)?;
+    }
+    None => context.stack_push(ExprKind::Nil.into())?,
+  }
+
+  Ok(context)
+}
+
+/// Like `fold`, but starts from the first item.
+fn reduce(
+  engine: &Engine,
+  mut context: Context,
+  expr: Expr,
+) -> Result<Context, RunError> {
+  let func = context.stack_pop(&expr)?;
+  let list = context.stack_pop(&expr)?;
+
+  match into_items(list.kind) {
+    Some((items, _)) => {
+      let mut items = items.into_iter();
+      let mut acc = items.next().unwrap_or(ExprKind::Nil.into());
+      for item in items {
+        (context, acc) = call_with(engine, context, &expr, &func, [acc, item])?;
+      }
+
+      context.stack_push(acc)?;
+    }
+    None => context.stack_push(ExprKind::Nil.into())?,
+  }
+
+  Ok(context)
+}
+
+/// Calls `func` with each item, leaving its results on the stack.
+fn each(
+  engine: &Engine,
+  mut context: Context,
+  expr: Expr,
+) -> Result<Context, RunError> {
+  let func = context.stack_pop(&expr)?;
+  let list = context.stack_pop(&expr)?;
+
+  if let Some((items, _)) = into_items(list.kind) {
+    for item in items {
+      if let Some(journal) = context.journal_mut() {
+        journal.commit();
+      }
+
+      context.stack_push(item)?;
+      context = engine.call_expr(context, func.clone())?;
+    }
+  }
+
+  Ok(context)
+}
+
+/// Returns whether `func` returns `true` for any item.
+fn any(
+  engine: &Engine,
+  mut context: Context,
+  expr: Expr,
+) -> Result<Context, RunError> {
+  let func = context.stack_pop(&expr)?;
+  let list = context.stack_pop(&expr)?;
+
+  match into_items(list.kind) {
+    Some((items, _)) => {
+      let mut any = false;
+      for item in items {
+        let result;
+        (context, result) = call_with(engine, context, &expr, &func, [item])?;
+
+        if result.kind.is_truthy() {
+          any = true;
+          break;
         }
+      }
 
-        Ok(context)
+      context.stack_push(ExprKind::Boolean(any).into())?;
+    }
+    None => context.stack_push(ExprKind::Nil.into())?,
+  }
+
+  Ok(context)
+}
+
+/// Returns whether `func` returns `true` for every item.
+fn all(
+  engine: &Engine,
+  mut context: Context,
+  expr: Expr,
+) -> Result<Context, RunError> {
+  let func = context.stack_pop(&expr)?;
+  let list = context.stack_pop(&expr)?;
+
+  match into_items(list.kind) {
+    Some((items, _)) => {
+      let mut all = true;
+      for item in items {
+        let result;
+        (context, result) = call_with(engine, context, &expr, &func, [item])?;
+
+        if !result.kind.is_truthy() {
+          all = false;
+          break;
+        }
       }
+
+      context.stack_push(ExprKind::Boolean(all).into())?;
+    }
+    None => context.stack_push(ExprKind::Nil.into())?,
+  }
+
+  Ok(context)
+}
+
+/// Returns the first item for which `func` returns `true`.
+fn find(
+  engine: &Engine,
+  mut context: Context,
+  expr: Expr,
+) -> Result<Context, RunError> {
+  let func = context.stack_pop(&expr)?;
+  let list = context.stack_pop(&expr)?;
+
+  let mut found = ExprKind::Nil.into();
+  if let Some((items, _)) = into_items(list.kind) {
+    for item in items {
+      let result;
+      (context, result) =
+        call_with(engine, context, &expr, &func, [item.clone()])?;
+
+      if result.kind.is_truthy() {
+        found = item;
+        break;
+      }
+    }
+  }
+
+  context.stack_push(found)?;
+
+  Ok(context)
+}
+
+/// Sorts the items with [`sort_cmp`].
+fn sort(mut context: Context, expr: Expr) -> Result<Context, RunError> {
+  let list = context.stack_pop(&expr)?;
+
+  match into_items(list.kind) {
+    Some((mut items, is_string)) => {
+      items.sort_by(|lhs, rhs| sort_cmp(&lhs.kind, &rhs.kind));
+
+      context.stack_push(from_items(items, is_string).into())?;
+    }
+    None => context.stack_push(ExprKind::Nil.into())?,
+  }
+
+  Ok(context)
+}
+
+/// Sorts the items by the keys which `func` returns for them.
+fn sort_by(
+  engine: &Engine,
+  mut context: Context,
+  expr: Expr,
+) -> Result<Context, RunError> {
+  let func = context.stack_pop(&expr)?;
+  let list = context.stack_pop(&expr)?;
+
+  match into_items(list.kind) {
+    Some((items, is_string)) => {
+      let mut keyed = Vec::with_capacity(items.len());
+      for item in items {
+        let key;
+        (context, key) =
+          call_with(engine, context, &expr, &func, [item.clone()])?;
+        keyed.push((key, item));
+      }
+
+      keyed.sort_by(|(lhs, _), (rhs, _)| sort_cmp(&lhs.kind, &rhs.kind));
+      let items = keyed.into_iter().map(|(_, item)| item).collect();
+
+      context.stack_push(from_items(items, is_string).into())?;
+    }
+    None => context.stack_push(ExprKind::Nil.into())?,
+  }
+
+  Ok(context)
+}
+
+/// Pairs up the items of two lists.
+fn zip(mut context: Context, expr: Expr) -> Result<Context, RunError> {
+  let rhs = context.stack_pop(&expr)?;
+  let lhs = context.stack_pop(&expr)?;
+
+  let kind = match (into_items(lhs.kind), into_items(rhs.kind)) {
+    (Some((lhs, _)), Some((rhs, _))) => ExprKind::List(
+      lhs
+        .into_iter()
+        .zip(rhs)
+        .map(|(lhs, rhs)| ExprKind::List(vec![lhs, rhs]).into())
+        .collect(),
+    ),
+    _ => ExprKind::Nil,
+  };
+
+  context.stack_push(kind.into())?;
+
+  Ok(context)
+}
+
+/// Pairs up the items of a list with their indices.
+fn enumerate(mut context: Context, expr: Expr) -> Result<Context, RunError> {
+  let list = context.stack_pop(&expr)?;
+
+  let kind = match into_items(list.kind) {
+    Some((items, _)) => ExprKind::List(
+      items
+        .into_iter()
+        .enumerate()
+        .map(|(i, item)| {
+          debug_assert!(i <= i64::MAX as usize);
+          ExprKind::List(vec![ExprKind::Integer(i as i64).into(), item]).into()
+        })
+        .collect(),
+    ),
+    None => ExprKind::Nil,
+  };
+
+  context.stack_push(kind.into())?;
+
+  Ok(context)
+}
+
+/// Returns the integers from `start` up to, but not including, `end`.
+fn range(mut context: Context, expr: Expr) -> Result<Context, RunError> {
+  let end = context.stack_pop(&expr)?;
+  let start = context.stack_pop(&expr)?;
+
+  let (start, end) = match (start.kind, end.kind) {
+    (ExprKind::Integer(start), ExprKind::Integer(end)) => (start, end),
+    _ => {
+      context.stack_push(ExprKind::Nil.into())?;
+      return Ok(context);
+    }
+  };
+
+  // The list is checked before it's allocated, since a large one would run
+  // out of memory before it could be pushed.
+  let len = usize::try_from(i128::from(end) - i128::from(start)).unwrap_or(0);
+  let mut items = Vec::new();
+  let exceeds_limit =
+    context.limits().collection_len.is_some_and(|max| len > max);
+  if exceeds_limit || items.try_reserve_exact(len).is_err() {
+    return Err(RunError {
+      reason: RunErrorReason::CollectionLimitExceeded,
+      context: Box::new(context),
+      expr: Box::new(expr),
+    });
+  }
+
+  items.extend((start..end).map(|i| Expr::from(ExprKind::Integer(i))));
+  context.stack_push(ExprKind::List(items).into())?;
+
+  Ok(context)
+}
+
+/// Returns the items of a list, or the graphemes of a string, along with
+/// whether it was a string.
+fn into_items(kind: ExprKind) -> Option<(Vec<Expr>, bool)> {
+  match kind {
+    ExprKind::List(x) => Some((x, false)),
+    ExprKind::String(x) => Some((
+      x.graphemes(true)
+        .map(|x| ExprKind::String(x.into()).into())
+        .collect(),
+      true,
+    )),
+    _ => None,
+  }
+}
+
+/// Collects items into a list, or joins them into a string if `is_string`.
+fn from_items(items: Vec<Expr>, is_string: bool) -> ExprKind {
+  if is_string {
+    ExprKind::String(
+      items
+        .into_iter()
+        .filter_map(|item| match item.kind {
+          ExprKind::String(x) => Some(x),
+          _ => None,
+        })
+        .collect(),
+    )
+  } else {
+    ExprKind::List(items)
+  }
+}
+
+/// Pushes `args` and calls `func`, then pops the result.
+///
+/// The journal is committed before each call, so that every item of an
+/// iteration is shown as its own step.
+fn call_with<const N: usize>(
+  engine: &Engine,
+  mut context: Context,
+  expr: &Expr,
+  func: &Expr,
+  args: [Expr; N],
+) -> Result<(Context, Expr), RunError> {
+  if let Some(journal) = context.journal_mut() {
+    journal.commit();
+  }
+
+  for arg in args {
+    context.stack_push(arg)?;
+  }
+
+  let mut context = engine.call_expr(context, func.clone())?;
+  let result = context.stack_pop(expr)?;
+
+  Ok((context, result))
+}
+
+/// Pops two operands and pushes the result of `op` on them, or nil if they
+/// can't be used together.
+///
+/// When integers overflow an [`i64`], the [`ArithmeticMode`] of the engine
+/// decides whether the result wraps, fails or stays an [`ExprKind::BigInt`].
+/// Decimals which overflow always fail.
+fn arithmetic(
+  engine: &Engine,
+  mut context: Context,
+  expr: Expr,
+  op: fn(ExprKind, ExprKind) -> Result<ExprKind, (ExprKind, ExprKind)>,
+) -> Result<Context, RunError> {
+  let rhs = context.stack_pop(&expr)?;
+  let lhs = context.stack_pop(&expr)?;
+
+  let integers = matches!(
+    (&lhs.kind, &rhs.kind),
+    (ExprKind::Integer(_), ExprKind::Integer(_))
+  );
+  let kind = match op(lhs.kind, rhs.kind) {
+    Ok(ExprKind::BigInt(x)) if integers => match engine.arithmetic_mode() {
+      ArithmeticMode::Wrapping => Some(ExprKind::Integer(wrap_bigint(&x))),
+      ArithmeticMode::Checked => None,
+      ArithmeticMode::Promote => Some(ExprKind::BigInt(x)),
+    },
+    Ok(kind) => Some(kind),
+    // Decimals can't be promoted, so they always fail when they overflow.
+    Err((ExprKind::Decimal(_), ExprKind::Decimal(rhs))) if !rhs.is_zero() => {
+      None
+    }
+    Err(_) => Some(ExprKind::Nil),
+  };
+
+  let Some(kind) = kind else {
+    return Err(RunError {
+      reason: RunErrorReason::ArithmeticOverflow,
+      context: Box::new(context),
+      expr: Box::new(expr),
+    });
+  };
+
+  context.stack_push(kind.into())?;
+
+  Ok(context)
+}
+
+/// Truncates `x` to its lowest 64 bits, as a two's complement [`i64`].
+fn wrap_bigint(x: &BigInt) -> i64 {
+  let low = x.iter_u64_digits().next().unwrap_or_default();
+
+  match x.sign() {
+    Sign::Minus => low.wrapping_neg() as i64,
+    _ => low as i64,
+  }
+}
+
+/// Pops a template and the values of its placeholders, and pushes the
+/// formatted string, or nil if the template is invalid.
+///
+/// The positional placeholders are filled from the stack, in the order that
+/// the values were pushed. If there are named placeholders, they're filled
+/// from a record which is popped before the positional values.
+fn format_template(
+  mut context: Context,
+  expr: Expr,
+) -> Result<Context, RunError> {
+  let template = context.stack_pop(&expr)?;
+  let segments = match template.kind {
+    ExprKind::String(ref x) => template::parse(x),
+    _ => None,
+  };
+  let Some(segments) = segments else {
+    context.stack_push(ExprKind::Nil.into())?;
+    return Ok(context);
+  };
+
+  let placeholders = segments
+    .iter()
+    .filter_map(|segment| match segment {
+      Segment::Placeholder(x) => Some(x),
+      Segment::Text(_) => None,
+    })
+    .collect::<Vec<_>>();
+
+  // Huge widths would run out of memory before the limits of the context are
+  // checked, so they're refused first.
+  let max_width = context
+    .limits()
+    .collection_len
+    .map_or(template::MAX_WIDTH, |max| max.min(template::MAX_WIDTH));
+  if placeholders.iter().any(|x| {
+    x.spec
+      .width
+      .max(x.spec.precision)
+      .is_some_and(|width| width > max_width)
+  }) {
+    return Err(RunError {
+      reason: RunErrorReason::CollectionLimitExceeded,
+      context: Box::new(context),
+      expr: Box::new(expr),
+    });
+  }
+
+  let record = if placeholders.iter().any(|x| x.name.is_some()) {
+    match context.stack_pop(&expr)?.kind {
+      ExprKind::Record(record) => Some(record),
+      _ => {
+        context.stack_push(ExprKind::Nil.into())?;
+        return Ok(context);
+      }
+    }
+  } else {
+    None
+  };
+
+  let positional = placeholders.iter().filter(|x| x.name.is_none()).count();
+  let mut values = (0..positional)
+    .map(|_| context.stack_pop(&expr))
+    .collect::<Result<Vec<_>, _>>()?;
+  values.reverse();
+
+  let mut values = values.into_iter();
+  let mut string = String::new();
+  for segment in segments {
+    match segment {
+      Segment::Text(x) => string.push_str(&x),
+      Segment::Placeholder(Placeholder {
+        name: Some(name),
+        spec,
+        ..
+      }) => {
+        let value = record
+          .as_ref()
+          .and_then(|record| record.get(&Key::String(name.into())))
+          .map(|value| &value.kind)
+          .unwrap_or(&ExprKind::Nil);
+
+        string.push_str(&spec.apply(value));
+      }
+      Segment::Placeholder(Placeholder {
+        name: None, spec, ..
+      }) => {
+        let value = values.next().map_or(ExprKind::Nil, |value| value.kind);
+        string.push_str(&spec.apply(&value));
+      }
+    }
+  }
+
+  context.stack_push(ExprKind::String(string.into()).into())?;
+
+  Ok(context)
+}
+
+/// How [`combine`] combines two collections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Combine {
+  /// Keeps the keys in either collection.
+  Union,
+  /// Keeps the keys in both collections.
+  Intersection,
+  /// Keeps the keys in the first collection which aren't in the second.
+  Difference,
+}
+
+/// Pops two sets or two records and pushes them combined, or nil if they
+/// aren't the same kind of collection.
+///
+/// The order of the first collection is kept, and the union of two records
+/// takes the values of the second.
+fn combine(
+  mut context: Context,
+  expr: Expr,
+  combine: Combine,
+) -> Result<Context, RunError> {
+  let rhs = context.stack_pop(&expr)?;
+  let lhs = context.stack_pop(&expr)?;
+
+  let kind = match (lhs.kind, rhs.kind) {
+    (ExprKind::Set(mut lhs), ExprKind::Set(rhs)) => {
+      match combine {
+        Combine::Union => lhs.extend(rhs),
+        Combine::Intersection => lhs.retain(|x| rhs.contains(x)),
+        Combine::Difference => lhs.retain(|x| !rhs.contains(x)),
+      }
+
+      ExprKind::Set(lhs)
+    }
+    (ExprKind::Record(mut lhs), ExprKind::Record(rhs)) => {
+      match combine {
+        Combine::Union => lhs.extend(rhs),
+        Combine::Intersection => lhs.retain(|x, _| rhs.contains_key(x)),
+        Combine::Difference => lhs.retain(|x, _| !rhs.contains_key(x)),
+      }
+
+      ExprKind::Record(lhs)
+    }
+    _ => ExprKind::Nil,
+  };
+
+  context.stack_push(kind.into())?;
+
+  Ok(context)
+}
+
+/// Orders items for `sort`.
+///
+/// Items are ordered by their type first, so that every item can be compared.
+/// Numbers are compared by value, and strings are compared by their bytes.
+fn sort_cmp(lhs: &ExprKind, rhs: &ExprKind) -> Ordering {
+  fn rank(kind: &ExprKind) -> u8 {
+    match kind {
+      ExprKind::Nil => 0,
+      ExprKind::Boolean(_) => 1,
+      ExprKind::Integer(_)
+      | ExprKind::BigInt(_)
+      | ExprKind::Float(_)
+      | ExprKind::Decimal(_) => 2,
+      ExprKind::String(_) => 3,
+      _ => 4,
+    }
+  }
+
+  match (lhs, rhs) {
+    (ExprKind::Boolean(lhs), ExprKind::Boolean(rhs)) => lhs.cmp(rhs),
+    (ExprKind::Integer(lhs), ExprKind::Integer(rhs)) => lhs.cmp(rhs),
+    (ExprKind::Float(lhs), ExprKind::Float(rhs)) => lhs.total_cmp(rhs),
+    (ExprKind::Integer(lhs), ExprKind::Float(rhs)) => {
+      (*lhs as f64).total_cmp(rhs)
+    }
+    (ExprKind::Float(lhs), ExprKind::Integer(rhs)) => {
+      lhs.total_cmp(&(*rhs as f64))
+    }
+    (ExprKind::BigInt(_), ExprKind::Integer(_) | ExprKind::BigInt(_))
+    | (ExprKind::Integer(_), ExprKind::BigInt(_)) => {
+      lhs.partial_cmp(rhs).unwrap_or(Ordering::Equal)
+    }
+    (ExprKind::BigInt(lhs), ExprKind::Float(rhs)) => {
+      lhs.to_f64().unwrap_or(f64::NAN).total_cmp(rhs)
+    }
+    (ExprKind::Float(lhs), ExprKind::BigInt(rhs)) => {
+      lhs.total_cmp(&rhs.to_f64().unwrap_or(f64::NAN))
+    }
+    (ExprKind::Decimal(_), ExprKind::Integer(_) | ExprKind::Decimal(_))
+    | (ExprKind::Integer(_), ExprKind::Decimal(_)) => {
+      lhs.partial_cmp(rhs).unwrap_or(Ordering::Equal)
+    }
+    (ExprKind::Decimal(lhs), ExprKind::Float(rhs)) => {
+      lhs.to_f64().unwrap_or(f64::NAN).total_cmp(rhs)
+    }
+    (ExprKind::Float(lhs), ExprKind::Decimal(rhs)) => {
+      lhs.total_cmp(&rhs.to_f64().unwrap_or(f64::NAN))
+    }
+    (ExprKind::String(lhs), ExprKind::String(rhs)) => lhs.cmp(rhs),
+    _ => rank(lhs).cmp(&rank(rhs)),
+  }
+}
+
+/// Pops the cases and value of a `match`, and binds the names of the first
+/// pattern which matches the value in a new [`Scope`].
+///
+/// The cases are a list of pairs, each of a pattern and the body to run if it
+/// matches.
+pub(crate) fn match_scope(
+  context: &mut Context,
+  expr: &Expr,
+) -> Result<(Expr, Scope), RunError> {
+  let cases = context.stack_pop(expr)?;
+  let value = context.stack_pop(expr)?;
+
+  let cases = match cases.kind {
+    ExprKind::List(x) if x.len() % 2 == 0 => x,
+    _ => {
+      return Err(RunError {
+        reason: RunErrorReason::InvalidMatch,
+        context: Box::new(context.clone()),
+        expr: Box::new(expr.clone()),
+      })
+    }
+  };
+
+  let mut bindings = Vec::new();
+  for case in cases.chunks_exact(2) {
+    if match_pattern(&case[0], &value, &mut bindings) {
+      let mut scope = context.scope().duplicate();
+      for (name, expr) in bindings {
+        scope.define(name, expr);
+      }
+
+      return Ok((case[1].clone(), scope));
+    }
+
+    bindings.clear();
+  }
+
+  Err(RunError {
+    reason: RunErrorReason::NoMatch,
+    context: Box::new(context.clone()),
+    expr: Box::new(expr.clone()),
+  })
+}
+
+/// Returns whether `value` matches `pattern`, adding the names which it binds
+/// to `bindings`.
+///
+/// - `_` matches anything.
+/// - A symbol matches anything, and binds it to that name.
+/// - A lazy item matches itself, such as `'a` for the symbol `a`.
+/// - An s-expression such as `(integer x)` matches items of that type, along
+///   with the optional pattern inside it.
+/// - A list matches lists of the same length whose items match, and `& x`
+///   binds the rest of the items to the pattern `x`.
+/// - A record matches records which have each of its keys, whose values match.
+/// - Anything else matches items which are equal to it.
+fn match_pattern(
+  pattern: &Expr,
+  value: &Expr,
+  bindings: &mut Vec<(Symbol, Expr)>,
+) -> bool {
+  match (&pattern.kind, &value.kind) {
+    (ExprKind::Underscore, _) => true,
+    (ExprKind::Symbol(name), _) => {
+      bindings.push((*name, value.clone()));
+      true
+    }
+    (ExprKind::Lazy(x), _) => x.kind == value.kind,
+    (ExprKind::SExpr { call, body }, _) => match body.as_slice() {
+      [] => call.as_str() == value.kind.type_of(),
+      [x] => {
+        call.as_str() == value.kind.type_of()
+          && match_pattern(x, value, bindings)
+      }
+      _ => false,
+    },
+    (ExprKind::List(patterns), ExprKind::List(values)) => {
+      let rest = Symbol::from_ref("&");
+      let (patterns, rest) = match patterns
+        .iter()
+        .position(|x| x.kind == ExprKind::Symbol(rest))
+      {
+        Some(i) if i + 2 == patterns.len() => {
+          (&patterns[..i], Some(&patterns[i + 1]))
+        }
+        Some(_) => return false,
+        None => (patterns.as_slice(), None),
+      };
+
+      let is_len_valid = match rest {
+        Some(_) => values.len() >= patterns.len(),
+        None => values.len() == patterns.len(),
+      };
+
+      is_len_valid
+        && patterns
+          .iter()
+          .zip(values)
+          .all(|(pattern, value)| match_pattern(pattern, value, bindings))
+        && rest.is_none_or(|rest| {
+          let values = values[patterns.len()..].to_vec();
+          match_pattern(rest, &ExprKind::List(values).into(), bindings)
+        })
+    }
+    (ExprKind::Record(patterns), ExprKind::Record(values)) => {
+      patterns.iter().all(|(key, pattern)| {
+        values
+          .get(key)
+          .is_some_and(|value| match_pattern(pattern, value, bindings))
+      })
+    }
+    (pattern, value) => pattern == value,
+  }
+}
+
+/// Returns a record of the items in scope which are called `names`.
+fn export_record(context: &Context, names: Expr) -> Result<Expr, RunError> {
+  let names = match names.kind {
+    ExprKind::List(names)
+      if names
+        .iter()
+        .all(|name| matches!(name.kind, ExprKind::Symbol(_))) =>
+    {
+      names
+    }
+    _ => return Ok(ExprKind::Nil.into()),
+  };
+
+  let mut record = IndexMap::with_capacity(names.len());
+  for name in names {
+    let ExprKind::Symbol(symbol) = name.kind else {
+      unreachable!()
+    };
+
+    let item = context.scope_item(symbol).ok_or_else(|| RunError {
+      reason: RunErrorReason::UnknownCall,
+      context: Box::new(context.clone()),
+      expr: Box::new(name),
+    })?;
+    record.insert(Key::Symbol(symbol), item);
+  }
+
+  Ok(ExprKind::Record(record).into())
+}
+
+/// Returns `func` with `args` pushed at the start of its body, as if they had
+/// been pushed before it was called, or nil if it isn't a function.
+fn bind_args(func: Expr, args: Vec<Expr>) -> Expr {
+  let ExprKind::Function { scope, body } = func.kind else {
+    return ExprKind::Nil.into();
+  };
+
+  // Each arg is made lazy, so that it's pushed as it is rather than called.
+  let body = args
+    .into_iter()
+    .map(|arg| ExprKind::Lazy(Box::new(arg)).into())
+    .chain(body)
+    .collect();
+
+  // The info is dropped, since it no longer describes the body.
+  ExprKind::Function { scope, body }.into()
+}
+
+/// Returns `func` with the current values of `names` copied into its scope,
+/// so that it doesn't see any later sets of them.
+///
+/// Returns nil if `func` isn't a scoped function or if `names` isn't a list of
+/// symbols.
+fn capture_values(
+  context: &Context,
+  func: Expr,
+  names: Expr,
+) -> Result<Expr, RunError> {
+  let ExprKind::Function {
+    scope: FnScope::Scoped(mut scope),
+    body,
+  } = func.kind
+  else {
+    return Ok(ExprKind::Nil.into());
+  };
+
+  let ExprKind::Record(values) = export_record(context, names)?.kind else {
+    return Ok(ExprKind::Nil.into());
+  };
+
+  for (name, value) in values {
+    let Key::Symbol(name) = name else {
+      unreachable!()
+    };
+
+    scope.capture(name, value);
+  }
+
+  Ok(Expr {
+    kind: ExprKind::Function {
+      scope: FnScope::Scoped(scope),
+      body,
+    },
+    info: func.info,
+  })
+}
+
+/// Pops the path and name of a `use`, and defines the name as the exports of
+/// the file at that path.
+///
+/// Each file is only run the first time that it's used.
+fn use_module(
+  engine: &Engine,
+  mut context: Context,
+  expr: Expr,
+) -> Result<Context, RunError> {
+  let name = context.stack_pop(&expr)?;
+  let path = context.stack_pop(&expr)?;
+
+  let (ExprKind::Symbol(name), ExprKind::String(path)) = (name.kind, path.kind)
+  else {
+    return Err(RunError {
+      reason: RunErrorReason::InvalidImport,
+      context: Box::new(context),
+      expr: Box::new(expr),
+    });
+  };
+  let path = resolve_import(engine, &context, &expr, &path)?;
+
+  let exports = match context.file_module(&path) {
+    Some(exports) => exports.clone(),
+    None => {
+      let exports;
+      (context, exports) = load_module(engine, context, &expr, &path)?;
+      context.add_file_module(path, exports.clone());
+
+      exports
+    }
+  };
+
+  context.def_scope_item(name, exports)?;
+
+  Ok(context)
+}
+
+/// Runs the file at `path` in a new [`Scope`], and returns the record which
+/// it exports.
+///
+/// The record is the item which the file leaves on top of the stack, such as
+/// with `export`. Anything else which it pushes is dropped.
+fn load_module(
+  engine: &Engine,
+  mut context: Context,
+  expr: &Expr,
+  path: &Path,
+) -> Result<(Context, Expr), RunError> {
+  let exprs = parse_import(&mut context, expr, path)?;
+  context.enter_import(path.to_path_buf(), expr)?;
+
+  if let Some(journal) = context.journal_mut() {
+    journal.commit();
+    journal.push_op(JournalOp::ScopelessFnStart(expr.info.clone()));
+  }
+
+  let stack_len = context.stack().len();
+  context.push_scope(Scope::new());
+  context.enter_call(expr)?;
+  context = engine.run(context, exprs).map_err(exit_import)?;
+  context.exit_call();
+  context.exit_import();
+
+  let exports = if context.stack().len() > stack_len {
+    context.stack_pop(expr)?
+  } else {
+    ExprKind::Nil.into()
+  };
+  while context.stack().len() > stack_len {
+    context.stack_pop(expr)?;
+  }
+
+  if context.journal().is_some() {
+    let scope = context.scope().clone();
+    let journal = context.journal_mut().as_mut().unwrap();
+    journal.commit();
+    journal.push_op(JournalOp::FnEnd(expr.info.clone(), scope.into()));
+  }
+
+  context.pop_scope();
+
+  match exports.kind {
+    ExprKind::Record(_) => Ok((context, exports)),
+    _ => Err(RunError {
+      reason: RunErrorReason::InvalidImport,
+      context: Box::new(context),
+      expr: Box::new(expr.clone()),
+    }),
+  }
+}
+
+/// Pops the path of an `import`, and runs the file at that path in the
+/// current scope.
+fn import_file(
+  engine: &Engine,
+  mut context: Context,
+  expr: Expr,
+) -> Result<Context, RunError> {
+  let path = context.stack_pop(&expr)?;
+  let ExprKind::String(path) = path.kind else {
+    return Err(RunError {
+      reason: RunErrorReason::InvalidImport,
+      context: Box::new(context),
+      expr: Box::new(expr),
+    });
+  };
+
+  let path = resolve_import(engine, &context, &expr, &path)?;
+  let exprs = parse_import(&mut context, &expr, &path)?;
+  context.enter_import(path, &expr)?;
+
+  // Imports should trigger a new commit
+  if let Some(journal) = context.journal_mut() {
+    journal.commit();
+    journal.push_op(JournalOp::ScopelessFnStart(expr.info.clone()));
+  }
+
+  context.enter_call(&expr)?;
+  context = engine.run(context, exprs).map_err(exit_import)?;
+  context.exit_call();
+  context.exit_import();
+
+  if context.journal().is_some() {
+    let scope = context.scope().clone();
+    let journal = context.journal_mut().as_mut().unwrap();
+    journal.commit();
+    journal.push_op(JournalOp::FnEnd(expr.info.clone(), scope.into()));
+  }
+
+  Ok(context)
+}
+
+/// Resolves the `path` of an import relative to the file which `expr` is in,
+/// returning the canonical path of the file.
+fn resolve_import(
+  engine: &Engine,
+  context: &Context,
+  expr: &Expr,
+  path: &str,
+) -> Result<PathBuf, RunError> {
+  engine
+    .resolve_path(expr.info.as_ref().map(|info| &info.source), Path::new(path))
+    .and_then(|path| path.canonicalize().ok())
+    .ok_or_else(|| RunError {
+      reason: RunErrorReason::FileNotFound(path.into()),
+      context: Box::new(context.clone()),
+      expr: Box::new(expr.clone()),
+    })
+}
+
+/// Reads and parses the file at `path`, adding it to the sources of the
+/// [`Context`].
+fn parse_import(
+  context: &mut Context,
+  expr: &Expr,
+  path: &Path,
+) -> Result<Vec<Expr>, RunError> {
+  let Ok(source) = Source::from_path(path) else {
+    return Err(RunError {
+      reason: RunErrorReason::FileNotFound(path.to_path_buf()),
+      context: Box::new(context.clone()),
+      expr: Box::new(expr.clone()),
+    });
+  };
+
+  context.add_source(source.clone());
+  parse(&mut Lexer::new(source)).map_err(|err| RunError {
+    reason: RunErrorReason::ParseError(err),
+    context: Box::new(context.clone()),
+    expr: Box::new(expr.clone()),
+  })
+}
+
+/// Leaves the innermost import of the [`Context`] of an error, so that the
+/// import stack is balanced if the error is caught.
+fn exit_import(mut err: RunError) -> RunError {
+  err.context.exit_import();
+  err
+}
+
+/// Runs `body`, recovering from any catchable [`RunError`].
+///
+/// When an error is caught, the stack and scopes are restored to how they were
+/// before `body` ran, and the error is returned as a record.
+fn catch_expr(
+  engine: &Engine,
+  context: Context,
+  body: Expr,
+) -> Result<(Context, Option<Expr>), RunError> {
+  let stack = context.stack().to_vec();
+  let scope_depth = context.scope_depth();
+  let call_depth = context.call_depth();
+
+  match engine.call_expr(context, body) {
+    Ok(context) => Ok((context, None)),
+    Err(err) if err.reason.is_catchable() => {
+      let record = err.to_record();
+      let mut context = err.context;
+
+      *context.stack_mut() = stack;
+      context.truncate_scopes(scope_depth);
+      context.set_call_depth(call_depth);
+
+      if let Some(journal) = context.journal_mut() {
+        journal.commit();
+      }
+
+      Ok((*context, Some(record)))
     }
+    Err(err) => Err(err),
   }
 }
 
@@ -50,11 +50,15 @@ pub enum TokenKind {
   // Or, instead, add stricter syntax for both `:` and `,` within records
   LeftCurly,
   RightCurly,
+  HashCurly,
   LeftSquare,
   RightSquare,
   Integer,
   Float,
+  Decimal,
   String,
+  Bytes,
+  FormatString,
   Symbol,
 }
 
@@ -69,22 +73,27 @@ impl fmt::Display for TokenKind {
       Self::RightParen => write!(f, ")"),
       Self::LeftCurly => write!(f, "{{"),
       Self::RightCurly => write!(f, "}}"),
+      Self::HashCurly => write!(f, "#{{"),
       Self::LeftSquare => write!(f, "["),
       Self::RightSquare => write!(f, "]"),
       Self::Integer => write!(f, "an integer literal"),
       Self::Float => write!(f, "a float literal"),
+      Self::Decimal => write!(f, "a decimal literal"),
       Self::String => write!(f, "a string literal"),
+      Self::Bytes => write!(f, "a bytes literal"),
+      Self::FormatString => write!(f, "a format string literal"),
       Self::Symbol => write!(f, "a symbol literal"),
     }
   }
 }
 
 /// Converts a <code>&[str]</code> into a stream of [`Token`]s.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Lexer {
   source: Source,
   cursor: usize,
   peeked: Option<Token>,
+  comments: Vec<Span>,
 }
 
 impl Lexer {
@@ -101,6 +110,7 @@ impl Lexer {
         .unwrap_or(0),
       source,
       peeked: None,
+      comments: Vec::new(),
     }
   }
 
@@ -110,6 +120,13 @@ impl Lexer {
     self.source.clone()
   }
 
+  /// Returns the [`Span`]s of the comments which have been lexed so far,
+  /// including their leading `;`.
+  #[inline]
+  pub fn comments(&self) -> &[Span] {
+    &self.comments
+  }
+
   /// Returns the next [`Token`] in the stream without consuming it.
   #[inline]
   pub fn peek(&mut self) -> Token {
@@ -211,6 +228,17 @@ impl Lexer {
               },
             };
           }
+          '#' if source[self.cursor + c_len..].starts_with('{') => {
+            self.cursor += c_len + '{'.len_utf8();
+
+            break Token {
+              kind: TokenKind::HashCurly,
+              span: Span {
+                start,
+                end: self.cursor,
+              },
+            };
+          }
           '[' => {
             self.cursor += c_len;
 
@@ -250,6 +278,7 @@ impl Lexer {
           | '<'
           | '>'
           | '?'
+          | '&'
           | 'a'..='z'
           | 'A'..='Z' => state = State::Symbol,
           // TODO: Square brackets should be checked in the parsing step.
@@ -270,11 +299,21 @@ impl Lexer {
         },
         State::Comment => match c {
           '\0' => {
+            self.comments.push(Span {
+              start,
+              end: self.cursor,
+            });
+
             state = State::Start;
             start = self.cursor;
             self.cursor -= c_len;
           }
           '\n' => {
+            self.comments.push(Span {
+              start,
+              end: self.cursor,
+            });
+
             state = State::Start;
             start = self.cursor + c_len;
           }
@@ -309,6 +348,17 @@ impl Lexer {
         State::Integer => match c {
           '0'..='9' => {}
           '.' => state = State::Float,
+          'd' => {
+            self.cursor += c_len;
+
+            break Token {
+              kind: TokenKind::Decimal,
+              span: Span {
+                start,
+                end: self.cursor,
+              },
+            };
+          }
           _ => {
             break Token {
               kind: TokenKind::Integer,
@@ -321,6 +371,17 @@ impl Lexer {
         },
         State::Float => match c {
           '0'..='9' => {}
+          'd' => {
+            self.cursor += c_len;
+
+            break Token {
+              kind: TokenKind::Decimal,
+              span: Span {
+                start,
+                end: self.cursor,
+              },
+            };
+          }
           _ => {
             break Token {
               kind: TokenKind::Float,
@@ -354,8 +415,16 @@ impl Lexer {
           '"' => {
             self.cursor += c_len;
 
+            // Bytes and format string literals share the string states, after
+            // their `b` or `f` prefix.
             break Token {
-              kind: TokenKind::String,
+              kind: if source[start..].starts_with('b') {
+                TokenKind::Bytes
+              } else if source[start..].starts_with('f') {
+                TokenKind::FormatString
+              } else {
+                TokenKind::String
+              },
               span: Span {
                 start,
                 end: self.cursor,
@@ -377,6 +446,9 @@ impl Lexer {
           _ => state = State::String,
         },
         State::Symbol => match c {
+          '"' if matches!(&source[start..self.cursor], "b" | "f") => {
+            state = State::String
+          }
           '_'
           | '+'
           | '-'
@@ -389,6 +461,7 @@ impl Lexer {
           | '<'
           | '>'
           | '?'
+          | '&'
           | 'a'..='z'
           | 'A'..='Z'
           | '0'..='9' => {}
@@ -443,12 +516,15 @@ mod test {
   #[case("'" => vec![Token { kind: TokenKind::Apostrophe, span: Span { start: 0, end: 1 } }, Token { kind: TokenKind::Eof, span: Span { start: 1, end: 1 } }] ; "apostrophe")]
   #[case("(" => vec![Token { kind: TokenKind::LeftParen, span: Span { start: 0, end: 1 } }, Token { kind: TokenKind::Eof, span: Span { start: 1, end: 1 } }] ; "left paren")]
   #[case(")" => vec![Token { kind: TokenKind::RightParen, span: Span { start: 0, end: 1 } }, Token { kind: TokenKind::Eof, span: Span { start: 1, end: 1 } }] ; "right paren")]
+  #[case("#{" => vec![Token { kind: TokenKind::HashCurly, span: Span { start: 0, end: 2 } }, Token { kind: TokenKind::Eof, span: Span { start: 2, end: 2 } }] ; "hash curly")]
   // #[case("[" => vec![Token { kind: TokenKind::LeftSquare, span: Span { start: 0, end: 1 } }, Token { kind: TokenKind::Eof, span: Span { start: 1, end: 1 } }] ; "left square")]
   // #[case("]" => vec![Token { kind: TokenKind::RightSquare, span: Span { start: 0, end: 1 } }, Token { kind: TokenKind::Eof, span: Span { start: 1, end: 1 } }] ; "right square")]
   #[case("123" => vec![Token { kind: TokenKind::Integer, span: Span { start: 0, end: 3 } }, Token { kind: TokenKind::Eof, span: Span { start: 3, end: 3 } }] ; "integer")]
   #[case("-123" => vec![Token { kind: TokenKind::Integer, span: Span { start: 0, end: 4 } }, Token { kind: TokenKind::Eof, span: Span { start: 4, end: 4 } }] ; "negative integer")]
   #[case("1.2" => vec![Token { kind: TokenKind::Float, span: Span { start: 0, end: 3 } }, Token { kind: TokenKind::Eof, span: Span { start: 3, end: 3 } }] ; "float")]
   #[case("-1.2" => vec![Token { kind: TokenKind::Float, span: Span { start: 0, end: 4 } }, Token { kind: TokenKind::Eof, span: Span { start: 4, end: 4 } }] ; "negative float")]
+  #[case("1.10d" => vec![Token { kind: TokenKind::Decimal, span: Span { start: 0, end: 5 } }, Token { kind: TokenKind::Eof, span: Span { start: 5, end: 5 } }] ; "decimal")]
+  #[case("-5d" => vec![Token { kind: TokenKind::Decimal, span: Span { start: 0, end: 3 } }, Token { kind: TokenKind::Eof, span: Span { start: 3, end: 3 } }] ; "negative integer decimal")]
   #[case("hello" => vec![Token { kind: TokenKind::Symbol, span: Span { start: 0, end: 5 } }, Token { kind: TokenKind::Eof, span: Span { start: 5, end: 5 } }] ; "symbol")]
   #[case("h3l10" => vec![Token { kind: TokenKind::Symbol, span: Span { start: 0, end: 5 } }, Token { kind: TokenKind::Eof, span: Span { start: 5, end: 5 } }] ; "alphanumeric symbol")]
   #[case("he_lo" => vec![Token { kind: TokenKind::Symbol, span: Span { start: 0, end: 5 } }, Token { kind: TokenKind::Eof, span: Span { start: 5, end: 5 } }] ; "underscore symbol")]
@@ -460,10 +536,14 @@ mod test {
   #[case("<" => vec![Token { kind: TokenKind::Symbol, span: Span { start: 0, end: 1 } }, Token { kind: TokenKind::Eof, span: Span { start: 1, end: 1 } }] ; "left angle symbol only")]
   #[case(">" => vec![Token { kind: TokenKind::Symbol, span: Span { start: 0, end: 1 } }, Token { kind: TokenKind::Eof, span: Span { start: 1, end: 1 } }] ; "right angle symbol only")]
   #[case("?" => vec![Token { kind: TokenKind::Symbol, span: Span { start: 0, end: 1 } }, Token { kind: TokenKind::Eof, span: Span { start: 1, end: 1 } }] ; "question symbol only")]
+  #[case("&" => vec![Token { kind: TokenKind::Symbol, span: Span { start: 0, end: 1 } }, Token { kind: TokenKind::Eof, span: Span { start: 1, end: 1 } }] ; "ampersand symbol only")]
   #[case("nil" => vec![Token { kind: TokenKind::Symbol, span: Span { start: 0, end: 3 } }, Token { kind: TokenKind::Eof, span: Span { start: 3, end: 3 } }] ; "nil")]
   #[case("fn" => vec![Token { kind: TokenKind::Symbol, span: Span { start: 0, end: 2 } }, Token { kind: TokenKind::Eof, span: Span { start: 2, end: 2 } }] ; "fn_")]
   #[case("fn!" => vec![Token { kind: TokenKind::Symbol, span: Span { start: 0, end: 3 } }, Token { kind: TokenKind::Eof, span: Span { start: 3, end: 3 } }] ; "fn exclamation")]
   #[case("\"hello\"" => vec![Token { kind: TokenKind::String, span: Span { start: 0, end: 7 } }, Token { kind: TokenKind::Eof, span: Span { start: 7, end: 7 } }] ; "string")]
+  #[case("b\"a\\x00\"" => vec![Token { kind: TokenKind::Bytes, span: Span { start: 0, end: 8 } }, Token { kind: TokenKind::Eof, span: Span { start: 8, end: 8 } }] ; "bytes")]
+  #[case("ab\"a\"" => vec![Token { kind: TokenKind::Symbol, span: Span { start: 0, end: 2 } }, Token { kind: TokenKind::String, span: Span { start: 2, end: 5 } }, Token { kind: TokenKind::Eof, span: Span { start: 5, end: 5 } }] ; "symbol before string")]
+  #[case("f\"{a}\"" => vec![Token { kind: TokenKind::FormatString, span: Span { start: 0, end: 6 } }, Token { kind: TokenKind::Eof, span: Span { start: 6, end: 6 } }] ; "format string")]
   fn lexer(source: &str) -> Vec<Token> {
     let source = Source::new("", source);
     let mut lexer = Lexer::new(source);
@@ -1,7 +1,11 @@
 pub mod chain;
+pub mod checker;
+pub mod compiler;
 pub mod context;
 pub mod engine;
+pub mod execution;
 pub mod expr;
+pub mod formatter;
 pub mod intrinsic;
 pub mod journal;
 pub mod lexer;
@@ -10,19 +14,25 @@ pub mod parser;
 pub mod scope;
 pub mod source;
 pub mod symbol;
+pub mod template;
+pub mod vm;
 
 pub mod prelude {
   //! Re-exports commonly used items.
 
   use super::*;
 
-  pub use context::Context;
-  pub use engine::{Engine, RunError, RunErrorReason};
-  pub use expr::{Error, Expr, ExprInfo, ExprKind};
+  pub use context::{Context, Limits};
+  pub use engine::{
+    ArithmeticMode, Backend, CancellationToken, Engine, RunError,
+    RunErrorReason,
+  };
+  pub use execution::Execution;
+  pub use expr::{Error, Expr, ExprInfo, ExprKind, Key};
   pub use intrinsic::Intrinsic;
   pub use lexer::Lexer;
   pub use module::Module;
-  pub use parser::{parse, ParseError, ParseErrorKind};
+  pub use parser::{parse, parse_recovering, ParseError, ParseErrorKind};
   pub use source::Source;
   pub use symbol::Symbol;
 }
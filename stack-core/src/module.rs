@@ -47,4 +47,10 @@ impl Module {
   pub fn func(&self, name: Symbol) -> Option<Func> {
     self.funcs.get(&name).cloned()
   }
+
+  /// Returns the names of the functions, in no particular order.
+  #[inline]
+  pub fn func_names(&self) -> impl Iterator<Item = Symbol> + '_ {
+    self.funcs.keys().copied()
+  }
 }
@@ -1,260 +1,526 @@
 use compact_str::ToCompactString;
 use core::fmt;
+use indexmap::{IndexMap, IndexSet};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
 use crate::{
-  expr::{Expr, ExprInfo, ExprKind, FnScope},
+  expr::{Expr, ExprInfo, ExprKind, FnScope, Key},
+  intrinsic::Intrinsic,
   lexer::{Lexer, Span, Token, TokenKind},
   scope::Scope,
   source::{Location, Source},
   symbol::Symbol,
+  template::{self, Placeholder, Segment},
 };
 
 pub fn parse(lexer: &mut Lexer) -> Result<Vec<Expr>, ParseError> {
+  let (exprs, mut errors) = parse_recovering(lexer);
+
+  if errors.is_empty() {
+    Ok(exprs)
+  } else {
+    Err(errors.swap_remove(0))
+  }
+}
+
+/// Parses as much as possible, returning the [`Expr`]s along with every
+/// [`ParseError`], ordered by where they occur.
+///
+/// Unexpected tokens are skipped. A group which is closed by the bracket of an
+/// outer group is closed there, and a group which is never closed ends, along
+/// with the groups within it, at the end of the line that it starts on.
+pub fn parse_recovering(lexer: &mut Lexer) -> (Vec<Expr>, Vec<ParseError>) {
+  let mut parser = Parser {
+    source: lexer.source(),
+    lexer,
+    errors: Vec::new(),
+    closers: Vec::new(),
+    line_end: None,
+  };
   let mut exprs = Vec::new();
 
   loop {
-    let token = lexer.peek();
-
-    match token.kind {
-      TokenKind::Eof => break Ok(exprs),
-      _ => exprs.push(parse_expr(lexer)?),
+    match parser.lexer.peek().kind {
+      TokenKind::Eof => break,
+      _ => exprs.extend(parser.expr()),
     }
   }
+
+  let mut errors = parser.errors;
+  errors.sort_by_key(|err| err.kind.span().start);
+
+  (exprs, errors)
 }
 
-fn parse_expr(lexer: &mut Lexer) -> Result<Expr, ParseError> {
-  let source = lexer.source();
-  let token = lexer.next();
-
-  match token.kind {
-    TokenKind::Invalid
-    | TokenKind::Eof
-    | TokenKind::RightSquare
-    | TokenKind::RightParen
-    | TokenKind::RightCurly => Err(ParseError {
-      source,
-      kind: ParseErrorKind::UnexpectedToken(token),
-    }),
-
-    TokenKind::Apostrophe => {
-      let next_token = lexer.peek();
-      let expr = parse_expr(lexer)?;
-
-      Ok(Expr {
-        kind: ExprKind::Lazy(Box::new(expr)),
-        info: Some(ExprInfo {
-          source,
-          span: Span {
-            start: token.span.start,
-            end: next_token.span.end,
-          },
-        }),
-      })
-    }
-    TokenKind::LeftSquare => {
-      let (list, end_span) = parse_list(lexer)?;
+/// How a group of expressions ended.
+enum GroupEnd {
+  /// By its closing bracket, with its span.
+  Closed(Span),
+  /// By the closing bracket of an outer group.
+  Outer,
+  /// By the end of the line which it starts on.
+  Line,
+  Eof,
+}
 
-      Ok(Expr {
-        kind: ExprKind::List(list),
-        info: Some(ExprInfo {
-          source,
-          span: Span {
-            start: token.span.start,
-            end: end_span.end,
-          },
-        }),
-      })
-    }
-    TokenKind::LeftParen => {
-      let (list, end_span) = parse_parenthetical(lexer)?;
-
-      let kind = if let Some(Expr {
-        kind: ExprKind::Symbol(symbol),
-        ..
-      }) = list.first()
-      {
-        let str = symbol.as_str();
-        if str == "fn" {
-          ExprKind::Function {
-            scope: FnScope::Scoped(Scope::new()),
-            body: list.into_iter().skip(1).collect(),
-          }
-        } else if str == "fn!" {
-          ExprKind::Function {
-            scope: FnScope::Scopeless,
-            body: list.into_iter().skip(1).collect(),
+struct Parser<'a> {
+  lexer: &'a mut Lexer,
+  source: Source,
+  errors: Vec<ParseError>,
+  /// The closing brackets of the groups which are open.
+  closers: Vec<TokenKind>,
+  /// The end of the line which an unclosed group is being recovered up to,
+  /// where every group within it ends too.
+  line_end: Option<usize>,
+}
+
+impl Parser<'_> {
+  /// Parses the next expression, returning [`None`] if it's invalid.
+  fn expr(&mut self) -> Option<Expr> {
+    let source = self.source.clone();
+    let token = self.lexer.next();
+
+    match token.kind {
+      TokenKind::Invalid
+      | TokenKind::Eof
+      | TokenKind::RightSquare
+      | TokenKind::RightParen
+      | TokenKind::RightCurly => {
+        self.error(ParseErrorKind::UnexpectedToken(token));
+        None
+      }
+
+      TokenKind::Apostrophe => {
+        let next_token = self.lexer.peek();
+        if self.is_group_end(next_token) {
+          self.error(ParseErrorKind::UnexpectedToken(next_token));
+          return None;
+        }
+
+        let expr = self.expr()?;
+
+        Some(Expr {
+          kind: ExprKind::Lazy(Box::new(expr)),
+          info: Some(ExprInfo {
+            source,
+            span: Span {
+              start: token.span.start,
+              end: next_token.span.end,
+            },
+          }),
+        })
+      }
+      TokenKind::LeftSquare => {
+        let (list, end_span) = self.group(token, TokenKind::RightSquare);
+
+        Some(Expr {
+          kind: ExprKind::List(list),
+          info: Some(ExprInfo {
+            source,
+            span: Span {
+              start: token.span.start,
+              end: end_span.end,
+            },
+          }),
+        })
+      }
+      TokenKind::LeftParen => {
+        let (list, end_span) = self.group(token, TokenKind::RightParen);
+
+        let kind = if let Some(Expr {
+          kind: ExprKind::Symbol(symbol),
+          ..
+        }) = list.first()
+        {
+          let str = symbol.as_str();
+          if str == "fn" {
+            ExprKind::Function {
+              scope: FnScope::Scoped(Scope::new()),
+              body: list.into_iter().skip(1).collect(),
+            }
+          } else if str == "fn!" {
+            ExprKind::Function {
+              scope: FnScope::Scopeless,
+              body: list.into_iter().skip(1).collect(),
+            }
+          } else {
+            ExprKind::SExpr {
+              call: *symbol,
+              body: list.into_iter().skip(1).collect(),
+            }
           }
         } else {
-          ExprKind::SExpr {
-            call: *symbol,
-            body: list.into_iter().skip(1).collect(),
+          self.error(ParseErrorKind::Parenthetical(token));
+          return None;
+        };
+
+        Some(Expr {
+          kind,
+          info: Some(ExprInfo {
+            source,
+            span: Span {
+              start: token.span.start,
+              end: end_span.end,
+            },
+          }),
+        })
+      }
+
+      TokenKind::LeftCurly => {
+        let (items, end_span) = self.group(token, TokenKind::RightCurly);
+
+        let mut record = IndexMap::new();
+        let mut items = items.into_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+          if let Some(key) = self.key(key, token.span) {
+            record.insert(key, value);
           }
         }
-      } else {
-        return Err(ParseError {
-          kind: ParseErrorKind::Parenthetical(token),
+
+        Some(Expr {
+          kind: ExprKind::Record(record),
+          info: Some(ExprInfo {
+            source,
+            span: Span {
+              start: token.span.start,
+              end: end_span.end,
+            },
+          }),
+        })
+      }
+
+      TokenKind::HashCurly => {
+        let (items, end_span) = self.group(token, TokenKind::RightCurly);
+
+        let mut set = IndexSet::new();
+        for item in items {
+          if let Some(item) = self.key(item, token.span) {
+            set.insert(item);
+          }
+        }
+
+        Some(Expr {
+          kind: ExprKind::Set(set),
+          info: Some(ExprInfo {
+            source,
+            span: Span {
+              start: token.span.start,
+              end: end_span.end,
+            },
+          }),
+        })
+      }
+
+      TokenKind::Integer => {
+        let slice = &source.source()[token.span.start..token.span.end];
+        // Literals which don't fit in an `i64` become a `BigInt`.
+        let Ok(literal) = slice.parse() else {
+          self.error(ParseErrorKind::InvalidLiteral(token));
+          return None;
+        };
+
+        Some(Expr {
+          kind: ExprKind::from_bigint(literal),
+          info: Some(ExprInfo {
+            source,
+            span: token.span,
+          }),
+        })
+      }
+      TokenKind::Float => {
+        let slice = &source.source()[token.span.start..token.span.end];
+        let Ok(literal) = slice.parse() else {
+          self.error(ParseErrorKind::InvalidLiteral(token));
+          return None;
+        };
+
+        Some(Expr {
+          kind: ExprKind::Float(literal),
+          info: Some(ExprInfo {
+            source,
+            span: token.span,
+          }),
+        })
+      }
+      TokenKind::Decimal => {
+        // Discard the `d` suffix from the slice.
+        let slice = &source.source()[token.span.start..token.span.end - 1];
+        let Ok(literal) = slice.parse() else {
+          self.error(ParseErrorKind::InvalidLiteral(token));
+          return None;
+        };
+
+        Some(Expr {
+          kind: ExprKind::Decimal(literal),
+          info: Some(ExprInfo {
+            source,
+            span: token.span,
+          }),
+        })
+      }
+      TokenKind::String => {
+        //   // Discard the quotation marks from the slice.
+        let slice = &source.source()[token.span.start + 1..token.span.end - 1];
+
+        Some(Expr {
+          kind: ExprKind::String(unescape_string(slice).to_compact_string()),
+          info: Some(ExprInfo {
+            source,
+            span: token.span,
+          }),
+        })
+      }
+      TokenKind::FormatString => {
+        // Discard the prefix and quotation marks from the slice.
+        let slice = &source.source()[token.span.start + 2..token.span.end - 1];
+        let Some((names, template)) = interpolate(&unescape_string(slice))
+        else {
+          self.error(ParseErrorKind::InvalidLiteral(token));
+          return None;
+        };
+
+        let info = Some(ExprInfo {
           source,
+          span: token.span,
         });
-      };
 
-      Ok(Expr {
-        kind,
-        info: Some(ExprInfo {
-          source,
-          span: Span {
-            start: token.span.start,
-            end: end_span.end,
+        // This desugars into `(format names.. template)`, so a placeholder
+        // which doesn't push exactly one value fails with
+        // `RunErrorReason::InvalidArgument`, like any other argument.
+        let mut body = names
+          .into_iter()
+          .map(|name| Expr {
+            kind: ExprKind::Symbol(name),
+            info: info.clone(),
+          })
+          .collect::<Vec<_>>();
+        body.push(Expr {
+          kind: ExprKind::String(template.into()),
+          info: info.clone(),
+        });
+
+        Some(Expr {
+          kind: ExprKind::SExpr {
+            call: Symbol::from_ref(Intrinsic::Format.as_str()),
+            body,
           },
-        }),
-      })
+          info,
+        })
+      }
+      TokenKind::Bytes => {
+        // Discard the prefix and quotation marks from the slice.
+        let slice = &source.source()[token.span.start + 2..token.span.end - 1];
+        let Some(literal) = unescape_bytes(slice) else {
+          self.error(ParseErrorKind::InvalidLiteral(token));
+          return None;
+        };
+
+        Some(Expr {
+          kind: ExprKind::Bytes(literal),
+          info: Some(ExprInfo {
+            source,
+            span: token.span,
+          }),
+        })
+      }
+      TokenKind::Symbol => {
+        let slice = &source.source()[token.span.start..token.span.end];
+
+        Some(Expr {
+          kind: match slice {
+            "nil" => ExprKind::Nil,
+            "true" => ExprKind::Boolean(true),
+            "false" => ExprKind::Boolean(false),
+            "_" => ExprKind::Underscore,
+            slice => ExprKind::Symbol(Symbol::from_ref(slice)),
+          },
+          info: Some(ExprInfo {
+            source,
+            span: token.span,
+          }),
+        })
+      }
     }
+  }
 
-    TokenKind::LeftCurly => {
-      let (record, end_span) = parse_record(lexer)?;
+  /// Parses the expressions of a group up to its `closer`, returning them
+  /// along with the span of where the group ends.
+  fn group(&mut self, open: Token, closer: TokenKind) -> (Vec<Expr>, Span) {
+    let lexer = self.lexer.clone();
+    let errors = self.errors.len();
+
+    let (exprs, end) = self.group_exprs(closer, self.line_end);
+    let (exprs, end) = match end {
+      GroupEnd::Closed(span) => return (exprs, span),
+      // Rather than taking the rest of the source, the group ends at the end
+      // of its line. Only the outermost group is parsed again, since the
+      // groups within it reach the end of the source as well.
+      GroupEnd::Eof if self.line_end.is_none() && self.closers.is_empty() => {
+        *self.lexer = lexer;
+        self.errors.truncate(errors);
+
+        let source = self.source.source();
+        let line_end = source[open.span.end..]
+          .find('\n')
+          .map(|i| open.span.end + i)
+          .unwrap_or(source.len());
+
+        self.line_end = Some(line_end);
+        let group = self.group_exprs(closer, Some(line_end));
+        self.line_end = None;
+
+        group
+      }
+      end => (exprs, end),
+    };
 
-      Ok(Expr {
-        kind: ExprKind::Record(record),
-        info: Some(ExprInfo {
-          source,
-          span: Span {
-            start: token.span.start,
-            end: end_span.end,
-          },
-        }),
-      })
+    if let GroupEnd::Closed(span) = end {
+      return (exprs, span);
     }
 
-    TokenKind::Integer => {
-      let slice = &source.source()[token.span.start..token.span.end];
-      let literal = slice.parse().map_err(|_| ParseError {
-        source: source.clone(),
-        kind: ParseErrorKind::InvalidLiteral(token),
-      })?;
+    self.error(ParseErrorKind::Unclosed(open));
 
-      Ok(Expr {
-        kind: ExprKind::Integer(literal),
-        info: Some(ExprInfo {
-          source,
-          span: token.span,
-        }),
-      })
-    }
-    TokenKind::Float => {
-      let slice = &source.source()[token.span.start..token.span.end];
-      let literal = slice.parse().map_err(|_| ParseError {
-        source: source.clone(),
-        kind: ParseErrorKind::InvalidLiteral(token),
-      })?;
-
-      Ok(Expr {
-        kind: ExprKind::Float(literal),
-        info: Some(ExprInfo {
-          source,
-          span: token.span,
-        }),
-      })
-    }
-    TokenKind::String => {
-      //   // Discard the quotation marks from the slice.
-      let slice = &source.source()[token.span.start + 1..token.span.end - 1];
-
-      Ok(Expr {
-        kind: ExprKind::String(
-          slice
-            .replace("\\n", "\n")
-            .replace("\\t", "\t")
-            .replace("\\r", "\r")
-            .replace("\\0", "\0")
-            .to_compact_string(),
-        ),
-        info: Some(ExprInfo {
-          source,
-          span: token.span,
-        }),
-      })
-    }
-    TokenKind::Symbol => {
-      let slice = &source.source()[token.span.start..token.span.end];
-
-      Ok(Expr {
-        kind: match slice {
-          "nil" => ExprKind::Nil,
-          "true" => ExprKind::Boolean(true),
-          "false" => ExprKind::Boolean(false),
-          "_" => ExprKind::Underscore,
-          slice => ExprKind::Symbol(Symbol::from_ref(slice)),
-        },
-        info: Some(ExprInfo {
-          source,
-          span: token.span,
-        }),
-      })
-    }
+    let end_span = exprs
+      .last()
+      .and_then(|expr| expr.info.as_ref())
+      .map(|info| info.span)
+      .unwrap_or(open.span);
+
+    (exprs, end_span)
   }
-}
 
-fn parse_list(lexer: &mut Lexer) -> Result<(Vec<Expr>, Span), ParseError> {
-  let mut list = Vec::new();
+  fn group_exprs(
+    &mut self,
+    closer: TokenKind,
+    line_end: Option<usize>,
+  ) -> (Vec<Expr>, GroupEnd) {
+    self.closers.push(closer);
+    let mut exprs = Vec::new();
+
+    let end = loop {
+      let token = self.lexer.peek();
+
+      if token.kind == closer {
+        break GroupEnd::Closed(self.lexer.next().span);
+      } else if line_end.is_some_and(|line_end| token.span.start > line_end) {
+        break GroupEnd::Line;
+      } else if token.kind == TokenKind::Eof {
+        break GroupEnd::Eof;
+      } else if self.is_group_end(token) {
+        break GroupEnd::Outer;
+      }
 
-  loop {
-    let token = lexer.peek();
+      exprs.extend(self.expr());
+    };
 
-    match token.kind {
-      TokenKind::RightSquare => break Ok((list, lexer.next().span)),
-      _ => list.push(parse_expr(lexer)?),
-    }
+    self.closers.pop();
+    (exprs, end)
   }
-}
 
-fn parse_parenthetical(
-  lexer: &mut Lexer,
-) -> Result<(Vec<Expr>, Span), ParseError> {
-  let mut list = Vec::new();
+  /// Returns whether `token` ends a group, rather than starting an
+  /// expression.
+  fn is_group_end(&self, token: Token) -> bool {
+    token.kind == TokenKind::Eof || self.closers.contains(&token.kind)
+  }
 
-  loop {
-    let token = lexer.peek();
+  /// Converts `expr` into a [`Key`], reporting an error if it can't be
+  /// hashed. The span of `open` is used if `expr` doesn't have one.
+  fn key(&mut self, expr: Expr, open: Span) -> Option<Key> {
+    let span = expr.info.as_ref().map_or(open, |info| info.span);
 
-    match token.kind {
-      TokenKind::RightParen => break Ok((list, lexer.next().span)),
-      _ => list.push(parse_expr(lexer)?),
+    match Key::try_from(expr.kind) {
+      Ok(key) => Some(key),
+      Err(_) => {
+        self.error(ParseErrorKind::UnhashableKey(span));
+        None
+      }
     }
   }
+
+  fn error(&mut self, kind: ParseErrorKind) {
+    self.errors.push(ParseError {
+      source: self.source.clone(),
+      kind,
+    });
+  }
 }
 
-fn parse_record(
-  lexer: &mut Lexer,
-) -> Result<(HashMap<Symbol, Expr>, Span), ParseError> {
-  let mut record = HashMap::new();
-  let mut key: Option<Symbol> = None;
-  let mut val: Option<Expr> = None;
+fn unescape_string(slice: &str) -> String {
+  slice
+    .replace("\\n", "\n")
+    .replace("\\t", "\t")
+    .replace("\\r", "\r")
+    .replace("\\0", "\0")
+}
 
-  loop {
-    let token = lexer.peek();
+/// Splits the contents of a format string literal into the names of its
+/// placeholders, and a template where they're positional.
+///
+/// Returns [`None`] if the template is invalid, or if a placeholder isn't
+/// named with a symbol.
+fn interpolate(literal: &str) -> Option<(Vec<Symbol>, String)> {
+  let mut names = Vec::new();
+  let mut template = String::new();
+
+  for segment in template::parse(literal)? {
+    match segment {
+      Segment::Text(x) => template.push_str(&template::escape(&x)),
+      Segment::Placeholder(Placeholder {
+        name, spec_source, ..
+      }) => {
+        let name = name?;
+
+        let mut lexer = Lexer::new(Source::new("", name));
+        let token = lexer.next();
+        if token.kind != TokenKind::Symbol || token.span.end != name.len() {
+          return None;
+        }
 
-    match token.kind {
-      TokenKind::RightCurly => break Ok((record, lexer.next().span)),
-      _ => {
-        if key.is_none() {
-          key = Some(parse_expr(lexer)?.kind.into());
+        names.push(Symbol::from_ref(name));
 
-          continue;
+        template.push('{');
+        if !spec_source.is_empty() {
+          template.push(':');
+          template.push_str(spec_source);
         }
+        template.push('}');
+      }
+    }
+  }
 
-        if val.is_none() {
-          val = Some(parse_expr(lexer)?)
-        }
+  Some((names, template))
+}
 
-        if let (Some(k), Some(v)) = (key, val.clone()) {
-          record.insert(k, v);
+/// Unescapes the contents of a bytes literal, returning [`None`] if it has an
+/// invalid escape.
+///
+/// Besides the escapes of strings, `\xNN` is a byte in hex.
+fn unescape_bytes(slice: &str) -> Option<Vec<u8>> {
+  let mut bytes = Vec::with_capacity(slice.len());
+  let mut iter = slice.bytes();
+
+  while let Some(byte) = iter.next() {
+    if byte != b'\\' {
+      bytes.push(byte);
+      continue;
+    }
 
-          key = None;
-          val = None;
-        }
+    bytes.push(match iter.next()? {
+      b'n' => b'\n',
+      b't' => b'\t',
+      b'r' => b'\r',
+      b'0' => b'\0',
+      b'"' => b'"',
+      b'\\' => b'\\',
+      b'x' => {
+        let digits = [iter.next()?, iter.next()?];
+        u8::from_str_radix(core::str::from_utf8(&digits).ok()?, 16).ok()?
       }
-    }
+      _ => return None,
+    });
   }
+
+  Some(bytes)
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -263,6 +529,14 @@ pub struct ParseError {
   pub kind: ParseErrorKind,
 }
 
+impl ParseError {
+  /// Returns the [`Location`] of the token which caused the error.
+  #[inline]
+  pub fn location(&self) -> Option<Location> {
+    self.kind.location(&self.source)
+  }
+}
+
 impl std::error::Error for ParseError {}
 
 impl fmt::Display for ParseError {
@@ -286,14 +560,24 @@ pub enum ParseErrorKind {
   UnexpectedToken(Token),
   InvalidLiteral(Token),
   Parenthetical(Token),
+  Unclosed(Token),
+  /// A record key or set item which can't be hashed, such as a float.
+  UnhashableKey(Span),
 }
 
 impl ParseErrorKind {
   pub fn location(self, source: &Source) -> Option<Location> {
+    source.location(self.span().start)
+  }
+
+  /// Returns the [`Span`] of the token which caused the error.
+  pub fn span(self) -> Span {
     match self {
-      Self::UnexpectedToken(x) => source.location(x.span.start),
-      Self::InvalidLiteral(x) => source.location(x.span.start),
-      Self::Parenthetical(x) => source.location(x.span.start),
+      Self::UnexpectedToken(x) => x.span,
+      Self::InvalidLiteral(x) => x.span,
+      Self::Parenthetical(x) => x.span,
+      Self::Unclosed(x) => x.span,
+      Self::UnhashableKey(x) => x,
     }
   }
 }
@@ -306,6 +590,8 @@ impl fmt::Display for ParseErrorKind {
       Self::Parenthetical(x) => {
         write!(f, "mismatched/unknown usage of parenthesis {x}")
       }
+      Self::Unclosed(x) => write!(f, "unclosed {x}"),
+      Self::UnhashableKey(_) => write!(f, "unhashable key"),
     }
   }
 }
@@ -321,8 +607,46 @@ mod test {
 
   #[case("" => Ok(Vec::<Expr>::new()) ; "empty")]
   #[case("1" => Ok(vec![Expr { kind: ExprKind::Integer(1), info: Some(ExprInfo { source: s("1"), span: Span { start: 0, end: 1 } }) }]))]
+  #[case("1.10d" => Ok(vec![Expr { kind: ExprKind::Decimal(rust_decimal::Decimal::new(110, 2)), info: Some(ExprInfo { source: s("1.10d"), span: Span { start: 0, end: 5 } }) }]) ; "decimal")]
+  #[case("b\"a\\x00\\\"\"" => Ok(vec![Expr { kind: ExprKind::Bytes(vec![b'a', 0, b'"']), info: Some(ExprInfo { source: s("b\"a\\x00\\\"\""), span: Span { start: 0, end: 10 } }) }]) ; "bytes")]
+  #[case("b\"\\xzz\"" => Err(ParseError { source: s("b\"\\xzz\""), kind: ParseErrorKind::InvalidLiteral(Token { kind: TokenKind::Bytes, span: Span { start: 0, end: 7 } }) }) ; "invalid bytes escape")]
+  #[case("f\"{{{a}}}\"" => Ok(vec![Expr { kind: ExprKind::SExpr { call: Symbol::from_ref("format"), body: vec![ExprKind::Symbol(Symbol::from_ref("a")).into(), ExprKind::String("{{{}}}".into()).into()] }, info: None }]) ; "format string")]
+  #[case("f\"{}\"" => Err(ParseError { source: s("f\"{}\""), kind: ParseErrorKind::InvalidLiteral(Token { kind: TokenKind::FormatString, span: Span { start: 0, end: 5 } }) }) ; "positional format string")]
   fn parse(source: &str) -> Result<Vec<Expr>, ParseError> {
     let mut lexer = Lexer::new(s(source));
     super::parse(&mut lexer)
   }
+
+  #[case("[1 2\n3 4 +" => ("[1 2] 3 4 +".into(), vec!["unclosed [".into()]) ; "unclosed at eof")]
+  #[case("(fn [1 2)\n5" => ("(fn [1 2]) 5".into(), vec!["unclosed [".into()]) ; "closed by outer group")]
+  #[case("1 ] 2 @ 3" => ("1 2 3".into(), vec!["unexpected token ]".into(), "unexpected token invalid characters".into()]) ; "unexpected tokens")]
+  #[case("(1 2) 3\n'" => ("3".into(), vec!["mismatched/unknown usage of parenthesis (".into(), "unexpected token end of file".into()]) ; "invalid groups")]
+  #[case("{a 1 b 2} #{3 1 3}" => ("{a: 1, b: 2} #{3 1}".into(), vec![]) ; "records and sets")]
+  #[case("{1.5 1 a 2} #{[1] 2}" => ("{a: 2} #{2}".into(), vec!["unhashable key".into(), "unhashable key".into()]) ; "unhashable keys")]
+  #[case("[1 2\n3 [4 [5\n6" => ("[1 2] 3 [4 [5]] 6".into(), vec!["unclosed [".into(), "unclosed [".into(), "unclosed [".into()]) ; "nested unclosed groups")]
+  fn parse_recovering(source: &str) -> (String, Vec<String>) {
+    let mut lexer = Lexer::new(s(source));
+    let (exprs, errors) = super::parse_recovering(&mut lexer);
+
+    (
+      exprs
+        .iter()
+        .map(|expr| expr.to_string())
+        .collect::<Vec<_>>()
+        .join(" "),
+      errors.iter().map(|err| err.kind.to_string()).collect(),
+    )
+  }
+
+  #[test]
+  fn recovers_deeply_unclosed_groups() {
+    let source = "[".repeat(30);
+    let start = std::time::Instant::now();
+
+    let mut lexer = Lexer::new(s(&source));
+    let (_, errors) = super::parse_recovering(&mut lexer);
+
+    assert_eq!(errors.len(), 30);
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+  }
 }
@@ -146,6 +146,14 @@ impl Scope {
     }
   }
 
+  /// Defines `name` as an item which isn't linked to any other scope, so that
+  /// it keeps its value when the item that it was copied from is set.
+  pub fn capture(&mut self, name: Symbol, item: Expr) {
+    self
+      .items
+      .insert(name, Rc::new(RefCell::new(Chain::new(Some(item)))));
+  }
+
   pub fn reserve(&mut self, name: Symbol) {
     self
       .items
@@ -208,6 +216,85 @@ impl Scope {
   }
 }
 
+/// Copies scopes and the items in them, so that the copies don't share any
+/// items with the originals.
+///
+/// The scopes of functions are copied as well, and items which are shared
+/// between the originals are shared between the copies.
+#[derive(Default)]
+pub struct Detacher {
+  /// The copied values, keyed by the values that they were copied from.
+  values: HashMap<*const RefCell<Option<Expr>>, Rc<RefCell<Option<Expr>>>>,
+}
+
+impl Detacher {
+  pub fn scope(&mut self, scope: &Scope) -> Scope {
+    let items = scope
+      .items
+      .iter()
+      .map(|(name, item)| {
+        let value = self.value(item.borrow().root());
+        (*name, Rc::new(RefCell::new(Chain::from_root(value))))
+      })
+      .collect();
+
+    Scope { items }
+  }
+
+  pub fn expr(&mut self, expr: &Expr) -> Expr {
+    let kind = match expr.kind {
+      ExprKind::Lazy(ref x) => ExprKind::Lazy(Box::new(self.expr(x))),
+      ExprKind::List(ref x) => {
+        ExprKind::List(x.iter().map(|x| self.expr(x)).collect())
+      }
+      ExprKind::Record(ref x) => ExprKind::Record(
+        x.iter()
+          .map(|(key, value)| (key.clone(), self.expr(value)))
+          .collect(),
+      ),
+      ExprKind::Function {
+        ref scope,
+        ref body,
+      } => ExprKind::Function {
+        scope: match scope {
+          FnScope::Scoped(scope) => FnScope::Scoped(self.scope(scope)),
+          FnScope::Scopeless => FnScope::Scopeless,
+        },
+        body: body.iter().map(|x| self.expr(x)).collect(),
+      },
+      ExprKind::SExpr { call, ref body } => ExprKind::SExpr {
+        call,
+        body: body.iter().map(|x| self.expr(x)).collect(),
+      },
+      ref kind => kind.clone(),
+    };
+
+    Expr {
+      kind,
+      info: expr.info.clone(),
+    }
+  }
+
+  fn value(
+    &mut self,
+    value: Rc<RefCell<Option<Expr>>>,
+  ) -> Rc<RefCell<Option<Expr>>> {
+    if let Some(copy) = self.values.get(&Rc::as_ptr(&value)) {
+      return copy.clone();
+    }
+
+    // The copy is remembered before the value is copied, since functions can
+    // refer to themselves through their scopes.
+    let copy = Rc::new(RefCell::new(None));
+    self.values.insert(Rc::as_ptr(&value), copy.clone());
+
+    let expr = value.borrow().clone();
+    *copy.borrow_mut() = expr.map(|expr| self.expr(&expr));
+
+    copy
+  }
+}
+
 #[derive(Debug)]
 pub struct Scanner<'s> {
   pub scope: &'s mut Scope,
@@ -218,7 +305,10 @@ impl<'s> Scanner<'s> {
     Self { scope }
   }
 
-  pub fn scan(&mut self, expr: Expr) -> Result<Expr, (Expr, RunErrorReason)> {
+  pub fn scan(
+    &mut self,
+    expr: Expr,
+  ) -> Result<Expr, (Box<Expr>, RunErrorReason)> {
     if expr.kind.is_function() {
       let expr = expr;
       if let ExprKind::Function { scope, mut body } = expr.kind {
@@ -259,7 +349,7 @@ impl<'s> Scanner<'s> {
         Ok(expr)
       }
     } else {
-      Err((expr, RunErrorReason::InvalidFunction))
+      Err((Box::new(expr), RunErrorReason::InvalidFunction))
     }
   }
 }
@@ -541,4 +631,31 @@ mod tests {
       vec![&ExprKind::Integer(0),]
     );
   }
+
+  #[test]
+  fn captured_vars_ignore_later_sets() {
+    let source = Source::new(
+      "",
+      "0 'a def '(fn a) '[a] capture '(fn a) 1 'a set call swap call a",
+    );
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    let engine = Engine::new();
+    let mut context = Context::new().with_stack_capacity(32);
+    context = engine.run(context, exprs).unwrap();
+
+    assert_eq!(
+      context
+        .stack()
+        .iter()
+        .map(|expr| &expr.kind)
+        .collect::<Vec<_>>(),
+      vec![
+        &ExprKind::Integer(1),
+        &ExprKind::Integer(0),
+        &ExprKind::Integer(1)
+      ]
+    );
+  }
 }
@@ -84,6 +84,20 @@ impl Source {
     self.0.source.as_str()
   }
 
+  /// Returns whether both [`Source`]s point to the same allocation.
+  #[inline]
+  #[must_use]
+  pub(crate) fn ptr_eq(&self, other: &Self) -> bool {
+    Arc::ptr_eq(&self.0, &other.0)
+  }
+
+  /// Returns the pointer to the allocation of this [`Source`].
+  #[inline]
+  #[must_use]
+  pub(crate) fn as_ptr(&self) -> *const () {
+    Arc::as_ptr(&self.0).cast()
+  }
+
   /// Returns the [`Location`] calculated from a byte index.
   ///
   /// [`None`] is returned when `index` is out-of-bounds, or `index` is not on
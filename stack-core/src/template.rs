@@ -0,0 +1,255 @@
+//! Parses the templates of the `format` intrinsic, such as
+//! `"{name:>8} costs {:.2}"`.
+//!
+//! A placeholder is either positional (`{}`), which is filled from the stack,
+//! or named (`{name}`), which is filled from a record. Either can be followed
+//! by a specifier of `[[fill]align][width][.precision]`, where the alignment
+//! is `<`, `^` or `>`. Braces are escaped by doubling them, as in `{{`.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::expr::ExprKind;
+
+/// The largest width or precision which a placeholder can have, since the
+/// padding is allocated up front.
+pub const MAX_WIDTH: usize = u16::MAX as usize;
+
+/// A part of a template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment<'a> {
+  /// Text which is written as it is, with its braces unescaped.
+  Text(String),
+  Placeholder(Placeholder<'a>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placeholder<'a> {
+  /// The name of the value, or [`None`] if it's positional.
+  pub name: Option<&'a str>,
+  pub spec: Spec,
+  /// The source of the specifier, without its leading `:`.
+  pub spec_source: &'a str,
+}
+
+/// How a value is written into a placeholder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spec {
+  pub fill: char,
+  /// The alignment of the value, or [`None`] to align numbers to the right and
+  /// everything else to the left.
+  pub align: Option<Align>,
+  /// The minimum number of characters to write.
+  pub width: Option<usize>,
+  /// The number of decimal places of floats and decimals, or the maximum
+  /// number of characters of strings.
+  pub precision: Option<usize>,
+}
+
+impl Default for Spec {
+  fn default() -> Self {
+    Self {
+      fill: ' ',
+      align: None,
+      width: None,
+      precision: None,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+  Left,
+  Center,
+  Right,
+}
+
+impl Align {
+  fn from_char(c: char) -> Option<Self> {
+    match c {
+      '<' => Some(Self::Left),
+      '^' => Some(Self::Center),
+      '>' => Some(Self::Right),
+      _ => None,
+    }
+  }
+}
+
+impl Spec {
+  /// Parses a specifier, returning [`None`] if it's invalid.
+  pub fn parse(source: &str) -> Option<Self> {
+    let mut spec = Self::default();
+
+    let mut chars = source.chars();
+    let rest = match (chars.next(), chars.next()) {
+      (Some(fill), Some(align)) if Align::from_char(align).is_some() => {
+        spec.fill = fill;
+        spec.align = Align::from_char(align);
+        chars.as_str()
+      }
+      (Some(align), _) if Align::from_char(align).is_some() => {
+        spec.align = Align::from_char(align);
+        &source[align.len_utf8()..]
+      }
+      _ => source,
+    };
+
+    let (width, precision) = match rest.split_once('.') {
+      Some((width, precision)) => (width, Some(precision)),
+      None => (rest, None),
+    };
+
+    if !width.is_empty() {
+      spec.width = Some(parse_number(width)?);
+    }
+
+    if let Some(precision) = precision {
+      spec.precision = Some(parse_number(precision)?);
+    }
+
+    Some(spec)
+  }
+
+  /// Writes `kind` as a string, with this specifier applied.
+  pub fn apply(&self, kind: &ExprKind) -> String {
+    let string = match (kind, self.precision) {
+      (ExprKind::Float(x), Some(precision)) => format!("{x:.precision$}"),
+      (ExprKind::Decimal(x), Some(precision)) => format!("{x:.precision$}"),
+      (ExprKind::String(x), Some(precision)) => {
+        x.graphemes(true).take(precision).collect()
+      }
+      (kind, _) => kind.to_string(),
+    };
+
+    let len = string.graphemes(true).count();
+    let padding = match self.width {
+      Some(width) if width > len => width - len,
+      _ => return string,
+    };
+
+    let is_number = matches!(
+      kind,
+      ExprKind::Integer(_)
+        | ExprKind::BigInt(_)
+        | ExprKind::Float(_)
+        | ExprKind::Decimal(_)
+    );
+    let align =
+      self
+        .align
+        .unwrap_or(if is_number { Align::Right } else { Align::Left });
+
+    let (before, after) = match align {
+      Align::Left => (0, padding),
+      Align::Center => (padding / 2, padding - padding / 2),
+      Align::Right => (padding, 0),
+    };
+
+    let fill = |n| core::iter::repeat_n(self.fill, n);
+    fill(before)
+      .chain(string.chars())
+      .chain(fill(after))
+      .collect()
+  }
+}
+
+/// Parses a template into its segments, returning [`None`] if it has an
+/// unclosed or unescaped brace, or an invalid specifier.
+pub fn parse(template: &str) -> Option<Vec<Segment<'_>>> {
+  let mut segments = Vec::new();
+  let mut text = String::new();
+
+  let mut rest = template;
+  while let Some(i) = rest.find(['{', '}']) {
+    text.push_str(&rest[..i]);
+
+    let brace = &rest[i..i + 1];
+    rest = &rest[i + 1..];
+
+    if let Some(after) = rest.strip_prefix(brace) {
+      text.push_str(brace);
+      rest = after;
+      continue;
+    }
+
+    if brace == "}" {
+      return None;
+    }
+
+    let end = rest
+      .find(['{', '}'])
+      .filter(|&end| &rest[end..end + 1] == "}")?;
+    let (name, spec_source) =
+      rest[..end].split_once(':').unwrap_or((&rest[..end], ""));
+    rest = &rest[end + 1..];
+
+    if !text.is_empty() {
+      segments.push(Segment::Text(core::mem::take(&mut text)));
+    }
+
+    segments.push(Segment::Placeholder(Placeholder {
+      name: (!name.is_empty()).then_some(name),
+      spec: Spec::parse(spec_source)?,
+      spec_source,
+    }));
+  }
+
+  text.push_str(rest);
+  if !text.is_empty() {
+    segments.push(Segment::Text(text));
+  }
+
+  Some(segments)
+}
+
+/// Escapes the braces in `text`, so that it's written as it is.
+pub fn escape(text: &str) -> String {
+  text.replace('{', "{{").replace('}', "}}")
+}
+
+fn parse_number(source: &str) -> Option<usize> {
+  if source.is_empty() || !source.bytes().all(|b| b.is_ascii_digit()) {
+    return None;
+  }
+
+  source.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use test_case::case;
+
+  fn placeholder<'a>(
+    name: Option<&'a str>,
+    spec_source: &'a str,
+  ) -> Segment<'a> {
+    Segment::Placeholder(Placeholder {
+      name,
+      spec: Spec::parse(spec_source).unwrap(),
+      spec_source,
+    })
+  }
+
+  #[case("hello" => Some(vec![Segment::Text("hello".into())]) ; "text")]
+  #[case("{} and {name:>4}" => Some(vec![placeholder(None, ""), Segment::Text(" and ".into()), placeholder(Some("name"), ">4")]) ; "placeholders")]
+  #[case("{{}}" => Some(vec![Segment::Text("{}".into())]) ; "escaped braces")]
+  #[case("{" => None ; "unclosed brace")]
+  #[case("}" => None ; "unescaped brace")]
+  #[case("{a{b}" => None ; "nested brace")]
+  #[case("{:x}" => None ; "invalid spec")]
+  fn parses(template: &str) -> Option<Vec<Segment<'_>>> {
+    parse(template)
+  }
+
+  #[case(ExprKind::Integer(42), ">5" => "   42" ; "right")]
+  #[case(ExprKind::Integer(42), "5" => "   42" ; "number default")]
+  #[case(ExprKind::String("ab".into()), "5" => "ab   " ; "string default")]
+  #[case(ExprKind::String("ab".into()), "*^6" => "**ab**" ; "center with fill")]
+  #[case(ExprKind::Float(1.0 / 3.0), ".2" => "0.33" ; "float precision")]
+  #[case(ExprKind::Decimal(rust_decimal::Decimal::new(15, 1)), "0>6.2" => "001.50" ; "decimal fill width and precision")]
+  #[case(ExprKind::String("hello".into()), ".2" => "he" ; "string precision")]
+  #[case(ExprKind::String("hello".into()), "2" => "hello" ; "wider than width")]
+  fn applies(kind: ExprKind, spec: &str) -> String {
+    Spec::parse(spec).unwrap().apply(&kind)
+  }
+}
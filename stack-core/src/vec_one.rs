@@ -122,12 +122,12 @@ impl<T> VecOne<T> {
   }
 
   #[inline]
-  pub fn iter(&self) -> Iter<T> {
+  pub fn iter(&self) -> Iter<'_, T> {
     self.vec.iter()
   }
 
   #[inline]
-  pub fn iter_mut(&mut self) -> IterMut<T> {
+  pub fn iter_mut(&mut self) -> IterMut<'_, T> {
     self.vec.iter_mut()
   }
 }
@@ -0,0 +1,821 @@
+//! A virtual machine which runs [`Program`]s.
+//!
+//! The [`Vm`] behaves identically to the tree-walker in [`Engine::run_expr`],
+//! but calls into functions, `if`, `call`, `let`, `try` and `catch` without
+//! recursing, and reuses compiled function bodies between calls.
+
+use std::rc::Rc;
+
+use crate::{
+  compiler::{
+    compile, is_scan_invariant, Instr, Program, ProgramKey, ProgramKind,
+  },
+  context::Context,
+  engine::{Engine, RunError, RunErrorReason},
+  expr::{Expr, ExprKind, FnScope},
+  intrinsic::{let_scope, match_scope, Intrinsic},
+  journal::JournalOp,
+  symbol::Symbol,
+};
+
+/// Runs [`Program`]s against a [`Context`].
+pub struct Vm {
+  context: Context,
+  frames: Vec<Frame>,
+  args: Vec<Vec<Expr>>,
+  marks: Vec<usize>,
+  empty: Rc<Program>,
+}
+
+#[derive(Debug)]
+struct Frame {
+  program: Rc<Program>,
+  pc: usize,
+  kind: FrameKind,
+}
+
+#[derive(Debug)]
+enum FrameKind {
+  /// A list of expressions to run.
+  Block,
+  /// A called expression, such as the items of a called list.
+  Call,
+  /// The body of a function, which may `recur`.
+  Function { expr: Expr, is_scoped: bool },
+  /// The body of an intrinsic which commits the journal once it's done.
+  Intrinsic,
+  /// The body of a `let`, which pops its scope once it's done.
+  Let { expr: Expr },
+  /// The body of a `try` or `catch`, which recovers from errors.
+  Catch {
+    handler: Option<Expr>,
+    stack: Vec<Expr>,
+    scope_depth: usize,
+    call_depth: usize,
+    args_len: usize,
+    marks_len: usize,
+  },
+}
+
+impl Vm {
+  /// Creates a new [`Vm`] with nothing to run.
+  pub fn new(context: Context) -> Self {
+    Self {
+      context,
+      frames: Vec::new(),
+      args: Vec::new(),
+      marks: Vec::new(),
+      empty: Rc::default(),
+    }
+  }
+
+  /// Compiles `exprs` and queues them to run.
+  pub fn with_exprs(mut self, engine: &Engine, exprs: Vec<Expr>) -> Self {
+    let program = Rc::new(compile(engine, exprs));
+    self.push_frame(program, FrameKind::Block);
+    self
+  }
+
+  /// Returns the [`Context`].
+  #[inline]
+  pub fn context(&self) -> &Context {
+    &self.context
+  }
+
+  #[inline]
+  pub(crate) fn context_mut(&mut self) -> &mut Context {
+    &mut self.context
+  }
+
+  /// Returns the [`Context`], dropping anything left to run.
+  #[inline]
+  pub fn into_context(self) -> Context {
+    self.context
+  }
+
+  /// Returns whether there is nothing left to run.
+  #[inline]
+  pub fn is_finished(&self) -> bool {
+    self.frames.is_empty()
+  }
+
+  /// Returns the next expression to be run, if there is one.
+  pub fn next_expr(&self) -> Option<&Expr> {
+    for (i, frame) in self.frames.iter().enumerate().rev() {
+      let pc = if i + 1 == self.frames.len() && self.is_recurring(frame) {
+        0
+      } else {
+        frame.pc
+      };
+
+      let expr = frame.program.instrs()[pc.min(frame.program.instrs().len())..]
+        .iter()
+        .find_map(|instr| match instr {
+          Instr::Push(index)
+          | Instr::Intrinsic(_, index)
+          | Instr::Module(_, index)
+          | Instr::Symbol(index)
+          | Instr::Function(index, _)
+          | Instr::SExprStart(index) => Some(frame.program.constant(*index)),
+          _ => None,
+        });
+
+      if expr.is_some() {
+        return expr;
+      }
+    }
+
+    None
+  }
+
+  /// Returns the expressions which called the functions that are running,
+  /// from the outermost to the innermost.
+  pub fn call_stack(&self) -> impl DoubleEndedIterator<Item = &Expr> {
+    self.frames.iter().filter_map(|frame| match frame.kind {
+      FrameKind::Function { ref expr, .. } => Some(expr),
+      _ => None,
+    })
+  }
+
+  /// Returns whether the next [`Instr`] is a whole expression, rather than
+  /// the collection of an argument or leaving a finished frame.
+  pub(crate) fn is_at_expr(&self) -> bool {
+    self.frames.last().is_some_and(|frame| {
+      matches!(
+        frame.program.instrs().get(frame.pc),
+        Some(
+          Instr::Push(_)
+            | Instr::Intrinsic(..)
+            | Instr::Module(..)
+            | Instr::Symbol(_)
+            | Instr::Function(..)
+            | Instr::SExprStart(_)
+        )
+      )
+    })
+  }
+
+  /// Returns whether the next step leaves a finished frame.
+  pub(crate) fn is_at_frame_end(&self) -> bool {
+    self
+      .frames
+      .last()
+      .is_some_and(|frame| frame.pc >= frame.program.instrs().len())
+  }
+
+  /// Returns whether the next step consumes fuel.
+  pub(crate) fn needs_fuel(&self) -> bool {
+    let Some(frame) = self.frames.last() else {
+      return false;
+    };
+
+    match frame.program.instrs().get(frame.pc) {
+      Some(Instr::Intrinsic(..) | Instr::Module(..) | Instr::Function(..)) => {
+        true
+      }
+      Some(Instr::Symbol(index)) => match frame.program.constant(*index).kind {
+        ExprKind::Symbol(symbol) => self
+          .context
+          .scope_item(symbol)
+          .is_some_and(|item| item.kind.is_function()),
+        _ => false,
+      },
+      Some(_) => false,
+      None => self.is_recurring(frame),
+    }
+  }
+
+  /// Returns whether `frame` is a function which is about to `recur`.
+  fn is_recurring(&self, frame: &Frame) -> bool {
+    matches!(frame.kind, FrameKind::Function { .. })
+      && frame.pc >= frame.program.instrs().len()
+      && self.context.stack().last().map(|e| &e.kind)
+        == Some(&ExprKind::Symbol(Symbol::from_ref("recur")))
+  }
+
+  /// Runs until finished, returning the [`Context`].
+  pub fn run(mut self, engine: &Engine) -> Result<Context, RunError> {
+    while !self.is_finished() {
+      self.step(engine)?;
+    }
+
+    Ok(self.context)
+  }
+
+  /// Runs the next [`Instr`], or leaves a finished frame.
+  ///
+  /// Once this returns an error, the [`Vm`] must not be used again.
+  pub fn step(&mut self, engine: &Engine) -> Result<(), RunError> {
+    match self.step_inner(engine) {
+      Ok(()) => Ok(()),
+      Err(err) => self.unwind(engine, err),
+    }
+  }
+
+  /// Queues `expr` to be called, the same as [`Engine::call_expr`].
+  pub fn call(&mut self, engine: &Engine, expr: Expr) -> Result<(), RunError> {
+    self.context.enter_call(&expr)?;
+    let expr = self.context.scan_expr(expr)?;
+
+    let program = match expr.kind {
+      ExprKind::List(exprs) => {
+        let key = ProgramKey::new(&expr.info, ProgramKind::Block, exprs.len());
+        engine
+          .programs()
+          .get_or_compile(key, || compile(engine, exprs))
+      }
+      _ => Rc::new(compile(engine, vec![expr])),
+    };
+
+    self.push_frame(program, FrameKind::Call);
+
+    Ok(())
+  }
+
+  fn push_frame(&mut self, program: Rc<Program>, kind: FrameKind) {
+    self.frames.push(Frame {
+      program,
+      pc: 0,
+      kind,
+    });
+  }
+
+  /// Pushes a frame which only runs its exit behaviour.
+  fn push_marker(&mut self, kind: FrameKind) {
+    self.push_frame(self.empty.clone(), kind);
+  }
+
+  fn step_inner(&mut self, engine: &Engine) -> Result<(), RunError> {
+    let Some(frame) = self.frames.last_mut() else {
+      return Ok(());
+    };
+
+    if frame.pc >= frame.program.instrs().len() {
+      let frame = self.frames.pop().unwrap();
+      return self.exit(engine, frame);
+    }
+
+    let program = frame.program.clone();
+    let instr = &program.instrs()[frame.pc];
+    frame.pc += 1;
+
+    match instr {
+      Instr::Push(index) => {
+        let expr = program.constant(*index);
+        self.check_interrupt(engine, expr)?;
+
+        let expr = match expr.kind {
+          ExprKind::Lazy(ref x) => (**x).clone(),
+          _ => expr.clone(),
+        };
+        self.context.stack_push(expr)
+      }
+      Instr::Intrinsic(intrinsic, index) => {
+        let expr = program.constant(*index);
+        self.check_interrupt(engine, expr)?;
+        self.run_intrinsic(engine, *intrinsic, expr)
+      }
+      Instr::Module(func, index) => {
+        let expr = program.constant(*index);
+        self.check_interrupt(engine, expr)?;
+
+        self.commit();
+        self.consume_fuel(engine, expr)?;
+        if let Some(journal) = self.context.journal_mut() {
+          journal.push_op(JournalOp::FnCall(expr.clone()));
+        }
+        self.context = func(engine, self.take_context(), expr.clone())?;
+        if let Some(journal) = self.context.journal_mut() {
+          journal.commit();
+        }
+
+        Ok(())
+      }
+      Instr::Symbol(index) => {
+        let expr = program.constant(*index);
+        self.check_interrupt(engine, expr)?;
+        self.run_symbol(engine, expr)
+      }
+      Instr::Function(index, body) => {
+        let expr = program.constant(*index);
+        self.check_interrupt(engine, expr)?;
+
+        let expr = self.context.scan_expr(expr.clone())?;
+        let ExprKind::Function { ref scope, .. } = expr.kind else {
+          unreachable!()
+        };
+        let scope = scope.clone();
+        let body = match body {
+          Some(body) => body.clone(),
+          None => self.compile_body(engine, &expr),
+        };
+
+        self.call_fn(engine, expr, &scope, body)
+      }
+      Instr::SExprStart(index) => {
+        let expr = program.constant(*index);
+        self.check_interrupt(engine, expr)?;
+
+        if let Some(journal) = self.context.journal_mut() {
+          journal.commit();
+          journal.push_op(JournalOp::SCall(expr.clone()));
+        }
+
+        self.args.push(Vec::new());
+
+        Ok(())
+      }
+      Instr::Mark => {
+        self.marks.push(self.context.stack().len());
+        Ok(())
+      }
+      Instr::Arg(index) => {
+        let arg = self.context.stack_pop(program.constant(*index))?;
+        self.args.last_mut().unwrap().push(arg);
+        Ok(())
+      }
+      Instr::MarkedArg(index) => {
+        let stack_len = self.marks.pop().unwrap();
+        if self.context.stack().len() != stack_len + 1 {
+          return Err(RunError {
+            reason: RunErrorReason::InvalidArgument,
+            context: Box::new(self.take_context()),
+            expr: Box::new(program.constant(*index).clone()),
+          });
+        }
+
+        let arg = self.context.stack_pop(program.constant(*index))?;
+        self.args.last_mut().unwrap().push(arg);
+        Ok(())
+      }
+      Instr::SExprEnd { flipped } => {
+        let mut args = self.args.pop().unwrap();
+        if *flipped {
+          args.reverse();
+        }
+
+        for expr in args {
+          self.context.stack_push(expr)?;
+        }
+
+        Ok(())
+      }
+    }
+  }
+
+  fn run_intrinsic(
+    &mut self,
+    engine: &Engine,
+    intrinsic: Intrinsic,
+    expr: &Expr,
+  ) -> Result<(), RunError> {
+    self.commit();
+    self.consume_fuel(engine, expr)?;
+    if let Some(journal) = self.context.journal_mut() {
+      journal.commit();
+      journal.push_op(JournalOp::FnCall(expr.clone()));
+    }
+
+    match intrinsic {
+      Intrinsic::If => {
+        let body = self.context.stack_pop(expr)?;
+        let cond = self.context.stack_pop(expr)?;
+
+        self.push_marker(FrameKind::Intrinsic);
+        if cond.kind.is_truthy() {
+          self.call(engine, body)?;
+        }
+      }
+      Intrinsic::Call => {
+        let item = self.context.stack_pop(expr)?;
+
+        self.push_marker(FrameKind::Intrinsic);
+        self.call(engine, item)?;
+      }
+      Intrinsic::Let | Intrinsic::Match => {
+        let (body, scope) = match intrinsic {
+          Intrinsic::Let => let_scope(&mut self.context, expr)?,
+          _ => match_scope(&mut self.context, expr)?,
+        };
+
+        if let Some(journal) = self.context.journal_mut() {
+          journal.commit();
+          journal.push_op(JournalOp::ScopelessFnStart(expr.info.clone()));
+        }
+
+        self.context.push_scope(scope);
+        self.push_marker(FrameKind::Let { expr: expr.clone() });
+        self.call(engine, body)?;
+      }
+      Intrinsic::Try | Intrinsic::Catch => {
+        let handler = match intrinsic {
+          Intrinsic::Try => Some(self.context.stack_pop(expr)?),
+          _ => None,
+        };
+        let body = self.context.stack_pop(expr)?;
+
+        self.push_marker(FrameKind::Catch {
+          handler,
+          stack: self.context.stack().to_vec(),
+          scope_depth: self.context.scope_depth(),
+          call_depth: self.context.call_depth(),
+          args_len: self.args.len(),
+          marks_len: self.marks.len(),
+        });
+        self.call(engine, body)?;
+      }
+      _ => {
+        self.context =
+          intrinsic.run(engine, self.take_context(), expr.clone())?;
+        if let Some(journal) = self.context.journal_mut() {
+          journal.commit();
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  fn run_symbol(
+    &mut self,
+    engine: &Engine,
+    expr: &Expr,
+  ) -> Result<(), RunError> {
+    let ExprKind::Symbol(symbol) = expr.kind else {
+      unreachable!()
+    };
+
+    if let Some(journal) = self.context.journal_mut() {
+      journal.commit();
+    }
+
+    // Module functions are resolved when compiling, so a namespaced symbol can
+    // only be an item of a record, which is found like any other item.
+    match self.context.scope_item(symbol) {
+      Some(item) => match item.kind {
+        ExprKind::Function { ref scope, .. } => {
+          let body = self.compile_body(engine, &item);
+          self.call_fn(engine, expr.clone(), scope, body)
+        }
+        ExprKind::SExpr { .. } => self.call(engine, item),
+        _ => {
+          if let Some(journal) = self.context.journal_mut() {
+            journal.push_op(JournalOp::Call(expr.clone()));
+          }
+          let result = self.context.stack_push(item);
+          if let Some(journal) = self.context.journal_mut() {
+            journal.commit();
+          }
+
+          result
+        }
+      },
+      None => Err(RunError {
+        context: Box::new(self.take_context()),
+        expr: Box::new(expr.clone()),
+        reason: RunErrorReason::UnknownCall,
+      }),
+    }
+  }
+
+  /// Returns the compiled body of a function, reusing it when possible.
+  fn compile_body(&self, engine: &Engine, function: &Expr) -> Rc<Program> {
+    let ExprKind::Function { ref body, .. } = function.kind else {
+      unreachable!()
+    };
+
+    let key = is_scan_invariant(body)
+      .then(|| ProgramKey::new(&function.info, ProgramKind::FnBody, body.len()))
+      .flatten();
+
+    engine
+      .programs()
+      .get_or_compile(key, || compile(engine, body.clone()))
+  }
+
+  /// Starts a call of a function, the same as [`Engine::call_fn`].
+  fn call_fn(
+    &mut self,
+    engine: &Engine,
+    expr: Expr,
+    scope: &FnScope,
+    body: Rc<Program>,
+  ) -> Result<(), RunError> {
+    self.consume_fuel(engine, &expr)?;
+    self.context.enter_call(&expr)?;
+
+    if let FnScope::Scoped(scope) = scope {
+      self.context.push_scope(scope.clone());
+    }
+
+    let is_scoped = scope.is_scoped();
+    self.start_fn(&expr, is_scoped);
+    self.push_frame(body, FrameKind::Function { expr, is_scoped });
+
+    Ok(())
+  }
+
+  fn start_fn(&mut self, expr: &Expr, is_scoped: bool) {
+    if self.context.journal().is_none() {
+      return;
+    }
+
+    let scope = self.context.scope().clone();
+    let journal = self.context.journal_mut().as_mut().unwrap();
+    journal.push_op(JournalOp::FnCall(expr.clone()));
+    journal.commit();
+
+    if is_scoped {
+      journal
+        .push_op(JournalOp::ScopedFnStart(expr.info.clone(), scope.into()));
+    } else {
+      journal.push_op(JournalOp::ScopelessFnStart(expr.info.clone()));
+    }
+  }
+
+  fn end_fn(&mut self, expr: &Expr) {
+    if self.context.journal().is_some() {
+      let scope = self.context.scope().clone();
+      let journal = self.context.journal_mut().as_mut().unwrap();
+      journal.commit();
+      journal.push_op(JournalOp::FnEnd(expr.info.clone(), scope.into()));
+    }
+  }
+
+  fn exit(
+    &mut self,
+    engine: &Engine,
+    mut frame: Frame,
+  ) -> Result<(), RunError> {
+    match frame.kind {
+      FrameKind::Block => {}
+      FrameKind::Call => self.context.exit_call(),
+      FrameKind::Function {
+        ref expr,
+        is_scoped,
+      } => {
+        self.end_fn(expr);
+
+        if self.context.stack().last().map(|e| &e.kind)
+          == Some(&ExprKind::Symbol(Symbol::from_ref("recur")))
+        {
+          self.context.stack_pop(expr)?;
+          self.consume_fuel(engine, expr)?;
+          self.start_fn(expr, is_scoped);
+
+          frame.pc = 0;
+          self.frames.push(frame);
+        } else {
+          if is_scoped {
+            self.context.pop_scope();
+          }
+          self.context.exit_call();
+        }
+      }
+      FrameKind::Intrinsic => self.commit(),
+      FrameKind::Let { ref expr } => {
+        self.end_fn(expr);
+        self.context.pop_scope();
+        self.commit();
+      }
+      FrameKind::Catch { handler, .. } => {
+        if handler.is_none() {
+          self.context.stack_push(ExprKind::Nil.into())?;
+        }
+        self.commit();
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Unwinds to the innermost `try` or `catch`, recovering from `err`.
+  fn unwind(
+    &mut self,
+    engine: &Engine,
+    mut err: RunError,
+  ) -> Result<(), RunError> {
+    while err.reason.is_catchable() {
+      let Some(frame) = self.frames.pop() else {
+        break;
+      };
+
+      let FrameKind::Catch {
+        handler,
+        stack,
+        scope_depth,
+        call_depth,
+        args_len,
+        marks_len,
+      } = frame.kind
+      else {
+        continue;
+      };
+
+      let record = err.to_record();
+      self.context = *err.context;
+
+      *self.context.stack_mut() = stack;
+      self.context.truncate_scopes(scope_depth);
+      self.context.set_call_depth(call_depth);
+      self.args.truncate(args_len);
+      self.marks.truncate(marks_len);
+      self.commit();
+
+      match self.recover(engine, handler, record) {
+        Ok(()) => return Ok(()),
+        Err(next) => err = next,
+      }
+    }
+
+    Err(err)
+  }
+
+  fn recover(
+    &mut self,
+    engine: &Engine,
+    handler: Option<Expr>,
+    record: Expr,
+  ) -> Result<(), RunError> {
+    self.context.stack_push(record)?;
+
+    match handler {
+      Some(handler) => {
+        self.push_marker(FrameKind::Intrinsic);
+        self.call(engine, handler)
+      }
+      None => {
+        self.commit();
+        Ok(())
+      }
+    }
+  }
+
+  fn check_interrupt(
+    &mut self,
+    engine: &Engine,
+    expr: &Expr,
+  ) -> Result<(), RunError> {
+    match engine.interrupt() {
+      Some(reason) => Err(RunError {
+        reason,
+        context: Box::new(self.take_context()),
+        expr: Box::new(expr.clone()),
+      }),
+      None => Ok(()),
+    }
+  }
+
+  fn consume_fuel(
+    &mut self,
+    engine: &Engine,
+    expr: &Expr,
+  ) -> Result<(), RunError> {
+    self.context = engine.consume_fuel(self.take_context(), expr)?;
+    Ok(())
+  }
+
+  fn commit(&mut self) {
+    if let Some(journal) = self.context.journal_mut() {
+      journal.commit();
+    }
+  }
+
+  /// Takes the [`Context`] to be passed by value.
+  ///
+  /// It must be put back before it's used again.
+  #[inline]
+  fn take_context(&mut self) -> Context {
+    core::mem::take(&mut self.context)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{engine::Backend, prelude::*};
+
+  fn run(backend: Backend, source: &str) -> Result<Vec<Expr>, RunError> {
+    let source = Source::new("", source);
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    let engine = Engine::new().with_backend(backend);
+    let context = Context::new().with_stack_capacity(32);
+
+    engine
+      .run(context, exprs)
+      .map(|mut context| core::mem::take(context.stack_mut()))
+  }
+
+  fn assert_same(source: &str) -> Result<Vec<Expr>, RunError> {
+    let tree_walker = run(Backend::TreeWalker, source);
+    let vm = run(Backend::Vm, source);
+
+    assert_eq!(tree_walker, vm);
+    vm
+  }
+
+  #[test]
+  fn recur_loops() {
+    let stack = assert_same(
+      "0 'i def
+      (fn! i 1000 < '[i 1 + 'i set recur] if)
+      i
+
+      0 'total def
+      '(fn!
+        0 'j def
+        (fn! j 10 < '[j 1 + 'j set total j + 'total set recur] if)
+      ) 'sum def
+      sum sum total",
+    )
+    .unwrap();
+
+    assert_eq!(
+      stack.iter().map(|expr| &expr.kind).collect::<Vec<_>>(),
+      vec![&ExprKind::Integer(1000), &ExprKind::Integer(110)]
+    );
+  }
+
+  #[test]
+  fn closures_capture_scope() {
+    let stack = assert_same(
+      "'(fn 0 'a def '(fn a 1 + 'a set a)) call 'counter def
+      counter counter
+      10 2 '[(fn a b -)] '[a b] let",
+    )
+    .unwrap();
+
+    assert_eq!(
+      stack.iter().map(|expr| &expr.kind).collect::<Vec<_>>(),
+      vec![
+        &ExprKind::Integer(1),
+        &ExprKind::Integer(2),
+        &ExprKind::Integer(8)
+      ]
+    );
+  }
+
+  #[test]
+  fn errors_unwind_to_try() {
+    let stack = assert_same(
+      "'[(fn 1 'x def missing)] '[(prop _ \"reason\") swap drop] try
+      '[10 (fn '[1 2 +] call)] catch",
+    )
+    .unwrap();
+
+    assert_eq!(
+      stack.iter().map(|expr| &expr.kind).collect::<Vec<_>>(),
+      vec![
+        &ExprKind::String("unknown-call".into()),
+        &ExprKind::Integer(10),
+        &ExprKind::Integer(3),
+        &ExprKind::Nil,
+      ]
+    );
+  }
+
+  #[test]
+  fn errors_match() {
+    assert_eq!(
+      assert_same("1 (fn + 2)").map_err(|err| err.reason),
+      Err(RunErrorReason::StackUnderflow)
+    );
+    assert_eq!(
+      assert_same("1 [a] [b] let").map_err(|err| err.reason),
+      Err(RunErrorReason::UnknownCall)
+    );
+    assert_eq!(
+      assert_same("'(fn) 'f def (+ 1 f)").map_err(|err| err.reason),
+      Err(RunErrorReason::InvalidArgument)
+    );
+    assert_eq!(
+      assert_same("'(fn 1 2) 'g def (+ 1 g)").map_err(|err| err.reason),
+      Err(RunErrorReason::InvalidArgument)
+    );
+  }
+
+  #[test]
+  fn journals_match() {
+    let source = Source::new(
+      "",
+      "(fn 1 'a def [a 2 +] call) call (+ 1 _) 2 [x] [x] let \
+       (map [1 2] '(fn 2 *)) (match [3 4] '[[a & b] b])",
+    );
+    let mut lexer = Lexer::new(source);
+    let exprs = crate::parser::parse(&mut lexer).unwrap();
+
+    let journals = [Backend::TreeWalker, Backend::Vm].map(|backend| {
+      let engine = Engine::new().with_backend(backend);
+      let context = Context::new().with_journal(None);
+
+      engine
+        .run(context, exprs.clone())
+        .unwrap()
+        .journal()
+        .clone()
+    });
+
+    assert_eq!(journals[0], journals[1]);
+  }
+}
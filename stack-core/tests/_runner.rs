@@ -1,6 +1,7 @@
 use core::str::FromStr;
 use std::path::PathBuf;
 
+use indexmap::IndexSet;
 use stack_core::prelude::*;
 use test_case::case;
 
@@ -9,19 +10,35 @@ fn e(kind: ExprKind) -> Expr {
   kind.into()
 }
 
+#[inline]
+fn set_of(items: Vec<Key>) -> Expr {
+  e(ExprKind::Set(IndexSet::from_iter(items)))
+}
+
 // TODO: Add tests for missing intrinsics.
 
 #[case("intrinsics/arithmetic.stack" => Ok(vec![e(ExprKind::Integer(3)), e(ExprKind::Integer(-1)), e(ExprKind::Integer(6)), e(ExprKind::Integer(2)), e(ExprKind::Integer(0))]) ; "arithmetic")]
+#[case("intrinsics/bigint.stack" => Ok(vec![e(ExprKind::String("bigint".into())), e(ExprKind::String("9223372036854775808".into())), e(ExprKind::Integer(1)), e(ExprKind::String("9999999999800000000001".into())), e(ExprKind::Integer(0)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(true)), e(ExprKind::Float(18446744073709551616.0)), e(ExprKind::Float(-18446744073709551616.0)), e(ExprKind::Float(9223372036854775808.0)), e(ExprKind::Float(4611686018427387904.0))]) ; "bigint")]
+#[case("intrinsics/decimal.stack" => Ok(vec![e(ExprKind::Boolean(true)), e(ExprKind::String("59.97".into())), e(ExprKind::String("2.20".into())), e(ExprKind::Boolean(true)), e(ExprKind::String("12.345".into())), e(ExprKind::Integer(2)), e(ExprKind::String("decimal".into())), e(ExprKind::Nil), e(ExprKind::String("arithmetic-overflow".into()))]) ; "decimal")]
+#[case("intrinsics/bytes.stack" => Ok(vec![e(ExprKind::Integer(4)), e(ExprKind::Integer(255)), e(ExprKind::Bytes(vec![104, 101, 108, 108, 111])), e(ExprKind::Bytes(vec![97, 98, 65])), e(ExprKind::Integer(98)), e(ExprKind::Bytes(vec![104, 105])), e(ExprKind::String("hi".into())), e(ExprKind::Nil), e(ExprKind::String("bytes".into()))]) ; "bytes")]
 #[case("intrinsics/compare.stack" => Ok(vec![e(ExprKind::Boolean(true)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(false))]) ; "compare")]
 #[case("intrinsics/logical.stack" => Ok(vec![e(ExprKind::Boolean(false)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(false)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(false))]) ; "logical")]
 // TODO: Fix this.
-// #[case("intrinsics/assert_fail.stack" => Err(RunError { reason: RunErrorReason::AssertionFailed, expr: e(ExprKind::Integer(123)) }) ; "assert fail")]
+// #[case("intrinsics/assert_fail.stack" => Err(RunError { reason: RunErrorReason::AssertionFailed, expr: Box::new(e(ExprKind::Integer(123))) }) ; "assert fail")]
 #[case("intrinsics/assert_okay.stack" => Ok(vec![]) ; "assert okay")]
 #[case("intrinsics/stack.stack" => Ok(vec![e(ExprKind::Integer(1)), e(ExprKind::Integer(3)), e(ExprKind::Integer(3)), e(ExprKind::Integer(5)), e(ExprKind::Integer(4)), e(ExprKind::Integer(7)), e(ExprKind::Integer(8)), e(ExprKind::Integer(6))]) ; "stack")]
 #[case("intrinsics/orelse.stack" => Ok(vec![e(ExprKind::Integer(1)), e(ExprKind::Integer(2)), e(ExprKind::Integer(1)), e(ExprKind::Nil)]) ; "orelse")]
 #[case("intrinsics/push.stack" => Ok(vec![e(ExprKind::List(vec![e(ExprKind::Integer(1)), e(ExprKind::Integer(2)), e(ExprKind::Integer(3))])), e(ExprKind::String("he".into())), e(ExprKind::String("he".into()))]) ; "push")]
-#[case("intrinsics/record.stack" => Ok(vec![e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(false)),e(ExprKind::Boolean(false)),e(ExprKind::Integer(2)), e(ExprKind::Integer(2))]) ; "record")]
+#[case("intrinsics/record.stack" => Ok(vec![e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(true)),e(ExprKind::Boolean(false)),e(ExprKind::Boolean(false)),e(ExprKind::List(vec![e(ExprKind::String("name".into())), e(ExprKind::String("type".into()))])), e(ExprKind::List(vec![e(ExprKind::String("john".into())), e(ExprKind::String("person".into()))])), e(ExprKind::Integer(2)), e(ExprKind::Integer(2)), e(ExprKind::List(vec![e(ExprKind::Symbol(Symbol::from_ref("c"))), e(ExprKind::Symbol(Symbol::from_ref("a"))), e(ExprKind::Symbol(Symbol::from_ref("b")))])), e(ExprKind::String("one".into())), e(ExprKind::Integer(1)), e(ExprKind::List(vec![e(ExprKind::Integer(1)), e(ExprKind::Integer(3)), e(ExprKind::Integer(4))]))]) ; "record")]
+#[case("intrinsics/set.stack" => Ok(vec![set_of(vec![Key::Integer(3), Key::Integer(1), Key::Integer(2)]), set_of(vec![Key::Integer(1), Key::Integer(2), Key::Integer(4)]), e(ExprKind::Boolean(true)), set_of(vec![Key::Integer(1), Key::Integer(3)]), set_of(vec![Key::Integer(1), Key::Integer(2), Key::Integer(3), Key::Integer(4)]), set_of(vec![Key::Integer(3)]), set_of(vec![Key::Integer(1), Key::Integer(2)]), set_of(vec![Key::Integer(1), Key::String("a".into())]), e(ExprKind::List(vec![e(ExprKind::Integer(1)), e(ExprKind::Integer(2))])), e(ExprKind::Nil)]) ; "set")]
+#[case("intrinsics/format.stack" => Ok(vec![e(ExprKind::String("1 + 2 = 3".into())), e(ExprKind::String("ann is 30".into())), e(ExprKind::String("3.14".into())), e(ExprKind::String("[**ab**|  42]".into())), e(ExprKind::String("hi ann  !".into())), e(ExprKind::Nil), e(ExprKind::String("invalid-argument".into()))]) ; "format")]
+#[case("intrinsics/closures.stack" => Ok(vec![e(ExprKind::List(vec![e(ExprKind::Integer(3)), e(ExprKind::Integer(3)), e(ExprKind::Integer(3))])), e(ExprKind::List(vec![e(ExprKind::Integer(1)), e(ExprKind::Integer(2)), e(ExprKind::Integer(3))])), e(ExprKind::Integer(-9)), e(ExprKind::Symbol(Symbol::from_ref("x"))), e(ExprKind::Integer(7)), e(ExprKind::Integer(7)), e(ExprKind::Nil)]) ; "closures")]
 #[case("intrinsics/pop.stack" => Ok(vec![e(ExprKind::List(vec![e(ExprKind::Integer(1)), e(ExprKind::Integer(2))])), e(ExprKind::Integer(3)), e(ExprKind::String("h".into())), e(ExprKind::String("e".into()))]) ; "pop")]
+#[case("intrinsics/try.stack" => Ok(vec![e(ExprKind::Integer(1)), e(ExprKind::String("stack-underflow".into())), e(ExprKind::Integer(3)), e(ExprKind::String("oops".into())), e(ExprKind::String("unknown-call".into())), e(ExprKind::Integer(4)), e(ExprKind::Nil)]) ; "try catch")]
+#[case("intrinsics/iteration.stack" => Ok(vec![e(ExprKind::List(vec![e(ExprKind::Integer(2)), e(ExprKind::Integer(4)), e(ExprKind::Integer(6))])), e(ExprKind::List(vec![e(ExprKind::Integer(2)), e(ExprKind::Integer(4))])), e(ExprKind::String("heo".into())), e(ExprKind::Integer(16)), e(ExprKind::Integer(6)), e(ExprKind::Nil), e(ExprKind::Integer(6)), e(ExprKind::Boolean(true)), e(ExprKind::Boolean(false)), e(ExprKind::Integer(2)), e(ExprKind::List(vec![e(ExprKind::Float(1.5)), e(ExprKind::Integer(2)), e(ExprKind::Integer(3))])), e(ExprKind::String("abc".into())), e(ExprKind::List(vec![e(ExprKind::String("a".into())), e(ExprKind::String("bb".into())), e(ExprKind::String("ccc".into()))])), e(ExprKind::List(vec![e(ExprKind::List(vec![e(ExprKind::Integer(1)), e(ExprKind::String("a".into()))])), e(ExprKind::List(vec![e(ExprKind::Integer(2)), e(ExprKind::String("b".into()))]))])), e(ExprKind::List(vec![e(ExprKind::List(vec![e(ExprKind::Integer(0)), e(ExprKind::String("a".into()))])), e(ExprKind::List(vec![e(ExprKind::Integer(1)), e(ExprKind::String("b".into()))]))])), e(ExprKind::List(vec![e(ExprKind::Integer(1)), e(ExprKind::Integer(2)), e(ExprKind::Integer(3))]))]) ; "iteration")]
+#[case("intrinsics/match.stack" => Ok(vec![e(ExprKind::String("one".into())), e(ExprKind::String("other".into())), e(ExprKind::List(vec![e(ExprKind::Integer(2)), e(ExprKind::Integer(3))])), e(ExprKind::Integer(1)), e(ExprKind::Integer(2)), e(ExprKind::Integer(1)), e(ExprKind::String("bob".into())), e(ExprKind::Float(1.5)), e(ExprKind::Integer(2)), e(ExprKind::String("no-match".into()))]) ; "pattern matching")]
+#[case("intrinsics/use.stack" => Ok(vec![e(ExprKind::Integer(6)), e(ExprKind::Integer(12)), e(ExprKind::Integer(8)), e(ExprKind::String("other".into())), e(ExprKind::String("unknown-call".into()))]) ; "modules")]
+#[case("intrinsics/import.stack" => Ok(vec![e(ExprKind::String("file-not-found".into())), e(ExprKind::String("parse-error".into())), e(ExprKind::String("import-cycle".into())), e(ExprKind::String("import-cycle".into())), e(ExprKind::String("invalid-import".into())), e(ExprKind::Integer(6))]) ; "import errors")]
 fn integration(subpath: &str) -> Result<Vec<Expr>, RunError> {
   let mut path = PathBuf::from_str("tests").unwrap();
   path.push(subpath);
@@ -30,7 +47,15 @@ fn integration(subpath: &str) -> Result<Vec<Expr>, RunError> {
   let mut lexer = Lexer::new(source);
   let exprs = crate::parse(&mut lexer).unwrap();
 
-  let engine = Engine::new();
+  let tree_walker = run(Backend::TreeWalker, exprs.clone());
+  let vm = run(Backend::Vm, exprs);
+  assert_eq!(tree_walker, vm, "the backends should behave identically");
+
+  tree_walker
+}
+
+fn run(backend: Backend, exprs: Vec<Expr>) -> Result<Vec<Expr>, RunError> {
+  let engine = Engine::new().with_backend(backend);
   let mut context = Context::new().with_stack_capacity(32);
   context = engine.run(context, exprs)?;
 
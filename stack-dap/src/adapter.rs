@@ -0,0 +1,581 @@
+//! Handles Debug Adapter Protocol requests for a single [`Debugger`].
+
+use std::{
+  io::{self, Write},
+  sync::{
+    mpsc::{self, Receiver, TryRecvError},
+    Arc,
+  },
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use stack_core::prelude::*;
+
+use crate::{
+  breakpoints::{normalize_path, Breakpoints, SourceBreakpoint},
+  debugger::{expr_location, Debugger, StepKind, Stop},
+  protocol::{Request, Writer},
+};
+
+/// The amount of expressions run between checking for requests.
+const SLICE: usize = 1024;
+
+/// The ID of the only thread.
+const THREAD_ID: i64 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LaunchArguments {
+  /// The path of the file to debug.
+  program: String,
+  #[serde(default)]
+  stop_on_entry: bool,
+  #[serde(default)]
+  fuel: Option<u64>,
+
+  /// Whether to run a sandbox variant of the enabled standard modules.
+  #[serde(default)]
+  #[cfg_attr(not(feature = "stack-std"), allow(dead_code))]
+  sandbox: bool,
+  /// Enable all standard modules.
+  #[serde(default)]
+  #[cfg_attr(not(feature = "stack-std"), allow(dead_code))]
+  enable_all: bool,
+  /// Enable the string standard module.
+  #[serde(default)]
+  #[cfg_attr(not(feature = "stack-std"), allow(dead_code))]
+  enable_str: bool,
+  /// Enable the file-system standard module.
+  #[serde(default)]
+  #[cfg_attr(not(feature = "stack-std"), allow(dead_code))]
+  enable_fs: bool,
+  /// Enable the scope standard module.
+  #[serde(default)]
+  #[cfg_attr(not(feature = "stack-std"), allow(dead_code))]
+  enable_scope: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+struct SetBreakpointsArguments {
+  source: SourceArgument,
+  #[serde(default)]
+  breakpoints: Vec<SourceBreakpoint>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+struct SourceArgument {
+  path: String,
+}
+
+/// Something which can be expanded in the variables view.
+#[derive(Debug, Clone, PartialEq)]
+enum Handle {
+  /// A scope, by its index from the root scope.
+  Scope(usize),
+  Stack,
+  Expr(Expr),
+}
+
+/// Output from `print`, `pretty` or `debug`, along with its category.
+type Output = (&'static str, String);
+
+/// Handles requests from a client, and runs the [`Debugger`].
+pub struct Adapter<W> {
+  writer: Writer<W>,
+  breakpoints: Breakpoints,
+  debugger: Option<Debugger>,
+  stop_on_entry: bool,
+  is_configured: bool,
+  handles: Vec<Handle>,
+  output_tx: mpsc::Sender<Output>,
+  output_rx: Receiver<Output>,
+}
+
+impl<W> Adapter<W>
+where
+  W: Write,
+{
+  pub fn new(writer: W) -> Self {
+    let (output_tx, output_rx) = mpsc::channel();
+
+    Self {
+      writer: Writer::new(writer),
+      breakpoints: Breakpoints::default(),
+      debugger: None,
+      stop_on_entry: false,
+      is_configured: false,
+      handles: Vec::new(),
+      output_tx,
+      output_rx,
+    }
+  }
+
+  /// Handles requests until the client disconnects.
+  ///
+  /// While the program is running, it's run in slices so that requests such
+  /// as `pause` are still handled.
+  pub fn run(mut self, requests: Receiver<Request>) -> io::Result<()> {
+    loop {
+      let is_running = self.debugger.as_ref().is_some_and(Debugger::is_running);
+
+      let request = if is_running {
+        match requests.try_recv() {
+          Ok(request) => Some(request),
+          Err(TryRecvError::Empty) => None,
+          Err(TryRecvError::Disconnected) => return Ok(()),
+        }
+      } else {
+        match requests.recv() {
+          Ok(request) => Some(request),
+          Err(_) => return Ok(()),
+        }
+      };
+
+      match request {
+        Some(request) => {
+          if !self.handle(request)? {
+            return Ok(());
+          }
+        }
+        None => self.run_slice()?,
+      }
+    }
+  }
+
+  /// Handles a request, returning whether to keep handling requests.
+  fn handle(&mut self, request: Request) -> io::Result<bool> {
+    match request.command.as_str() {
+      "initialize" => {
+        self.writer.respond(
+          &request,
+          json!({
+            "supportsConfigurationDoneRequest": true,
+            "supportsConditionalBreakpoints": true,
+            "supportsEvaluateForHovers": true,
+            "supportsTerminateRequest": true,
+          }),
+        )?;
+        self.writer.event("initialized", json!({}))?;
+      }
+      "launch" => self.launch(&request)?,
+      "setBreakpoints" => {
+        match serde_json::from_value::<SetBreakpointsArguments>(
+          request.arguments.clone(),
+        ) {
+          Ok(arguments) => {
+            let breakpoints = self
+              .breakpoints
+              .set(&arguments.source.path, arguments.breakpoints)
+              .iter()
+              .map(|breakpoint| {
+                json!({
+                  "id": breakpoint.id,
+                  "verified": breakpoint.verified,
+                  "line": breakpoint.line,
+                  "message": breakpoint.message,
+                })
+              })
+              .collect::<Vec<_>>();
+
+            self
+              .writer
+              .respond(&request, json!({ "breakpoints": breakpoints }))?;
+          }
+          Err(err) => self.writer.fail(&request, &err.to_string())?,
+        }
+      }
+      "setExceptionBreakpoints" => {
+        self.writer.respond(&request, json!({}))?;
+      }
+      "configurationDone" => {
+        self.writer.respond(&request, json!({}))?;
+        self.is_configured = true;
+        self.start()?;
+      }
+      "threads" => {
+        self.writer.respond(
+          &request,
+          json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }),
+        )?;
+      }
+      "stackTrace" => {
+        let frames = self
+          .debugger
+          .as_ref()
+          .map(Debugger::stack_frames)
+          .unwrap_or_default()
+          .into_iter()
+          .enumerate()
+          .map(|(id, frame)| {
+            let mut json = json!({
+              "id": id,
+              "name": frame.name,
+              "line": 0,
+              "column": 0,
+            });
+
+            if let Some((source, location)) =
+              frame.expr.as_ref().and_then(expr_location)
+            {
+              json["line"] = location.line.get().into();
+              json["column"] = location.column.get().into();
+              json["source"] = json!({
+                "name": source.name(),
+                "path": source.name(),
+              });
+            }
+
+            json
+          })
+          .collect::<Vec<_>>();
+
+        self.writer.respond(
+          &request,
+          json!({ "stackFrames": frames, "totalFrames": frames.len() }),
+        )?;
+      }
+      "scopes" => {
+        // Each scope holds everything visible from it, so the scopes of the
+        // innermost frame are shown for every frame.
+        let mut scopes = Vec::new();
+        if let Some(ref debugger) = self.debugger {
+          let depth = debugger.context().scope_depth();
+
+          scopes.push(json!({
+            "name": "Locals",
+            "variablesReference": self.handle_of(Handle::Scope(depth - 1)),
+            "expensive": false,
+          }));
+          if depth > 1 {
+            scopes.push(json!({
+              "name": "Globals",
+              "variablesReference": self.handle_of(Handle::Scope(0)),
+              "expensive": false,
+            }));
+          }
+          scopes.push(json!({
+            "name": "Stack",
+            "variablesReference": self.handle_of(Handle::Stack),
+            "expensive": false,
+          }));
+        }
+
+        self.writer.respond(&request, json!({ "scopes": scopes }))?;
+      }
+      "variables" => {
+        let variables = request.arguments["variablesReference"]
+          .as_u64()
+          .map(|reference| self.variables(reference as usize))
+          .unwrap_or_default();
+
+        self
+          .writer
+          .respond(&request, json!({ "variables": variables }))?;
+      }
+      "continue" | "next" | "stepIn" | "stepOut" => {
+        let kind = match request.command.as_str() {
+          "continue" => StepKind::Continue,
+          "next" => StepKind::Over,
+          "stepIn" => StepKind::In,
+          _ => StepKind::Out,
+        };
+
+        match self.debugger {
+          Some(ref mut debugger) => {
+            debugger.resume(kind);
+            self.handles.clear();
+            self
+              .writer
+              .respond(&request, json!({ "allThreadsContinued": true }))?;
+          }
+          None => self.writer.fail(&request, "nothing is running")?,
+        }
+      }
+      "pause" => {
+        self.writer.respond(&request, json!({}))?;
+
+        if let Some(ref mut debugger) = self.debugger {
+          if debugger.is_running() {
+            debugger.pause();
+            self.stopped("pause", None)?;
+          }
+        }
+      }
+      "evaluate" => {
+        let expression =
+          request.arguments["expression"].as_str().unwrap_or_default();
+        let source = Source::new("evaluate", expression);
+        let mut lexer = Lexer::new(source);
+
+        let result = match (parse(&mut lexer), self.debugger.as_ref()) {
+          (Ok(exprs), Some(debugger)) => {
+            debugger.evaluate(exprs).map_err(|err| err.to_string())
+          }
+          (Ok(_), None) => Err("nothing is running".into()),
+          (Err(err), _) => Err(err.to_string()),
+        };
+        self.send_output()?;
+
+        match result {
+          Ok(expr) => {
+            let variable = self.variable(String::new(), &expr);
+            self.writer.respond(
+              &request,
+              json!({
+                "result": variable["value"],
+                "type": variable["type"],
+                "variablesReference": variable["variablesReference"],
+              }),
+            )?;
+          }
+          Err(message) => self.writer.fail(&request, &message)?,
+        }
+      }
+      "disconnect" | "terminate" => {
+        self.writer.respond(&request, json!({}))?;
+        self.writer.event("terminated", json!({}))?;
+        return Ok(false);
+      }
+      _ => self.writer.fail(&request, "unsupported request")?,
+    }
+
+    Ok(true)
+  }
+
+  fn launch(&mut self, request: &Request) -> io::Result<()> {
+    let arguments = match serde_json::from_value::<LaunchArguments>(
+      request.arguments.clone(),
+    ) {
+      Ok(arguments) => arguments,
+      Err(err) => return self.writer.fail(request, &err.to_string()),
+    };
+
+    let source = match Source::from_path(normalize_path(&arguments.program)) {
+      Ok(source) => source,
+      Err(err) => return self.writer.fail(request, &err.to_string()),
+    };
+    let mut lexer = Lexer::new(source.clone());
+    let exprs = match parse(&mut lexer) {
+      Ok(exprs) => exprs,
+      Err(err) => return self.writer.fail(request, &err.to_string()),
+    };
+
+    // The output is dropped if the adapter has stopped listening for it.
+    let print_tx = self.output_tx.clone();
+    let debug_tx = self.output_tx.clone();
+    #[cfg_attr(not(feature = "stack-std"), allow(unused_mut))]
+    let mut engine = Engine::new()
+      .with_print_hook(Some(Arc::new(move |s| {
+        let _ = print_tx.send(("stdout", s));
+      })))
+      .with_debug_hook(Some(Arc::new(move |s| {
+        let _ = debug_tx.send(("console", s));
+      })));
+
+    #[cfg(feature = "stack-std")]
+    {
+      if arguments.enable_all || arguments.enable_str {
+        engine.add_module(stack_std::str::module());
+      }
+
+      if arguments.enable_all || arguments.enable_fs {
+        engine.add_module(stack_std::fs::module(arguments.sandbox));
+      }
+
+      if arguments.enable_all || arguments.enable_scope {
+        engine.add_module(stack_std::scope::module());
+      }
+    }
+
+    let mut context = Context::new();
+    context.add_source(source);
+    if let Some(fuel) = arguments.fuel {
+      context = context.with_fuel(fuel);
+    }
+
+    self.debugger = Some(Debugger::new(engine, context, exprs));
+    self.stop_on_entry = arguments.stop_on_entry;
+    self.writer.respond(request, json!({}))?;
+
+    self.start()
+  }
+
+  /// Starts the program once it's both launched and configured.
+  fn start(&mut self) -> io::Result<()> {
+    if !self.is_configured {
+      return Ok(());
+    }
+
+    let Some(ref mut debugger) = self.debugger else {
+      return Ok(());
+    };
+
+    if self.stop_on_entry {
+      debugger.pause();
+      self.stopped("entry", None)
+    } else {
+      debugger.resume(StepKind::Continue);
+      Ok(())
+    }
+  }
+
+  fn run_slice(&mut self) -> io::Result<()> {
+    let Some(ref mut debugger) = self.debugger else {
+      return Ok(());
+    };
+
+    let stop = debugger.run(&self.breakpoints, SLICE);
+    self.send_output()?;
+
+    match stop {
+      Some(Stop::Step) => self.stopped("step", None),
+      Some(Stop::Breakpoint(id)) => {
+        self.handles.clear();
+        self.writer.event(
+          "stopped",
+          json!({
+            "reason": "breakpoint",
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+            "hitBreakpointIds": [id],
+          }),
+        )
+      }
+      Some(Stop::Exception(message)) => {
+        self.stopped("exception", Some(message))
+      }
+      Some(Stop::OutOfFuel) => {
+        self.stopped("pause", Some("ran out of fuel".into()))
+      }
+      Some(Stop::Finished) => {
+        let failed = self
+          .debugger
+          .as_ref()
+          .is_some_and(|debugger| debugger.error().is_some());
+
+        self
+          .writer
+          .event("exited", json!({ "exitCode": i32::from(failed) }))?;
+        self.writer.event("terminated", json!({}))
+      }
+      None => Ok(()),
+    }
+  }
+
+  fn stopped(
+    &mut self,
+    reason: &str,
+    description: Option<String>,
+  ) -> io::Result<()> {
+    self.handles.clear();
+    self.writer.event(
+      "stopped",
+      json!({
+        "reason": reason,
+        "description": description,
+        "text": description,
+        "threadId": THREAD_ID,
+        "allThreadsStopped": true,
+      }),
+    )
+  }
+
+  fn send_output(&mut self) -> io::Result<()> {
+    while let Ok((category, output)) = self.output_rx.try_recv() {
+      self.writer.event(
+        "output",
+        json!({ "category": category, "output": format!("{output}\n") }),
+      )?;
+    }
+
+    Ok(())
+  }
+
+  /// Returns the reference to `handle`, which is never zero.
+  fn handle_of(&mut self, handle: Handle) -> usize {
+    self.handles.push(handle);
+    self.handles.len()
+  }
+
+  fn variables(&mut self, reference: usize) -> Vec<Value> {
+    let Some(debugger) = self.debugger.as_ref() else {
+      return Vec::new();
+    };
+    let Some(handle) = reference
+      .checked_sub(1)
+      .and_then(|index| self.handles.get(index))
+    else {
+      return Vec::new();
+    };
+
+    let items: Vec<(String, Expr)> = match handle {
+      Handle::Scope(index) => {
+        let Some(scope) = debugger.context().scopes().nth(*index) else {
+          return Vec::new();
+        };
+
+        let mut items = scope
+          .items
+          .iter()
+          .filter_map(|(name, val)| {
+            Some((name.to_string(), val.borrow().val()?))
+          })
+          .collect::<Vec<_>>();
+        items.sort_by(|(a, _), (b, _)| a.cmp(b));
+        items
+      }
+      Handle::Stack => debugger
+        .context()
+        .stack()
+        .iter()
+        .enumerate()
+        .map(|(i, expr)| (i.to_string(), expr.clone()))
+        .collect(),
+      Handle::Expr(expr) => match expr.kind {
+        ExprKind::List(ref x) => x
+          .iter()
+          .enumerate()
+          .map(|(i, expr)| (i.to_string(), expr.clone()))
+          .collect(),
+        ExprKind::Record(ref x) => x
+          .iter()
+          .map(|(key, expr)| (key.to_string(), expr.clone()))
+          .collect(),
+        ExprKind::Set(ref x) => x
+          .iter()
+          .enumerate()
+          .map(|(i, key)| (i.to_string(), key.clone().into()))
+          .collect(),
+        _ => Vec::new(),
+      },
+    };
+
+    items
+      .into_iter()
+      .map(|(name, expr)| self.variable(name, &expr))
+      .collect()
+  }
+
+  fn variable(&mut self, name: String, expr: &Expr) -> Value {
+    let reference = match expr.kind {
+      ExprKind::List(ref x) if !x.is_empty() => {
+        self.handle_of(Handle::Expr(expr.clone()))
+      }
+      ExprKind::Record(ref x) if !x.is_empty() => {
+        self.handle_of(Handle::Expr(expr.clone()))
+      }
+      ExprKind::Set(ref x) if !x.is_empty() => {
+        self.handle_of(Handle::Expr(expr.clone()))
+      }
+      _ => 0,
+    };
+
+    json!({
+      "name": name,
+      "value": expr.to_string(),
+      "type": expr.kind.type_of(),
+      "variablesReference": reference,
+    })
+  }
+}
@@ -0,0 +1,143 @@
+//! Line breakpoints, which may have a condition.
+
+use std::{
+  collections::{BTreeSet, HashMap},
+  fs,
+};
+
+use serde::Deserialize;
+use stack_core::prelude::*;
+
+/// A breakpoint as requested by the client.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SourceBreakpoint {
+  pub line: usize,
+  pub condition: Option<String>,
+}
+
+/// A breakpoint on a line of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+  pub id: i64,
+  pub line: usize,
+  /// Whether there is an expression on the line to stop at.
+  pub verified: bool,
+  pub message: Option<String>,
+  /// The expressions which must leave a truthy value on the stack for the
+  /// breakpoint to stop.
+  pub condition: Option<Vec<Expr>>,
+}
+
+/// The breakpoints of every file, keyed by their normalized path.
+#[derive(Debug, Clone, Default)]
+pub struct Breakpoints {
+  by_path: HashMap<String, Vec<Breakpoint>>,
+  next_id: i64,
+}
+
+impl Breakpoints {
+  /// Replaces the breakpoints of the file at `path`.
+  ///
+  /// Each breakpoint is moved to the first line at or after it which starts
+  /// an expression, which is found through [`Source::location`].
+  pub fn set(
+    &mut self,
+    path: &str,
+    requested: Vec<SourceBreakpoint>,
+  ) -> &[Breakpoint] {
+    let path = normalize_path(path);
+    let lines = Source::from_path(&path)
+      .ok()
+      .and_then(|source| expr_lines(&source));
+
+    let breakpoints = requested
+      .into_iter()
+      .map(|requested| {
+        self.next_id += 1;
+
+        let line = lines
+          .as_ref()
+          .and_then(|lines| lines.range(requested.line..).next().copied());
+        let condition = requested.condition.map(|condition| {
+          let source = Source::new("condition", condition);
+          let mut lexer = Lexer::new(source);
+          parse(&mut lexer)
+        });
+
+        let mut breakpoint = Breakpoint {
+          id: self.next_id,
+          line: line.unwrap_or(requested.line),
+          verified: line.is_some(),
+          message: None,
+          condition: None,
+        };
+
+        match condition {
+          Some(Ok(condition)) => breakpoint.condition = Some(condition),
+          Some(Err(err)) => {
+            breakpoint.verified = false;
+            breakpoint.message = Some(err.to_string());
+          }
+          None => {}
+        }
+
+        if line.is_none() {
+          breakpoint.message =
+            Some("no expression on or after this line".into());
+        }
+
+        breakpoint
+      })
+      .collect();
+
+    self.by_path.insert(path.clone(), breakpoints);
+    &self.by_path[&path]
+  }
+
+  /// Returns the verified breakpoint on `line` of the file at `path`.
+  pub fn get(&self, path: &str, line: usize) -> Option<&Breakpoint> {
+    self
+      .by_path
+      .get(path)?
+      .iter()
+      .find(|breakpoint| breakpoint.verified && breakpoint.line == line)
+  }
+}
+
+/// Returns the canonical form of `path`, so that paths from the client match
+/// the names of [`Source`]s.
+pub fn normalize_path(path: &str) -> String {
+  fs::canonicalize(path)
+    .map(|path| path.to_string_lossy().into_owned())
+    .unwrap_or_else(|_| path.into())
+}
+
+/// Returns the lines which start an expression, or [`None`] if the source
+/// can't be parsed.
+fn expr_lines(source: &Source) -> Option<BTreeSet<usize>> {
+  let mut lexer = Lexer::new(source.clone());
+  let exprs = parse(&mut lexer).ok()?;
+
+  let mut lines = BTreeSet::new();
+  let mut stack = exprs;
+  while let Some(expr) = stack.pop() {
+    if let Some(location) = expr
+      .info
+      .as_ref()
+      .and_then(|info| info.source.location(info.span.start))
+    {
+      lines.insert(location.line.get());
+    }
+
+    match expr.kind {
+      ExprKind::Lazy(x) => stack.push(*x),
+      ExprKind::List(x)
+      | ExprKind::Function { body: x, .. }
+      | ExprKind::SExpr { body: x, .. } => stack.extend(x),
+      ExprKind::Record(x) => stack.extend(x.into_values()),
+      _ => {}
+    }
+  }
+
+  Some(lines)
+}
@@ -0,0 +1,444 @@
+//! Runs a program under the control of breakpoints and steps.
+
+use core::time::Duration;
+
+use stack_core::{
+  execution::{Execution, PauseReason, Status},
+  prelude::*,
+  source::Location,
+};
+
+use crate::breakpoints::Breakpoints;
+
+/// How to resume a paused program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StepKind {
+  /// Run until a breakpoint is hit.
+  Continue,
+  /// Run until the next line, including lines in called functions.
+  In,
+  /// Run until the next line, without stopping in called functions.
+  Over,
+  /// Run until the current function returns.
+  Out,
+}
+
+/// Why a program stopped running.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+  /// A step finished.
+  Step,
+  /// A breakpoint was hit, with the ID of the breakpoint.
+  Breakpoint(i64),
+  /// The program failed, with the message of the error.
+  Exception(String),
+  /// The program ran out of fuel.
+  OutOfFuel,
+  /// There is nothing left to run.
+  Finished,
+}
+
+/// A function which is running, along with where it's at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+  pub name: String,
+  pub expr: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Resume {
+  kind: StepKind,
+  depth: usize,
+  line: Option<(String, usize)>,
+}
+
+/// The fuel which evaluating code can use, so that code which loops forever
+/// can't hang the debugger.
+const EVALUATION_FUEL: u64 = 100_000;
+/// How long evaluating code can run for.
+const EVALUATION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A program which is being debugged.
+pub struct Debugger {
+  engine: Engine,
+  execution: Execution,
+  resume: Option<Resume>,
+  /// Whether the next expression is on a different line to the last one, so
+  /// a breakpoint on its line should be hit.
+  arrived: bool,
+  error: Option<RunError>,
+}
+
+impl Debugger {
+  pub fn new(engine: Engine, context: Context, exprs: Vec<Expr>) -> Self {
+    let execution = engine.execute(context, exprs);
+
+    Self {
+      engine,
+      execution,
+      resume: None,
+      arrived: true,
+      error: None,
+    }
+  }
+
+  /// Returns the [`Context`] of the program.
+  #[inline]
+  pub fn context(&self) -> &Context {
+    self.execution.context()
+  }
+
+  /// Returns the error which the program failed with, if it did.
+  #[inline]
+  pub fn error(&self) -> Option<&RunError> {
+    self.error.as_ref()
+  }
+
+  /// Returns whether the program is running.
+  #[inline]
+  pub fn is_running(&self) -> bool {
+    self.resume.is_some()
+  }
+
+  /// Returns whether there is nothing left to run.
+  #[inline]
+  pub fn is_finished(&self) -> bool {
+    self.execution.is_finished()
+  }
+
+  /// Starts running the program until it stops.
+  pub fn resume(&mut self, kind: StepKind) {
+    self.resume = Some(Resume {
+      kind,
+      depth: self.depth(),
+      line: self.line(),
+    });
+  }
+
+  /// Stops running the program.
+  pub fn pause(&mut self) {
+    self.resume = None;
+    self.arrived = false;
+  }
+
+  /// Runs at most `budget` expressions, returning why the program stopped,
+  /// if it did.
+  pub fn run(
+    &mut self,
+    breakpoints: &Breakpoints,
+    budget: usize,
+  ) -> Option<Stop> {
+    let resume = self.resume.clone()?;
+
+    for _ in 0..budget {
+      if self.arrived {
+        self.arrived = false;
+
+        if let Some(id) = self.hit_breakpoint(breakpoints) {
+          return self.stop(Stop::Breakpoint(id));
+        }
+      }
+
+      let line = self.line();
+      match self.execution.step() {
+        Ok(Status::Finished) => return self.stop(Stop::Finished),
+        Ok(Status::Paused(PauseReason::OutOfFuel)) => {
+          return self.stop(Stop::OutOfFuel)
+        }
+        Ok(Status::Paused(_)) => {}
+        Err(err) => {
+          let message = err.to_string();
+          self.error = Some(err);
+          return self.stop(Stop::Exception(message));
+        }
+      }
+
+      let depth = self.depth();
+      let next_line = self.line();
+      self.arrived = next_line != line;
+
+      let stepped = match resume.kind {
+        StepKind::Continue => false,
+        StepKind::In => depth != resume.depth || next_line != resume.line,
+        StepKind::Over => {
+          depth < resume.depth
+            || (depth == resume.depth && next_line != resume.line)
+        }
+        StepKind::Out => depth < resume.depth,
+      };
+
+      if stepped {
+        return self.stop(Stop::Step);
+      }
+    }
+
+    None
+  }
+
+  /// Runs `exprs` against a copy of the [`Context`], returning the item on
+  /// the top of the stack.
+  ///
+  /// The copy doesn't share any variables with the program, so setting them
+  /// doesn't change the program. Running is limited by [`EVALUATION_FUEL`]
+  /// and [`EVALUATION_TIMEOUT`].
+  pub fn evaluate(&self, exprs: Vec<Expr>) -> Result<Expr, RunError> {
+    let context = self.context().detached().with_fuel(EVALUATION_FUEL);
+    let context = self.engine.clone().run_with_timeout(
+      context,
+      exprs,
+      EVALUATION_TIMEOUT,
+    )?;
+
+    Ok(
+      context
+        .stack()
+        .last()
+        .cloned()
+        .unwrap_or_else(|| ExprKind::Nil.into()),
+    )
+  }
+
+  /// Returns the running functions, from the innermost to the outermost.
+  pub fn stack_frames(&self) -> Vec<StackFrame> {
+    let current = match self.error {
+      Some(ref err) => Some((*err.expr).clone()),
+      None => self.execution.next_expr().cloned(),
+    };
+    let calls = self.execution.call_stack().rev().collect::<Vec<_>>();
+
+    let exprs = core::iter::once(current)
+      .chain(calls.iter().map(|expr| Some((*expr).clone())));
+    let names = calls
+      .iter()
+      .map(|expr| match expr.kind {
+        ExprKind::Symbol(ref x) => x.to_string(),
+        _ => "fn".into(),
+      })
+      .chain(core::iter::once("main".into()));
+
+    exprs
+      .zip(names)
+      .map(|(expr, name)| StackFrame { name, expr })
+      .collect()
+  }
+
+  fn stop(&mut self, stop: Stop) -> Option<Stop> {
+    self.pause();
+    Some(stop)
+  }
+
+  fn hit_breakpoint(&self, breakpoints: &Breakpoints) -> Option<i64> {
+    let (path, line) = self.line()?;
+    let breakpoint = breakpoints.get(&path, line)?;
+
+    match breakpoint.condition {
+      Some(ref condition) => self
+        .evaluate(condition.clone())
+        .is_ok_and(|expr| expr.kind.is_truthy())
+        .then_some(breakpoint.id),
+      None => Some(breakpoint.id),
+    }
+  }
+
+  /// Returns the number of functions which are running.
+  fn depth(&self) -> usize {
+    self.execution.call_stack().count()
+  }
+
+  /// Returns the name of the source and the line of the next expression.
+  fn line(&self) -> Option<(String, usize)> {
+    let expr = self.execution.next_expr()?;
+    let (source, location) = expr_location(expr)?;
+
+    Some((source.name().into(), location.line.get()))
+  }
+}
+
+/// Returns the [`Source`] and [`Location`] of the start of `expr`.
+pub fn expr_location(expr: &Expr) -> Option<(Source, Location)> {
+  let info = expr.info.as_ref()?;
+  let location = info.source.location(info.span.start)?;
+
+  Some((info.source.clone(), location))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::breakpoints::SourceBreakpoint;
+  use std::{env, fs};
+
+  fn debugger(source: &str) -> Debugger {
+    let source = Source::new("test", source);
+    let mut lexer = Lexer::new(source);
+    let exprs = parse(&mut lexer).unwrap();
+
+    Debugger::new(Engine::new(), Context::new(), exprs)
+  }
+
+  fn run(debugger: &mut Debugger, breakpoints: &Breakpoints) -> Stop {
+    loop {
+      if let Some(stop) = debugger.run(breakpoints, 100) {
+        return stop;
+      }
+    }
+  }
+
+  fn current_line(debugger: &Debugger) -> usize {
+    let frames = debugger.stack_frames();
+    let expr = frames[0].expr.as_ref().unwrap();
+    expr_location(expr).unwrap().1.line.get()
+  }
+
+  #[test]
+  fn steps_over_and_into_functions() {
+    let mut debugger = debugger(
+      "'(fn
+        1
+        2 +
+      ) 'f def
+      0 f
+      f",
+    );
+    let breakpoints = Breakpoints::default();
+
+    debugger.resume(StepKind::Over);
+    assert_eq!(run(&mut debugger, &breakpoints), Stop::Step);
+    assert_eq!(current_line(&debugger), 4);
+
+    debugger.resume(StepKind::Over);
+    assert_eq!(run(&mut debugger, &breakpoints), Stop::Step);
+    assert_eq!(current_line(&debugger), 5);
+
+    debugger.resume(StepKind::In);
+    assert_eq!(run(&mut debugger, &breakpoints), Stop::Step);
+    assert_eq!(current_line(&debugger), 2);
+    assert_eq!(debugger.stack_frames()[0].name, "f");
+
+    debugger.resume(StepKind::Out);
+    assert_eq!(run(&mut debugger, &breakpoints), Stop::Step);
+    assert_eq!(current_line(&debugger), 6);
+    assert_eq!(debugger.stack_frames().len(), 1);
+
+    debugger.resume(StepKind::Over);
+    assert_eq!(run(&mut debugger, &breakpoints), Stop::Finished);
+    assert_eq!(
+      debugger
+        .context()
+        .stack()
+        .iter()
+        .map(|expr| &expr.kind)
+        .collect::<Vec<_>>(),
+      vec![
+        &ExprKind::Integer(0),
+        &ExprKind::Integer(3),
+        &ExprKind::Integer(3)
+      ]
+    );
+  }
+
+  #[test]
+  fn stops_at_conditional_breakpoints() {
+    // Each run of the tests has its own file, so that they don't collide.
+    let file = env::temp_dir().join(format!(
+      "stack-dap-breakpoints-{}.stack",
+      std::process::id()
+    ));
+    fs::write(
+      &file,
+      "0 'i def
+      (fn!
+
+        i 1 + 'i set
+        i 5 < '[recur] if
+      )",
+    )
+    .unwrap();
+
+    let path = file.to_string_lossy().into_owned();
+    let source = Source::from_path(&path).unwrap();
+    let mut lexer = Lexer::new(source);
+    let exprs = parse(&mut lexer).unwrap();
+    let mut debugger = Debugger::new(Engine::new(), Context::new(), exprs);
+
+    let mut breakpoints = Breakpoints::default();
+    let set = breakpoints
+      .set(
+        &path,
+        vec![SourceBreakpoint {
+          line: 3,
+          condition: Some("i 3 =".into()),
+        }],
+      )
+      .to_vec();
+    assert_eq!(set[0].line, 4);
+    assert!(set[0].verified);
+
+    debugger.resume(StepKind::Continue);
+    assert_eq!(
+      run(&mut debugger, &breakpoints),
+      Stop::Breakpoint(set[0].id)
+    );
+    assert_eq!(
+      debugger.evaluate(vec![Expr {
+        kind: ExprKind::Symbol(Symbol::from_ref("i")),
+        info: None
+      }]),
+      Ok(ExprKind::Integer(3).into())
+    );
+
+    debugger.resume(StepKind::Continue);
+    assert_eq!(run(&mut debugger, &breakpoints), Stop::Finished);
+
+    fs::remove_file(file).unwrap();
+  }
+
+  #[test]
+  fn evaluates_without_changing_the_program() {
+    let mut debugger = debugger(
+      "0 'i def
+      '(fn i 1 + 'i set) 'inc def
+      i",
+    );
+    let breakpoints = Breakpoints::default();
+    let evaluate = |debugger: &Debugger, source: &str| {
+      let mut lexer = Lexer::new(Source::new("evaluate", source));
+      debugger.evaluate(parse(&mut lexer).unwrap())
+    };
+
+    debugger.resume(StepKind::Over);
+    assert_eq!(run(&mut debugger, &breakpoints), Stop::Step);
+    debugger.resume(StepKind::Over);
+    assert_eq!(run(&mut debugger, &breakpoints), Stop::Step);
+    assert_eq!(current_line(&debugger), 3);
+
+    assert_eq!(
+      evaluate(&debugger, "5 'i set inc i"),
+      Ok(ExprKind::Integer(6).into())
+    );
+    assert_eq!(evaluate(&debugger, "i"), Ok(ExprKind::Integer(0).into()));
+    assert_eq!(
+      evaluate(&debugger, "(fn! recur)").map_err(|err| err.reason),
+      Err(RunErrorReason::OutOfFuel)
+    );
+
+    debugger.resume(StepKind::Continue);
+    assert_eq!(run(&mut debugger, &breakpoints), Stop::Finished);
+    assert_eq!(
+      debugger.context().stack().last(),
+      Some(&ExprKind::Integer(0).into())
+    );
+  }
+
+  #[test]
+  fn stops_at_exceptions() {
+    let mut debugger = debugger("1\n2 missing");
+
+    debugger.resume(StepKind::Continue);
+    assert!(matches!(
+      run(&mut debugger, &Breakpoints::default()),
+      Stop::Exception(_)
+    ));
+    assert_eq!(current_line(&debugger), 2);
+  }
+}
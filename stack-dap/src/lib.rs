@@ -0,0 +1,6 @@
+//! A debug adapter for Stack, which speaks the Debug Adapter Protocol.
+
+pub mod adapter;
+pub mod breakpoints;
+pub mod debugger;
+pub mod protocol;
@@ -0,0 +1,33 @@
+use std::{
+  io::{self, BufReader},
+  sync::mpsc,
+  thread,
+};
+
+use stack_dap::{
+  adapter::Adapter,
+  protocol::{read_message, Request},
+};
+
+fn main() -> io::Result<()> {
+  let (tx, rx) = mpsc::channel();
+
+  // Requests are read on their own thread, so that they can be handled while
+  // the program is running.
+  thread::spawn(move || {
+    let mut stdin = BufReader::new(io::stdin());
+
+    while let Ok(Some(message)) = read_message(&mut stdin) {
+      match serde_json::from_value::<Request>(message) {
+        Ok(request) => {
+          if tx.send(request).is_err() {
+            break;
+          }
+        }
+        Err(err) => eprintln!("error: invalid request: {err}"),
+      }
+    }
+  });
+
+  Adapter::new(io::stdout()).run(rx)
+}
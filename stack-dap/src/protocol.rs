@@ -0,0 +1,124 @@
+//! Reading and writing Debug Adapter Protocol messages.
+//!
+//! Each message is a JSON object preceded by a `Content-Length` header.
+
+use std::io::{self, BufRead, Write};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// A request sent by the client.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Request {
+  pub seq: i64,
+  pub command: String,
+  #[serde(default)]
+  pub arguments: Value,
+}
+
+/// Reads a single message, returning [`None`] once the input is closed.
+pub fn read_message<R>(reader: &mut R) -> io::Result<Option<Value>>
+where
+  R: BufRead,
+{
+  let mut content_length = None;
+
+  loop {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+      return Ok(None);
+    }
+
+    let line = line.trim_end();
+    if line.is_empty() {
+      break;
+    }
+
+    if let Some((name, value)) = line.split_once(':') {
+      if name.eq_ignore_ascii_case("content-length") {
+        content_length = value.trim().parse::<usize>().ok();
+      }
+    }
+  }
+
+  let Some(content_length) = content_length else {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidData,
+      "missing Content-Length header",
+    ));
+  };
+
+  let mut content = vec![0; content_length];
+  reader.read_exact(&mut content)?;
+
+  serde_json::from_slice(&content)
+    .map(Some)
+    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Writes a single message.
+pub fn write_message<W>(writer: &mut W, message: &Value) -> io::Result<()>
+where
+  W: Write,
+{
+  let content = message.to_string();
+  write!(
+    writer,
+    "Content-Length: {}\r\n\r\n{}",
+    content.len(),
+    content
+  )?;
+  writer.flush()
+}
+
+/// Writes messages with increasing sequence numbers.
+pub struct Writer<W> {
+  writer: W,
+  seq: i64,
+}
+
+impl<W> Writer<W>
+where
+  W: Write,
+{
+  pub fn new(writer: W) -> Self {
+    Self { writer, seq: 0 }
+  }
+
+  /// Responds to `request` with a successful result.
+  pub fn respond(&mut self, request: &Request, body: Value) -> io::Result<()> {
+    self.send(json!({
+      "type": "response",
+      "request_seq": request.seq,
+      "success": true,
+      "command": request.command,
+      "body": body,
+    }))
+  }
+
+  /// Responds to `request` with an error.
+  pub fn fail(&mut self, request: &Request, message: &str) -> io::Result<()> {
+    self.send(json!({
+      "type": "response",
+      "request_seq": request.seq,
+      "success": false,
+      "command": request.command,
+      "message": message,
+    }))
+  }
+
+  /// Sends an event.
+  pub fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+    self.send(json!({
+      "type": "event",
+      "event": event,
+      "body": body,
+    }))
+  }
+
+  fn send(&mut self, mut message: Value) -> io::Result<()> {
+    self.seq += 1;
+    message["seq"] = self.seq.into();
+    write_message(&mut self.writer, &message)
+  }
+}
@@ -5,7 +5,7 @@ use eframe::egui::{
 };
 use itertools::Itertools;
 use stack_core::{
-  expr::display_fn_scope,
+  expr::{display_bytes, display_fn_scope},
   journal::{Journal, JournalOp, JournalScope},
   prelude::*,
 };
@@ -50,12 +50,21 @@ pub fn paint_expr(expr: &Expr, layout_job: &mut LayoutJob) {
     ExprKind::Integer(x) => {
       append_to_job(RichText::new(x.to_string()).color(blue), layout_job)
     }
+    ExprKind::BigInt(x) => {
+      append_to_job(RichText::new(x.to_string()).color(blue), layout_job)
+    }
     ExprKind::Float(x) => {
       append_to_job(RichText::new(x.to_string()).color(blue), layout_job)
     }
+    ExprKind::Decimal(x) => {
+      append_to_job(RichText::new(x.to_string()).color(blue), layout_job)
+    }
     ExprKind::String(x) => {
       append_to_job(RichText::new(format!("\"{x}\"")).color(green), layout_job)
     }
+    ExprKind::Bytes(x) => {
+      append_to_job(RichText::new(display_bytes(x)).color(green), layout_job)
+    }
 
     ExprKind::Symbol(x) => {
       append_to_job(RichText::new(x.to_string()).color(blue), layout_job)
@@ -85,7 +94,7 @@ pub fn paint_expr(expr: &Expr, layout_job: &mut LayoutJob) {
         .chain(core::iter::repeat(", "))
         .zip(x.iter())
       {
-        let key: Expr = ExprKind::Symbol(*key).into();
+        let key: Expr = key.clone().into();
         append_to_job(RichText::new(sep), layout_job);
         paint_expr(&key, layout_job);
         append_to_job(RichText::new(": "), layout_job);
@@ -94,6 +103,19 @@ pub fn paint_expr(expr: &Expr, layout_job: &mut LayoutJob) {
 
       append_to_job(RichText::new("}"), layout_job);
     }
+    ExprKind::Set(x) => {
+      append_to_job(RichText::new("#{"), layout_job);
+
+      for (sep, x) in core::iter::once("")
+        .chain(core::iter::repeat(" "))
+        .zip(x.iter())
+      {
+        append_to_job(RichText::new(sep), layout_job);
+        paint_expr(&x.clone().into(), layout_job);
+      }
+
+      append_to_job(RichText::new("}"), layout_job);
+    }
 
     ExprKind::Function { scope, body } => {
       // append_to_job(RichText::new(x.to_string()).color(yellow), layout_job)
@@ -295,7 +317,27 @@ pub fn string_with_quotes(expr: &Expr) -> String {
         .chain(core::iter::repeat(" "))
         .zip(x.iter())
         .for_each(|(sep, (key, value))| {
-          string.push_str(&format!("{sep}{key}: {}", string_with_quotes(value)))
+          string.push_str(&format!(
+            "{sep}{}: {}",
+            string_with_quotes(&key.clone().into()),
+            string_with_quotes(value)
+          ))
+        });
+      string.push('}');
+
+      string
+    }
+
+    ExprKind::Set(x) => {
+      let mut string = String::from("#{");
+      core::iter::once("")
+        .chain(core::iter::repeat(" "))
+        .zip(x.iter())
+        .for_each(|(sep, x)| {
+          string.push_str(&format!(
+            "{sep}{}",
+            string_with_quotes(&x.clone().into())
+          ))
         });
       string.push('}');
 
@@ -181,7 +181,7 @@ impl DebuggerApp {
       }
       Err(err) => {
         self.error = Some(err.to_string().clone());
-        self.context = err.context;
+        self.context = *err.context;
       }
     }
 
@@ -0,0 +1,305 @@
+//! Finds definitions and symbols in the expressions of a document.
+
+use lsp_types::{Position, Range};
+use stack_core::{lexer::Span, prelude::*};
+
+/// A variable which is defined with `def`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+  pub name: Symbol,
+  /// The span of the name of the variable.
+  pub name_span: Span,
+  /// The span of the whole definition, including the value.
+  pub span: Span,
+  pub is_function: bool,
+}
+
+/// A parsed document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+  source: Source,
+  exprs: Vec<Expr>,
+  errors: Vec<ParseError>,
+}
+
+impl Document {
+  pub fn new(name: &str, text: String) -> Self {
+    let source = Source::new(name, text);
+    let mut lexer = Lexer::new(source.clone());
+
+    let (exprs, errors) = parse_recovering(&mut lexer);
+
+    Self {
+      source,
+      exprs,
+      errors,
+    }
+  }
+
+  #[inline]
+  pub fn source(&self) -> &Source {
+    &self.source
+  }
+
+  /// Returns the errors of the document. The rest of the document is still
+  /// parsed, so definitions and symbols can be found despite them.
+  #[inline]
+  pub fn errors(&self) -> &[ParseError] {
+    &self.errors
+  }
+
+  /// Returns every definition, including those within lists and functions.
+  pub fn definitions(&self) -> Vec<Definition> {
+    let mut definitions = Vec::new();
+    visit(&self.exprs, &mut |exprs| {
+      definitions.extend(sequence_definitions(exprs));
+    });
+
+    definitions
+  }
+
+  /// Returns the definitions which aren't within lists or functions.
+  #[inline]
+  pub fn top_level_definitions(&self) -> Vec<Definition> {
+    sequence_definitions(&self.exprs)
+  }
+
+  /// Returns the symbol at the byte `offset`, along with its span.
+  pub fn symbol_at(&self, offset: usize) -> Option<(Symbol, Span)> {
+    let mut found = None;
+
+    visit(&self.exprs, &mut |exprs| {
+      for expr in exprs {
+        let Some(ref info) = expr.info else {
+          continue;
+        };
+        if !(info.span.start..=info.span.end).contains(&offset) {
+          continue;
+        }
+
+        match expr.kind.unlazy() {
+          ExprKind::Symbol(x) => found = Some((*x, info.span)),
+          ExprKind::SExpr { call, .. } => {
+            // The call directly follows the opening parenthesis.
+            let start = info.span.start + 1;
+            let span = Span {
+              start,
+              end: start + call.as_str().len(),
+            };
+
+            if (span.start..=span.end).contains(&offset) {
+              found = Some((*call, span));
+            }
+          }
+          _ => {}
+        }
+      }
+    });
+
+    found
+  }
+
+  /// Returns the byte offset of an LSP `position`.
+  pub fn offset(&self, position: Position) -> Option<usize> {
+    let text = self.source.source();
+    let line_start = if position.line == 0 {
+      0
+    } else {
+      text
+        .match_indices('\n')
+        .nth(position.line as usize - 1)
+        .map(|(i, _)| i + 1)?
+    };
+
+    let mut utf16 = 0;
+    for (i, c) in text[line_start..].char_indices() {
+      if utf16 >= position.character as usize || c == '\n' {
+        return Some(line_start + i);
+      }
+      utf16 += c.len_utf16();
+    }
+
+    Some(text.len())
+  }
+
+  /// Returns the LSP position of a byte `offset`.
+  pub fn position(&self, offset: usize) -> Position {
+    let text = &self.source.source()[..offset.min(self.source.source().len())];
+    let line_start = text.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+    Position {
+      line: text.matches('\n').count() as u32,
+      character: text[line_start..].encode_utf16().count() as u32,
+    }
+  }
+
+  #[inline]
+  pub fn range(&self, span: Span) -> Range {
+    Range {
+      start: self.position(span.start),
+      end: self.position(span.end),
+    }
+  }
+}
+
+/// Calls `f` with every sequence of expressions, such as the items of a list
+/// or the body of a function.
+fn visit<F>(exprs: &[Expr], f: &mut F)
+where
+  F: FnMut(&[Expr]),
+{
+  f(exprs);
+
+  for expr in exprs {
+    visit_expr(expr, f);
+  }
+}
+
+fn visit_expr<F>(expr: &Expr, f: &mut F)
+where
+  F: FnMut(&[Expr]),
+{
+  match expr.kind {
+    ExprKind::Lazy(ref x) => visit_expr(x, f),
+    ExprKind::List(ref x)
+    | ExprKind::Function { body: ref x, .. }
+    | ExprKind::SExpr { body: ref x, .. } => visit(x, f),
+    ExprKind::Record(ref x) => x.values().for_each(|expr| visit_expr(expr, f)),
+    _ => {}
+  }
+}
+
+/// Returns the definitions directly within `exprs`, which are either
+/// `value 'name def` or `(def 'name value)`.
+fn sequence_definitions(exprs: &[Expr]) -> Vec<Definition> {
+  let mut definitions = Vec::new();
+
+  for (i, expr) in exprs.iter().enumerate() {
+    let Some(ref info) = expr.info else {
+      continue;
+    };
+
+    match expr.kind {
+      ExprKind::Symbol(x) if x.as_str() == "def" && i > 0 => {
+        let Some((name, name_span)) = lazy_symbol(&exprs[i - 1]) else {
+          continue;
+        };
+        let value = i.checked_sub(2).map(|i| &exprs[i]);
+
+        definitions.push(Definition {
+          name,
+          name_span,
+          span: Span {
+            start: value
+              .and_then(|value| value.info.as_ref())
+              .map(|info| info.span.start)
+              .unwrap_or(name_span.start),
+            end: info.span.end,
+          },
+          is_function: value
+            .is_some_and(|value| value.kind.unlazy().is_function()),
+        });
+      }
+      ExprKind::SExpr { call, ref body } if call.as_str() == "def" => {
+        let Some((name, name_span)) = body.first().and_then(lazy_symbol) else {
+          continue;
+        };
+
+        definitions.push(Definition {
+          name,
+          name_span,
+          span: info.span,
+          is_function: body
+            .get(1)
+            .is_some_and(|value| value.kind.unlazy().is_function()),
+        });
+      }
+      _ => {}
+    }
+  }
+
+  definitions
+}
+
+/// Returns the name and span of a `'name`.
+fn lazy_symbol(expr: &Expr) -> Option<(Symbol, Span)> {
+  match expr.kind {
+    ExprKind::Lazy(ref x) => match x.kind {
+      ExprKind::Symbol(name) => Some((name, expr.info.as_ref()?.span)),
+      _ => None,
+    },
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn finds_definitions() {
+    let document =
+      Document::new("", "0 'a def\n'(fn 1 'b def) 'f def\n(def 'c 2)".into());
+
+    let names = |definitions: Vec<Definition>| {
+      definitions
+        .into_iter()
+        .map(|definition| (definition.name.to_string(), definition.is_function))
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+      names(document.top_level_definitions()),
+      vec![("a".into(), false), ("f".into(), true), ("c".into(), false)]
+    );
+    assert_eq!(document.definitions().len(), 4);
+
+    let f = &document.top_level_definitions()[1];
+    assert_eq!(
+      &document.source().source()[f.span.start..f.span.end],
+      "'(fn 1 'b def) 'f def"
+    );
+  }
+
+  #[test]
+  fn finds_symbols() {
+    let document = Document::new("", "1 'a def\n[a (+ a 1)]".into());
+
+    assert_eq!(
+      document.symbol_at(10).map(|(x, _)| x.to_string()),
+      Some("a".into())
+    );
+    assert_eq!(
+      document.symbol_at(13).map(|(x, _)| x.to_string()),
+      Some("+".into())
+    );
+    assert_eq!(document.symbol_at(0), None);
+  }
+
+  #[test]
+  fn recovers_from_errors() {
+    let document = Document::new("", "0 'a def\n[1 2\n1 'b def".into());
+
+    assert_eq!(document.errors().len(), 1);
+    assert_eq!(document.top_level_definitions().len(), 2);
+  }
+
+  #[test]
+  fn converts_positions() {
+    let document = Document::new("", "\"é\" 1\n2".into());
+
+    let position = Position {
+      line: 0,
+      character: 4,
+    };
+    assert_eq!(document.offset(position), Some(5));
+    assert_eq!(document.position(5), position);
+    assert_eq!(
+      document.offset(Position {
+        line: 1,
+        character: 0
+      }),
+      Some(7)
+    );
+  }
+}
@@ -0,0 +1,95 @@
+//! Documentation of the intrinsics, taken from the reference in the book.
+
+use std::collections::HashMap;
+
+const BUILTINS: &str = include_str!("../../docs/src/reference/builtins.md");
+
+/// The documentation of each intrinsic, keyed by its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Docs(HashMap<&'static str, &'static str>);
+
+impl Default for Docs {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Docs {
+  /// Collects the sections of the builtins reference.
+  ///
+  /// Each section starts with a heading such as ``### Add (`+`)``, and runs
+  /// until the next heading.
+  pub fn new() -> Self {
+    let mut docs = HashMap::new();
+    let mut section: Option<(&str, usize)> = None;
+
+    let mut offset = 0;
+    for line in BUILTINS.split_inclusive('\n') {
+      if line.starts_with('#') {
+        if let Some((name, start)) = section.take() {
+          docs.insert(name, BUILTINS[start..offset].trim());
+        }
+
+        section = line
+          .strip_prefix("### ")
+          .and_then(heading_name)
+          .map(|name| (name, offset));
+      }
+
+      offset += line.len();
+    }
+
+    if let Some((name, start)) = section {
+      docs.insert(name, BUILTINS[start..].trim());
+    }
+
+    Self(docs)
+  }
+
+  /// Returns the documentation of the intrinsic called `name`, as markdown.
+  #[inline]
+  pub fn get(&self, name: &str) -> Option<&'static str> {
+    self.0.get(name).copied()
+  }
+}
+
+/// Returns the name from a heading such as ``Add (`+`)``.
+fn heading_name(heading: &'static str) -> Option<&'static str> {
+  let (_, name) = heading.trim_end().rsplit_once('(')?;
+  let name = name.strip_suffix(')')?;
+
+  name
+    .strip_prefix('`')
+    .and_then(|name| name.strip_suffix('`'))
+    .or_else(|| {
+      name
+        .strip_prefix('\'')
+        .and_then(|name| name.strip_suffix('\''))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use stack_core::prelude::*;
+
+  #[test]
+  fn finds_sections_of_intrinsics() {
+    let docs = Docs::new();
+
+    let add = docs.get("+").unwrap();
+    assert!(add.starts_with("### Add (`+`)"));
+    assert!(add.contains("1 2 +"));
+    assert!(!add.contains("### Subtract"));
+
+    assert!(docs.get("orelse").is_some());
+    assert!(docs.get("Lists vs Functions").is_none());
+    assert!(
+      Intrinsic::all_as_slice()
+        .iter()
+        .filter(|name| docs.get(name).is_some())
+        .count()
+        > 40
+    );
+  }
+}
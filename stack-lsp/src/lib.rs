@@ -0,0 +1,5 @@
+//! A language server for Stack, which speaks the Language Server Protocol.
+
+pub mod analysis;
+pub mod docs;
+pub mod server;
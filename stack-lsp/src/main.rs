@@ -0,0 +1,27 @@
+use lsp_server::Connection;
+use stack_core::prelude::*;
+use stack_lsp::server::{Server, ServerError};
+
+fn main() -> Result<(), ServerError> {
+  let (connection, io_threads) = Connection::stdio();
+
+  let capabilities = serde_json::to_value(Server::capabilities())?;
+  connection.initialize(capabilities)?;
+
+  #[cfg_attr(not(feature = "stack-std"), allow(unused_mut))]
+  let mut engine = Engine::new();
+
+  #[cfg(feature = "stack-std")]
+  {
+    engine.add_module(stack_std::str::module());
+    engine.add_module(stack_std::fs::module(false));
+    engine.add_module(stack_std::scope::module());
+  }
+
+  Server::new(engine).run(&connection)?;
+
+  drop(connection);
+  io_threads.join()?;
+
+  Ok(())
+}
@@ -0,0 +1,325 @@
+//! Handles the requests and notifications of a client.
+
+use std::{collections::HashMap, error::Error};
+
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::{
+  notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as _, PublishDiagnostics,
+  },
+  request::{
+    Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest,
+    Request as _,
+  },
+  CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams,
+  CompletionResponse, Diagnostic, DiagnosticSeverity,
+  DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+  DidOpenTextDocumentParams, DocumentSymbol, DocumentSymbolParams,
+  DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse, Hover,
+  HoverContents, HoverParams, HoverProviderCapability, Location, MarkupContent,
+  MarkupKind, OneOf, PublishDiagnosticsParams, ServerCapabilities, SymbolKind,
+  TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind,
+  Url,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use stack_core::{lexer::Span, prelude::*};
+
+use crate::{analysis::Document, docs::Docs};
+
+pub type ServerError = Box<dyn Error + Send + Sync>;
+
+/// A language server for `.stack` files.
+pub struct Server {
+  engine: Engine,
+  docs: Docs,
+  documents: HashMap<Url, Document>,
+}
+
+impl Server {
+  /// Creates a new [`Server`], which completes the functions of the modules
+  /// of `engine`.
+  pub fn new(engine: Engine) -> Self {
+    Self {
+      engine,
+      docs: Docs::new(),
+      documents: HashMap::new(),
+    }
+  }
+
+  pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+      text_document_sync: Some(TextDocumentSyncCapability::Kind(
+        TextDocumentSyncKind::FULL,
+      )),
+      completion_provider: Some(CompletionOptions {
+        trigger_characters: Some(vec![":".into()]),
+        ..Default::default()
+      }),
+      hover_provider: Some(HoverProviderCapability::Simple(true)),
+      definition_provider: Some(OneOf::Left(true)),
+      document_symbol_provider: Some(OneOf::Left(true)),
+      ..Default::default()
+    }
+  }
+
+  /// Handles messages until the client shuts down the server.
+  pub fn run(&mut self, connection: &Connection) -> Result<(), ServerError> {
+    for message in &connection.receiver {
+      match message {
+        Message::Request(request) => {
+          if connection.handle_shutdown(&request)? {
+            return Ok(());
+          }
+
+          let response = self.handle_request(request);
+          connection.sender.send(Message::Response(response))?;
+        }
+        Message::Notification(notification) => {
+          if let Some(notification) = self.handle_notification(notification) {
+            connection
+              .sender
+              .send(Message::Notification(notification))?;
+          }
+        }
+        Message::Response(_) => {}
+      }
+    }
+
+    Ok(())
+  }
+
+  fn handle_request(&mut self, request: Request) -> Response {
+    let result = match request.method.as_str() {
+      Completion::METHOD => respond(&request, |params| self.completion(params)),
+      HoverRequest::METHOD => respond(&request, |params| self.hover(params)),
+      GotoDefinition::METHOD => {
+        respond(&request, |params| self.definition(params))
+      }
+      DocumentSymbolRequest::METHOD => {
+        respond(&request, |params| self.document_symbols(params))
+      }
+      _ => {
+        return Response::new_err(
+          request.id,
+          lsp_server::ErrorCode::MethodNotFound as i32,
+          format!("unknown method {}", request.method),
+        )
+      }
+    };
+
+    match result {
+      Ok(result) => Response::new_ok(request.id, result),
+      Err(err) => Response::new_err(
+        request.id,
+        lsp_server::ErrorCode::InvalidParams as i32,
+        err.to_string(),
+      ),
+    }
+  }
+
+  /// Handles a notification, returning the diagnostics to publish.
+  fn handle_notification(
+    &mut self,
+    notification: Notification,
+  ) -> Option<Notification> {
+    let (uri, text) = match notification.method.as_str() {
+      DidOpenTextDocument::METHOD => {
+        let params = notification
+          .extract::<DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD)
+          .ok()?;
+        (params.text_document.uri, params.text_document.text)
+      }
+      DidChangeTextDocument::METHOD => {
+        let mut params = notification
+          .extract::<DidChangeTextDocumentParams>(DidChangeTextDocument::METHOD)
+          .ok()?;
+        let change = params.content_changes.pop()?;
+        (params.text_document.uri, change.text)
+      }
+      DidCloseTextDocument::METHOD => {
+        let params = notification
+          .extract::<DidCloseTextDocumentParams>(DidCloseTextDocument::METHOD)
+          .ok()?;
+        self.documents.remove(&params.text_document.uri);
+        return None;
+      }
+      _ => return None,
+    };
+
+    let document = Document::new(uri.as_str(), text);
+    let diagnostics = document
+      .errors()
+      .iter()
+      .map(|err| Diagnostic {
+        range: document.range(err.kind.span()),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("stack".into()),
+        message: err.kind.to_string(),
+        ..Default::default()
+      })
+      .collect();
+    self.documents.insert(uri.clone(), document);
+
+    Some(Notification::new(
+      PublishDiagnostics::METHOD.into(),
+      PublishDiagnosticsParams {
+        uri,
+        diagnostics,
+        version: None,
+      },
+    ))
+  }
+
+  fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+    let mut items = Intrinsic::all_as_slice()
+      .iter()
+      .map(|name| CompletionItem {
+        label: (*name).into(),
+        kind: Some(CompletionItemKind::KEYWORD),
+        detail: self.docs.get(name).and_then(signature),
+        ..Default::default()
+      })
+      .collect::<Vec<_>>();
+
+    for module in self.engine.modules() {
+      items.extend(module.func_names().map(|func| CompletionItem {
+        label: format!("{}:{}", module.name(), func),
+        kind: Some(CompletionItemKind::FUNCTION),
+        detail: Some(format!("from the {} module", module.name())),
+        ..Default::default()
+      }));
+    }
+
+    if let Some(document) = self
+      .documents
+      .get(&params.text_document_position.text_document.uri)
+    {
+      let mut definitions = document.definitions();
+      definitions.sort_by(|a, b| a.name.as_str().cmp(b.name.as_str()));
+      definitions.dedup_by_key(|definition| definition.name);
+
+      items.extend(definitions.into_iter().map(|definition| CompletionItem {
+        label: definition.name.to_string(),
+        kind: Some(if definition.is_function {
+          CompletionItemKind::FUNCTION
+        } else {
+          CompletionItemKind::VARIABLE
+        }),
+        ..Default::default()
+      }));
+    }
+
+    Some(CompletionResponse::Array(items))
+  }
+
+  fn hover(&self, params: HoverParams) -> Option<Hover> {
+    let (document, symbol, span) =
+      self.symbol_at(&params.text_document_position_params)?;
+
+    let value = if let Some(docs) = self.docs.get(symbol.as_str()) {
+      docs.to_string()
+    } else if let Some((namespace, func)) = symbol.as_str().split_once(':') {
+      let module = self.engine.module(&Symbol::from_ref(namespace))?;
+      module.func(Symbol::from_ref(func))?;
+      format!("`{symbol}` from the `{namespace}` module")
+    } else {
+      let definition = document
+        .definitions()
+        .into_iter()
+        .find(|definition| definition.name == symbol)?;
+      let text =
+        &document.source().source()[definition.span.start..definition.span.end];
+      format!("```clojure\n{text}\n```")
+    };
+
+    Some(Hover {
+      contents: HoverContents::Markup(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+      }),
+      range: Some(document.range(span)),
+    })
+  }
+
+  fn definition(
+    &self,
+    params: GotoDefinitionParams,
+  ) -> Option<GotoDefinitionResponse> {
+    let position = &params.text_document_position_params;
+    let (document, symbol, _) = self.symbol_at(position)?;
+
+    let locations = document
+      .definitions()
+      .into_iter()
+      .filter(|definition| definition.name == symbol)
+      .map(|definition| Location {
+        uri: position.text_document.uri.clone(),
+        range: document.range(definition.name_span),
+      })
+      .collect::<Vec<_>>();
+
+    (!locations.is_empty()).then_some(GotoDefinitionResponse::Array(locations))
+  }
+
+  fn document_symbols(
+    &self,
+    params: DocumentSymbolParams,
+  ) -> Option<DocumentSymbolResponse> {
+    let document = self.documents.get(&params.text_document.uri)?;
+
+    #[allow(deprecated)]
+    let symbols = document
+      .top_level_definitions()
+      .into_iter()
+      .map(|definition| DocumentSymbol {
+        name: definition.name.to_string(),
+        detail: None,
+        kind: if definition.is_function {
+          SymbolKind::FUNCTION
+        } else {
+          SymbolKind::VARIABLE
+        },
+        tags: None,
+        deprecated: None,
+        range: document.range(definition.span),
+        selection_range: document.range(definition.name_span),
+        children: None,
+      })
+      .collect();
+
+    Some(DocumentSymbolResponse::Nested(symbols))
+  }
+
+  fn symbol_at(
+    &self,
+    position: &TextDocumentPositionParams,
+  ) -> Option<(&Document, Symbol, Span)> {
+    let document = self.documents.get(&position.text_document.uri)?;
+    let offset = document.offset(position.position)?;
+    let (symbol, span) = document.symbol_at(offset)?;
+
+    Some((document, symbol, span))
+  }
+}
+
+/// Deserializes the params of a request, then serializes its result.
+fn respond<P, R, F>(request: &Request, f: F) -> Result<Value, serde_json::Error>
+where
+  P: DeserializeOwned,
+  R: Serialize,
+  F: FnOnce(P) -> R,
+{
+  let params = serde_json::from_value(request.params.clone())?;
+  serde_json::to_value(f(params))
+}
+
+/// Returns the signature line of the documentation of an intrinsic.
+fn signature(docs: &str) -> Option<String> {
+  docs.lines().find_map(|line| {
+    line
+      .strip_prefix("**Signature:** ")
+      .map(|signature| signature.trim_matches('`').to_string())
+  })
+}
@@ -211,12 +211,30 @@ pub fn module() -> Module {
         Ok(context)
       }),
     )
+    .with_func(
+      Symbol::from_ref("to-byte-string"),
+      Arc::new(|_, mut context, expr| {
+        let item = context.stack_pop(&expr)?;
+
+        let kind = match item.kind {
+          ExprKind::String(ref x) => ExprKind::Bytes(x.as_bytes().to_vec()),
+          _ => ExprKind::Nil,
+        };
+
+        context.stack_push(kind.into())?;
+
+        Ok(context)
+      }),
+    )
     .with_func(
       Symbol::from_ref("from-bytes"),
       Arc::new(|_, mut context, expr| {
         let item = context.stack_pop(&expr)?;
 
         let kind = match item.kind {
+          ExprKind::Bytes(x) => CompactString::from_utf8(x)
+            .map(ExprKind::String)
+            .unwrap_or(ExprKind::Nil),
           ExprKind::List(ref x) => x
             .iter()
             .try_fold(Vec::with_capacity(x.len()), |mut v, x| match x.kind {
@@ -303,7 +321,7 @@ pub fn module() -> Module {
               let re = Regex::new(pattern);
               match re {
                 Ok(re) => ExprKind::Boolean(re.captures(string).is_some()),
-                Err(err) => {
+                Err(_) => {
                   todo!()
                 }
               }
@@ -339,7 +357,7 @@ pub fn module() -> Module {
                     )
                   })
                   .unwrap_or(ExprKind::Nil),
-                Err(err) => {
+                Err(_) => {
                   todo!()
                 }
               }
@@ -377,7 +395,7 @@ pub fn module() -> Module {
                     })
                     .collect::<Vec<_>>(),
                 ),
-                Err(err) => {
+                Err(_) => {
                   todo!()
                 }
               }